@@ -0,0 +1,91 @@
+/*
+ * C ABI of insize, in the cdylib built with `cargo build --release --features capi`.
+ *
+ * Functions are safe to call from several threads, errors are kept per thread.
+ */
+#ifndef INSIZE_H
+#define INSIZE_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Version of this header, compare with isp_abi_version() of the library. */
+#define ISP_ABI_VERSION 1
+
+/* Return codes. */
+#define ISP_OK 0
+#define ISP_ERR_INVALID 1
+#define ISP_ERR_NOT_FOUND 2
+#define ISP_ERR_IO 3
+#define ISP_ERR_PANIC 4
+
+/* Counting options, filled with the defaults by isp_options_default(). */
+typedef struct isp_options {
+    /* Maximum insert size to record, 0 for 500. */
+    uint32_t upper;
+    /* Flags all required and any skipped, like --include-flags and --exclude-flags. */
+    uint16_t include_flags;
+    uint16_t exclude_flags;
+    /* Skip duplicates when non-zero. */
+    int no_dups;
+    /* Count QC-fail reads when non-zero. */
+    int include_qcfail;
+    /* Threads inflating bam blocks, 0 for 1. */
+    uint32_t threads;
+    /* Reference fasta for cram, NULL without. */
+    const char *reference;
+} isp_options;
+
+/* Summary of qualified pairs, and of all pairs for all_ fields. */
+typedef struct isp_summary {
+    uint64_t count;
+    double mean;
+    double median;
+    uint64_t mode;
+    double sd;
+    double mad;
+    uint64_t iqr;
+    uint64_t q1;
+    uint64_t q3;
+    double trimmed_mean;
+    double skewness;
+    double kurtosis;
+    uint64_t min;
+    uint64_t max;
+    uint64_t all_count;
+    double all_mean;
+    double all_sd;
+} isp_summary;
+
+/* Version of the C ABI of the library. */
+uint32_t isp_abi_version(void);
+
+/* Fill opts with the defaults. */
+int isp_options_default(isp_options *opts);
+
+/*
+ * Summarize insert sizes of bam, sam or cram at path with opts, NULL for the defaults, into
+ * summary. Returns ISP_OK or an error code, see isp_last_error().
+ */
+int isp_compute(const char *path, const isp_options *opts, isp_summary *summary);
+
+/*
+ * Summary json of bam, sam or cram at path with opts, NULL for the defaults, like the summary
+ * json of the command line. NULL on error, see isp_last_error(), free with isp_string_free().
+ */
+char *isp_compute_json(const char *path, const isp_options *opts);
+
+/* Free a string returned by isp_compute_json(). */
+void isp_string_free(char *v);
+
+/* Message of the last error on the calling thread, NULL without, valid until the next call. */
+const char *isp_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
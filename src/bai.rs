@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::error::{open_file, Error, Result};
+use crate::http::{is_url, HttpReader};
+
+/// Pseudo bin holding metadata instead of chunks.
+const META_BIN: u32 = 37450;
+/// Maximum position covered by bins.
+const MAX_POS: i32 = 1 << 29;
+
+/// Bins and linear index of one reference.
+#[derive(Default)]
+struct RefIndex {
+    bins: HashMap<u32, Vec<(u64, u64)>>,
+    intervals: Vec<u64>,
+}
+
+/// Bai index, chunks of virtual offsets per bin.
+pub struct BaiIndex {
+    references: Vec<RefIndex>,
+}
+
+/// Count read from the index, negative ones are malformed.
+fn count(v: i32) -> Result<u32> {
+    u32::try_from(v).map_err(|_| Error::Malformed("Negative count in BAI index.".to_string()))
+}
+
+/// Index paths of bam at `bam`, `<bam>.bai` then `<bam stem>.bai`, the query string of an url
+/// is kept after the index path.
+fn index_paths(bam: &str) -> [String; 2] {
+    let (path, query) = match bam.find('?').filter(|_| is_url(bam)) {
+        Some(i) => bam.split_at(i),
+        None => (bam, ""),
+    };
+    [
+        format!("{}.bai{}", path, query),
+        format!(
+            "{}{}",
+            Path::new(path).with_extension("bai").to_string_lossy(),
+            query
+        ),
+    ]
+}
+
+impl BaiIndex {
+    /// Read index of bam at `bam`, `<bam>.bai` or `<bam stem>.bai`, also for urls.
+    pub fn for_bam(bam: &str) -> Result<Self> {
+        for v in index_paths(bam).iter() {
+            if is_url(v) {
+                match HttpReader::new(v) {
+                    Ok(reader) => {
+                        return Self::from_reader(BufReader::new(reader)).map_err(|e| e.in_file(v))
+                    }
+                    Err(Error::NotFound(_)) | Err(Error::Denied(_)) => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            if Path::new(v).exists() {
+                return Self::from_path(v);
+            }
+        }
+        Err(Error::Invalid(format!(
+            "No bai index for {}, create one by `samtools index`.",
+            bam
+        )))
+    }
+
+    /// Read a bai file from path.
+    pub fn from_path(v: &str) -> Result<Self> {
+        Self::from_reader(BufReader::new(open_file(v)?)).map_err(|e| e.in_file(v))
+    }
+
+    /// Read a bai index from a stream.
+    pub fn from_reader<R: Read>(mut file: R) -> Result<Self> {
+        // Magic header.
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if magic != [b'B', b'A', b'I', 1] {
+            return Err(Error::Malformed("not a BAI index".to_string()));
+        };
+
+        // Counts are not trusted for allocation, vectors grow with data actually read.
+        let n_ref = count(file.read_i32::<LittleEndian>()?)?;
+        let mut references = Vec::new();
+        for _ in 0..n_ref {
+            let mut index = RefIndex::default();
+            let n_bin = count(file.read_i32::<LittleEndian>()?)?;
+            for _ in 0..n_bin {
+                let bin = file.read_u32::<LittleEndian>()?;
+                let n_chunk = count(file.read_i32::<LittleEndian>()?)?;
+                let mut chunks = Vec::new();
+                for _ in 0..n_chunk {
+                    let beg = file.read_u64::<LittleEndian>()?;
+                    let end = file.read_u64::<LittleEndian>()?;
+                    chunks.push((beg, end));
+                }
+                if bin != META_BIN {
+                    index.bins.insert(bin, chunks);
+                }
+            }
+            let n_intv = count(file.read_i32::<LittleEndian>()?)?;
+            index.intervals = (0..n_intv)
+                .map(|_| file.read_u64::<LittleEndian>())
+                .collect::<std::io::Result<_>>()?;
+            references.push(index);
+        }
+        Ok(Self { references })
+    }
+
+    /// Chunks possibly holding reads overlapping `[beg, end)` of reference `rid`.
+    pub fn query(&self, rid: usize, beg: i32, end: i32) -> Vec<(u64, u64)> {
+        let index = match self.references.get(rid) {
+            Some(v) => v,
+            None => return Vec::new(),
+        };
+        let beg = i32::max(beg, 0);
+        let end = i32::min(end, MAX_POS);
+        if beg >= end {
+            return Vec::new();
+        }
+        // Reads ending before the 16kb window of `beg` start before this offset.
+        let min_offset = index
+            .intervals
+            .get((beg >> 14) as usize)
+            .or_else(|| index.intervals.last())
+            .copied()
+            .unwrap_or(0);
+        reg2bins(beg, end)
+            .into_iter()
+            .filter_map(|v| index.bins.get(&v))
+            .flatten()
+            .filter(|(_, e)| *e > min_offset)
+            .map(|(b, e)| (u64::max(*b, min_offset), *e))
+            .collect()
+    }
+}
+
+/// Bins overlapping `[beg, end)`, as in the sam specification.
+fn reg2bins(beg: i32, end: i32) -> Vec<u32> {
+    let end = end - 1;
+    let mut bins = vec![0u32];
+    for (offset, shift) in [(1, 26), (9, 23), (73, 20), (585, 17), (4681, 14)].iter() {
+        for k in (offset + (beg >> shift))..=(offset + (end >> shift)) {
+            bins.push(k as u32);
+        }
+    }
+    bins
+}
+
+/// Sort chunks and merge overlapping or adjacent ones.
+pub fn merge_chunks(mut chunks: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    chunks.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(chunks.len());
+    for (beg, end) in chunks {
+        match merged.last_mut() {
+            Some(last) if beg <= last.1 => last.1 = u64::max(last.1, end),
+            _ => merged.push((beg, end)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bins_of_region() {
+        assert_eq!(reg2bins(0, 1), [0, 1, 9, 73, 585, 4681]);
+        assert_eq!(reg2bins(0, 1 << 14), [0, 1, 9, 73, 585, 4681]);
+        assert_eq!(reg2bins(0, (1 << 14) + 1), [0, 1, 9, 73, 585, 4681, 4682]);
+        assert_eq!(
+            reg2bins(1 << 26, (1 << 26) + 1),
+            [0, 2, 17, 137, 1097, 8777]
+        );
+        // Whole range covers every bin once.
+        let all = reg2bins(0, MAX_POS);
+        assert_eq!(all.len(), META_BIN as usize - 1);
+        assert_eq!(all.last(), Some(&(META_BIN - 2)));
+    }
+
+    #[test]
+    fn index_paths_of_bam() {
+        assert_eq!(index_paths("data/a.bam"), ["data/a.bam.bai", "data/a.bai"]);
+        assert_eq!(
+            index_paths("https://host/data/a.bam?X-Amz-Date=1&sig=a.b"),
+            [
+                "https://host/data/a.bam.bai?X-Amz-Date=1&sig=a.b",
+                "https://host/data/a.bai?X-Amz-Date=1&sig=a.b"
+            ]
+        );
+        assert_eq!(
+            index_paths("https://host/a.bam"),
+            ["https://host/a.bam.bai", "https://host/a.bai"]
+        );
+        // A question mark of a local file name is no query.
+        assert_eq!(index_paths("what?.bam"), ["what?.bam.bai", "what?.bai"]);
+    }
+
+    #[test]
+    fn merge_overlapping_chunks() {
+        assert_eq!(merge_chunks(Vec::new()), []);
+        assert_eq!(
+            merge_chunks(vec![(50, 60), (10, 20), (15, 30), (30, 40), (45, 46)]),
+            [(10, 40), (45, 46), (50, 60)]
+        );
+        // A chunk inside another keeps the outer end.
+        assert_eq!(merge_chunks(vec![(10, 100), (20, 30)]), [(10, 100)]);
+    }
+}
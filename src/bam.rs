@@ -0,0 +1,341 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::ErrorKind::UnexpectedEof;
+use std::io::{self, BufRead, Read, Seek};
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt};
+
+use crate::bgzf::{Bgzf, BgzfReader, ParallelBgzfReader};
+use crate::error::{open_file, Error, Result};
+use crate::header::Header;
+use crate::reader::RecordReader;
+use crate::record::{soft_clips, span_of, text_cigar_ops, Fields, Record};
+
+/// Bam reader, header is consumed on creation.
+pub struct BamReader<T: BufRead> {
+    reader: T,
+    // Header text and reference names and lengths.
+    header: Header,
+    // Read group ids from `@RG` header lines.
+    read_groups: Vec<String>,
+    // Data of current record, reused between records.
+    data: Vec<u8>,
+    // Optional fields parsed, others are left empty.
+    fields: Fields,
+}
+
+/// Records up to this size are read in one go, larger ones only as far as data goes.
+const TRUSTED_RECORD_SIZE: usize = 1 << 20;
+
+/// Cigar op chars by bam op code.
+const CIGAR_OPS: &[u8; 9] = b"MIDNSHP=X";
+
+/// Read `len` bytes, the buffer only grows with data actually read, whatever a corrupt length
+/// says.
+fn read_vec<R: Read>(file: &mut R, len: usize) -> Result<Vec<u8>> {
+    let mut v = Vec::new();
+    file.take(len as u64).read_to_end(&mut v)?;
+    if v.len() < len {
+        return Err(io::Error::from(UnexpectedEof).into());
+    }
+    Ok(v)
+}
+
+/// Whether 4 bit base code is C, G or S of `=ACMGRSVTWYHKDBN`.
+fn is_gc(code: u8) -> bool {
+    matches!(code, 2 | 4 | 6)
+}
+
+/// Value of a `Z` type optional field, `None` when absent or malformed.
+fn find_string_tag<'a>(aux: &'a [u8], tag: &[u8; 2]) -> Option<&'a [u8]> {
+    let mut i = 0;
+    while i + 3 <= aux.len() {
+        let typ = aux[i + 2];
+        let data = &aux[i + 3..];
+        let size = match typ {
+            b'A' | b'c' | b'C' => 1,
+            b's' | b'S' => 2,
+            b'i' | b'I' | b'f' => 4,
+            b'Z' | b'H' => data.iter().position(|v| *v == 0)? + 1,
+            b'B' => {
+                let width = match *data.first()? {
+                    b'c' | b'C' => 1,
+                    b's' | b'S' => 2,
+                    b'i' | b'I' | b'f' => 4,
+                    _ => return None,
+                };
+                let n = LittleEndian::read_u32(data.get(1..5)?) as usize;
+                5 + n * width
+            }
+            _ => return None,
+        };
+        if &aux[i..i + 2] == tag && typ == b'Z' {
+            return Some(&data[..size - 1]);
+        }
+        i += 3 + size;
+    }
+    None
+}
+
+impl<R: Read> BamReader<BgzfReader<R>> {
+    /// Read a bam from compressed stream.
+    pub fn new(inner: R) -> Result<Self> {
+        Self::from_reader(BgzfReader::new(inner))
+    }
+}
+
+impl<R: Read> BamReader<ParallelBgzfReader<R>> {
+    /// Read a bam from compressed stream, blocks are inflated by `threads` workers.
+    pub fn with_threads(inner: R, threads: usize) -> Result<Self> {
+        Self::from_reader(ParallelBgzfReader::new(inner, threads))
+    }
+}
+
+impl<R: Read + Seek> BamReader<BgzfReader<R>> {
+    /// Go to the record at bgzf virtual offset `voffset`, e.g. of a checkpoint.
+    pub fn seek(&mut self, voffset: u64) -> Result<()> {
+        Ok(self.reader.seek(voffset)?)
+    }
+}
+
+impl BamReader<BgzfReader<File>> {
+    /// Read a bam file from path.
+    pub fn from_path(v: &str) -> Result<Self> {
+        Self::new(open_file(v)?)
+    }
+}
+
+impl<T: BufRead> BamReader<T> {
+    /// Read a bam from decompressed stream.
+    pub fn from_reader(file: T) -> Result<Self> {
+        Self::read_header(file).map_err(Error::in_header)
+    }
+
+    fn read_header(mut file: T) -> Result<Self> {
+        // Magic header.
+        let mut magic = [0u8; 4];
+        file.read_exact(&mut magic)?;
+        if magic != [b'B', b'A', b'M', 1] {
+            return Err(Error::NotBam);
+        };
+
+        // Header text.
+        let l_text = file.read_i32::<LittleEndian>()?;
+        let l_text = usize::try_from(l_text)
+            .map_err(|_| Error::Malformed("Wrong BAM header text length.".to_string()))?;
+        let text = read_vec(&mut file, l_text)?;
+        let text = String::from_utf8_lossy(&text).into_owned();
+        let read_groups = text
+            .lines()
+            .filter(|v| v.starts_with("@RG"))
+            .filter_map(|v| v.split('\t').find_map(|v| v.strip_prefix("ID:")))
+            .map(String::from)
+            .collect();
+
+        // Reference and length, counts are not trusted for allocation.
+        let n_ref: u32 = file.read_u32::<LittleEndian>()?;
+        let mut references = Vec::new();
+        for _ in 0..n_ref {
+            let l_name = file.read_u32::<LittleEndian>()? as usize;
+            let mut name = read_vec(&mut file, l_name)?;
+            name.pop();
+            let name = String::from_utf8(name)
+                .map_err(|_| Error::Malformed("Wrong BAM reference name.".to_string()))?;
+            references.push((name, file.read_u32::<LittleEndian>()?));
+        }
+
+        Ok(Self {
+            reader: file,
+            header: Header::new(text, references),
+            read_groups,
+            data: Vec::new(),
+            fields: Fields::ALL,
+        })
+    }
+
+    /// Reference names and lengths from header.
+    pub fn references(&self) -> &[(String, u32)] {
+        self.header.references()
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.reader
+    }
+}
+
+impl<T: Bgzf> BamReader<T> {
+    /// Iterate records, e.g. for flag and TLEN scans of other tools. Iteration ends after an
+    /// error, except malformed records, which are read whole and skipped past.
+    pub fn records(&mut self) -> Records<'_, T> {
+        Records {
+            reader: self,
+            done: false,
+        }
+    }
+}
+
+/// Iterator of records of a bam, see `BamReader::records`.
+pub struct Records<'a, T: Bgzf> {
+    reader: &'a mut BamReader<T>,
+    done: bool,
+}
+
+impl<T: Bgzf> Iterator for Records<'_, T> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut record = Record::default();
+        match self.reader.read_into(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = !matches!(e, Error::Malformed(_));
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<T: Bgzf> RecordReader for BamReader<T> {
+    /// Read a record (one line of bam).
+    fn read_into(&mut self, record: &mut Record) -> Result<bool> {
+        // End of input only between records, anything cut short is a truncated file.
+        if self.reader.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let block_size = self.reader.read_u32::<LittleEndian>()? as usize;
+        // Whole record is read at once into the reused buffer and parsed from the slice,
+        // nothing is allocated per record. It is read before validation, so a malformed record
+        // can be skipped.
+        if block_size <= TRUSTED_RECORD_SIZE {
+            if self.data.len() < block_size {
+                self.data.resize(block_size, 0);
+            }
+            self.reader.read_exact(&mut self.data[..block_size])?;
+        } else {
+            // The buffer only grows with data actually read, whatever a corrupt size says.
+            self.data.clear();
+            (&mut self.reader)
+                .take(block_size as u64)
+                .read_to_end(&mut self.data)?;
+            if self.data.len() < block_size {
+                return Err(io::Error::from(UnexpectedEof).into());
+            }
+        }
+        if block_size < 32 {
+            return Err(Error::Malformed("Wrong BAM record size.".to_string()));
+        }
+        let data = &self.data[..block_size];
+
+        // Ref id and position.
+        record.set_ref_id(LittleEndian::read_i32(&data[0..]));
+        let pos = LittleEndian::read_i32(&data[4..]);
+        record.set_pos(pos);
+        // Query name length and mapq, bin is skipped.
+        let l_name = data[8] as usize;
+        record.set_mapq(data[9]);
+        // Number of operations in CIGAR.
+        let l_cigar = LittleEndian::read_u16(&data[12..]) as usize;
+        record.set_cigar_len(l_cigar as u16);
+        // Flag.
+        record.set_flag(LittleEndian::read_u16(&data[14..]));
+        // Sequence length.
+        let l_seq = LittleEndian::read_u32(&data[16..]) as usize;
+        record.set_seq_len(l_seq as u32);
+        // Mate ref id, mate pos and template length.
+        record.set_mate_ref_id(LittleEndian::read_i32(&data[20..]));
+        record.set_mate_pos(LittleEndian::read_i32(&data[24..]));
+        record.set_tlen(LittleEndian::read_i32(&data[28..]));
+
+        // Offsets of cigar and optional fields, sequence and quality lie between.
+        let cigar_start = 32 + l_name;
+        let aux_start = cigar_start + l_cigar * 4 + l_seq.div_ceil(2) + l_seq;
+        if aux_start > block_size {
+            return Err(Error::Malformed("Wrong BAM record size.".to_string()));
+        }
+        // G and C bases of sequence, two to a byte.
+        let seq_start = cigar_start + l_cigar * 4;
+        let gc: u32 = if self.fields.contains(Fields::GC) {
+            data[seq_start..seq_start + l_seq.div_ceil(2)]
+                .iter()
+                .map(|v| is_gc(v >> 4) as u32 + is_gc(v & 0xf) as u32)
+                .sum()
+        } else {
+            0
+        };
+        record.set_gc_count(gc);
+        // Query name without the trailing NUL.
+        if self.fields.contains(Fields::NAME) {
+            record.set_name(&data[32..32 + l_name.saturating_sub(1)]);
+        } else {
+            record.set_name(&[]);
+        }
+        // Cigar, only reference span and soft clips are kept.
+        let ops = data[cigar_start..seq_start]
+            .chunks_exact(4)
+            .map(LittleEndian::read_u32)
+            .map(|v| {
+                (
+                    (v >> 4) as i32,
+                    CIGAR_OPS.get(v as usize & 0xf).copied().unwrap_or(b'?'),
+                )
+            });
+        let aux = &data[aux_start..];
+        let span = if self.fields.contains(Fields::CLIPS) {
+            // One pass for both span and clips.
+            let mut span = 0;
+            record.set_clips(soft_clips(ops.inspect(|v| span = span_of(span, v))));
+            // Optional fields, mate cigar for mate clips.
+            record.set_mate_clips(soft_clips(text_cigar_ops(
+                find_string_tag(aux, b"MC").unwrap_or(&[]),
+            )));
+            span
+        } else {
+            record.set_clips((0, 0));
+            record.set_mate_clips((0, 0));
+            ops.fold(0i32, |n, v| span_of(n, &v))
+        };
+        record.set_end(pos.saturating_add(i32::max(span, 1)));
+        // Optional fields, read group.
+        if self.fields.contains(Fields::READ_GROUP) {
+            record.set_read_group(find_string_tag(aux, b"RG").unwrap_or(&[]));
+        } else {
+            record.set_read_group(&[]);
+        }
+        Ok(true)
+    }
+
+    fn reference_name(&self, id: i32) -> Option<&str> {
+        self.references().get(id as usize).map(|v| v.0.as_str())
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn read_groups(&self) -> &[String] {
+        &self.read_groups
+    }
+
+    fn set_fields(&mut self, fields: Fields) {
+        self.fields = fields;
+    }
+
+    fn virtual_offset(&self) -> Option<u64> {
+        Bgzf::virtual_offset(&self.reader)
+    }
+
+    fn missing_eof(&self) -> bool {
+        self.reader.missing_eof()
+    }
+}
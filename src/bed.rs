@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader};
+
+use crate::error::{open_file, Error, Result};
+
+/// Target regions from bed, merged intervals per reference.
+#[derive(Default)]
+pub struct Targets {
+    intervals: HashMap<String, Vec<(i32, i32)>>,
+}
+
+impl Targets {
+    /// Read a bed file from path, `track`, `browser` and `#` lines are skipped.
+    pub fn from_path(v: &str) -> Result<Self> {
+        let mut intervals: HashMap<String, Vec<(i32, i32)>> = HashMap::new();
+        for line in BufReader::new(open_file(v)?).lines() {
+            let line = line?;
+            if line.is_empty()
+                || line.starts_with('#')
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            let err = || Error::Malformed(format!("Malformed bed line: {}", line)).in_file(v);
+            let mut fields = line.split('\t');
+            let name = fields.next().ok_or_else(err)?;
+            let start: i32 = fields
+                .next()
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(err)?;
+            let end: i32 = fields
+                .next()
+                .and_then(|v| v.trim().parse().ok())
+                .ok_or_else(err)?;
+            intervals
+                .entry(name.to_string())
+                .or_default()
+                .push((start, end));
+        }
+
+        // Sort and merge overlapping intervals.
+        for v in intervals.values_mut() {
+            v.sort_unstable();
+            let mut merged: Vec<(i32, i32)> = Vec::with_capacity(v.len());
+            for &(start, end) in v.iter() {
+                match merged.last_mut() {
+                    Some(last) if start <= last.1 => last.1 = i32::max(last.1, end),
+                    _ => merged.push((start, end)),
+                }
+            }
+            *v = merged;
+        }
+        Ok(Self { intervals })
+    }
+
+    /// Intervals of reference.
+    pub fn get(&self, name: &str) -> Option<&[(i32, i32)]> {
+        self.intervals.get(name).map(Vec::as_slice)
+    }
+
+    /// Feed intervals in reference name order to `state`.
+    pub fn hash_into<H: Hasher>(&self, state: &mut H) {
+        let mut names: Vec<&String> = self.intervals.keys().collect();
+        names.sort();
+        for name in names {
+            name.hash(state);
+            self.intervals[name].hash(state);
+        }
+    }
+}
+
+/// Position falls inside any of sorted and merged intervals.
+pub fn contains(intervals: &[(i32, i32)], pos: i32) -> bool {
+    let i = intervals.partition_point(|v| v.1 <= pos);
+    intervals.get(i).is_some_and(|v| v.0 <= pos)
+}
@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind::{BrokenPipe, UnexpectedEof};
+use std::io::{BufRead, Error, Read, Result, Seek, SeekFrom};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::{Decompress, FlushDecompress};
+
+use crate::error;
+
+/// Fixed part of gzip header, extra subfields follow.
+const HEADER_SIZE: usize = 12;
+/// Crc32 and inflated size after compressed data.
+const FOOTER_SIZE: usize = 8;
+/// Largest inflated size of a block, 64 KiB by the spec.
+const MAX_BLOCK_SIZE: usize = 1 << 16;
+/// Size of the empty block ending a bgzf file.
+const EOF_SIZE: usize = 28;
+/// Compressed data and footer of the EOF block.
+const EOF_DATA: [u8; 10] = [3, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+/// Whole EOF block as written by htslib.
+pub(crate) const EOF_BLOCK: [u8; EOF_SIZE] = [
+    31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, 66, 67, 2, 0, 27, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Bgzf stream telling whether it ended with the EOF block.
+pub trait Bgzf: BufRead {
+    /// Whether input ended without the EOF block, e.g. a partially transferred file,
+    /// `false` before end of input.
+    fn missing_eof(&self) -> bool;
+
+    /// Virtual offset of next byte, `None` when blocks are inflated ahead.
+    fn virtual_offset(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Implementation inflating bgzf blocks, libdeflate is faster on whole blocks when built in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Decompressor {
+    /// Zlib stream inflater of flate2.
+    #[cfg_attr(not(feature = "libdeflate"), default)]
+    Flate2,
+    /// Whole buffer inflater of libdeflate, needs the `libdeflate` feature.
+    #[cfg(feature = "libdeflate")]
+    #[cfg_attr(feature = "libdeflate", default)]
+    Libdeflate,
+}
+
+impl FromStr for Decompressor {
+    type Err = error::Error;
+
+    fn from_str(v: &str) -> error::Result<Self> {
+        match v {
+            "flate2" => Ok(Self::Flate2),
+            #[cfg(feature = "libdeflate")]
+            "libdeflate" => Ok(Self::Libdeflate),
+            #[cfg(not(feature = "libdeflate"))]
+            "libdeflate" => Err(error::Error::Invalid(
+                "Libdeflate needs insize built with the `libdeflate` feature.".to_string(),
+            )),
+            _ => Err(error::Error::Invalid(format!(
+                "Wrong decompressor: {}, use flate2 or libdeflate.",
+                v
+            ))),
+        }
+    }
+}
+
+// Decompressor of readers created from now on, as `Decompressor as u8`.
+static DECOMPRESSOR: AtomicU8 = AtomicU8::new(u8::MAX);
+
+/// Inflate blocks of bgzf readers created from now on with `v`, process wide, e.g. to
+/// benchmark one against the other.
+pub fn set_decompressor(v: Decompressor) {
+    DECOMPRESSOR.store(v as u8, Ordering::Relaxed);
+}
+
+/// Decompressor set by `set_decompressor`, the default one before.
+pub fn decompressor() -> Decompressor {
+    match DECOMPRESSOR.load(Ordering::Relaxed) {
+        #[cfg(feature = "libdeflate")]
+        v if v == Decompressor::Libdeflate as u8 => Decompressor::Libdeflate,
+        v if v == Decompressor::Flate2 as u8 => Decompressor::Flate2,
+        _ => Decompressor::default(),
+    }
+}
+
+/// Inflater state of one reader or worker.
+enum Inflater {
+    Flate2(Decompress),
+    #[cfg(feature = "libdeflate")]
+    Libdeflate(libdeflater::Decompressor),
+}
+
+impl Inflater {
+    fn new() -> Self {
+        match decompressor() {
+            Decompressor::Flate2 => Self::Flate2(Decompress::new(false)),
+            #[cfg(feature = "libdeflate")]
+            Decompressor::Libdeflate => Self::Libdeflate(libdeflater::Decompressor::new()),
+        }
+    }
+
+    /// Inflate raw deflate `data` into `out`, return bytes inflated.
+    fn inflate(&mut self, data: &[u8], out: &mut [u8]) -> Result<usize> {
+        match self {
+            Self::Flate2(v) => {
+                v.reset(false);
+                v.decompress(data, out, FlushDecompress::Finish)
+                    .map_err(|e| malformed(&e.to_string()))?;
+                Ok(v.total_out() as usize)
+            }
+            #[cfg(feature = "libdeflate")]
+            Self::Libdeflate(v) => v
+                .deflate_decompress(data, out)
+                .map_err(|e| malformed(&e.to_string())),
+        }
+    }
+}
+
+fn is_eof_block(bsize: usize, cdata: &[u8]) -> bool {
+    bsize == EOF_SIZE && cdata == EOF_DATA
+}
+
+/// Bgzf reader inflating one block at a time, keeping track of virtual offsets.
+pub struct BgzfReader<R: Read> {
+    inner: R,
+    // Compressed offset of current block.
+    block_offset: u64,
+    // Compressed offset of next block.
+    next_offset: u64,
+    // Inflated current block.
+    block: Vec<u8>,
+    // Read position in current block.
+    pos: usize,
+    // Compressed data of current block.
+    cdata: Vec<u8>,
+    inflater: Inflater,
+    // End of input reached, and whether the last block was the EOF block.
+    eof: bool,
+    eof_block: bool,
+}
+
+/// Io error of malformed bgzf data.
+fn malformed(v: &str) -> Error {
+    error::Error::Malformed(v.to_string()).into()
+}
+
+/// Read until `buf` is full or end of input, return bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(v) => n += v,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+impl<R: Read> BgzfReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            block_offset: 0,
+            next_offset: 0,
+            block: Vec::with_capacity(0x10000),
+            pos: 0,
+            cdata: Vec::with_capacity(0x10000),
+            inflater: Inflater::new(),
+            eof: false,
+            eof_block: false,
+        }
+    }
+
+    /// Virtual offset of next byte, compressed block offset << 16 | offset in block.
+    pub fn virtual_offset(&self) -> u64 {
+        if self.pos == self.block.len() {
+            self.next_offset << 16
+        } else {
+            (self.block_offset << 16) | self.pos as u64
+        }
+    }
+
+    /// Inflate next non-empty block, return `false` at end of input.
+    fn read_block(&mut self) -> Result<bool> {
+        self.block.clear();
+        self.pos = 0;
+        loop {
+            self.block_offset = self.next_offset;
+            let bsize = match read_raw_block(&mut self.inner, &mut self.cdata)? {
+                Some(v) => v,
+                None => {
+                    self.eof = true;
+                    return Ok(false);
+                }
+            };
+            self.eof = false;
+            self.eof_block = is_eof_block(bsize, &self.cdata);
+            self.next_offset += bsize as u64;
+            inflate_block(&mut self.inflater, &self.cdata, &mut self.block)?;
+            if !self.block.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Read one compressed block, `cdata` gets compressed data and footer, return block size or
+/// `None` at end of input.
+fn read_raw_block<R: Read>(inner: &mut R, cdata: &mut Vec<u8>) -> Result<Option<usize>> {
+    let mut header = [0u8; HEADER_SIZE];
+    match read_full(inner, &mut header)? {
+        0 => return Ok(None),
+        HEADER_SIZE => {}
+        _ => return Err(Error::new(UnexpectedEof, "Truncated BGZF block.")),
+    }
+    if header[..4] != [31, 139, 8, 4] {
+        return Err(malformed("Wrong BGZF block header."));
+    }
+
+    // Extra subfields, block size is in `BC`.
+    let xlen = LittleEndian::read_u16(&header[10..]) as usize;
+    let mut extra = vec![0u8; xlen];
+    inner.read_exact(&mut extra)?;
+    let mut bsize = None;
+    let mut i = 0;
+    while i + 4 <= xlen {
+        let slen = LittleEndian::read_u16(&extra[i + 2..]) as usize;
+        if extra[i] == b'B' && extra[i + 1] == b'C' && slen == 2 && i + 6 <= xlen {
+            bsize = Some(LittleEndian::read_u16(&extra[i + 4..]) as usize + 1);
+        }
+        i += 4 + slen;
+    }
+    let bsize = bsize.ok_or_else(|| malformed("No BGZF block size."))?;
+    if bsize < HEADER_SIZE + xlen + FOOTER_SIZE {
+        return Err(malformed("Wrong BGZF block size."));
+    }
+
+    // Compressed data and footer.
+    cdata.resize(bsize - HEADER_SIZE - xlen, 0);
+    inner.read_exact(cdata)?;
+    Ok(Some(bsize))
+}
+
+/// Inflate compressed data and footer of one block into `block`.
+fn inflate_block(inflater: &mut Inflater, cdata: &[u8], block: &mut Vec<u8>) -> Result<()> {
+    let clen = cdata.len() - FOOTER_SIZE;
+    let isize = LittleEndian::read_u32(&cdata[clen + 4..]) as usize;
+    block.clear();
+    if isize == 0 {
+        return Ok(());
+    }
+    // A corrupt footer would otherwise size the block to gigabytes.
+    if isize > MAX_BLOCK_SIZE {
+        return Err(malformed("Wrong BGZF inflated size."));
+    }
+    block.resize(isize, 0);
+    if inflater.inflate(&cdata[..clen], block)? != isize {
+        return Err(malformed("Wrong BGZF inflated size."));
+    }
+    Ok(())
+}
+
+impl<R: Read + Seek> BgzfReader<R> {
+    /// Seek to virtual offset.
+    pub fn seek(&mut self, voffset: u64) -> Result<()> {
+        let coffset = voffset >> 16;
+        let uoffset = (voffset & 0xffff) as usize;
+        self.inner.seek(SeekFrom::Start(coffset))?;
+        self.next_offset = coffset;
+        self.read_block()?;
+        if uoffset > self.block.len() {
+            return Err(malformed("Virtual offset out of block."));
+        }
+        self.pos = uoffset;
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for BgzfReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.block.len() {
+            self.read_block()?;
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = usize::min(self.pos + amt, self.block.len());
+    }
+}
+
+impl<R: Read> Bgzf for BgzfReader<R> {
+    fn missing_eof(&self) -> bool {
+        self.eof && !self.eof_block
+    }
+
+    fn virtual_offset(&self) -> Option<u64> {
+        Some(BgzfReader::virtual_offset(self))
+    }
+}
+
+impl<R: Read> Read for BgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = usize::min(data.len(), buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+/// Bgzf reader inflating blocks ahead on worker threads, compressed blocks are read on the
+/// calling thread and inflated blocks are consumed in order.
+pub struct ParallelBgzfReader<R: Read> {
+    inner: R,
+    // Compressed blocks to workers, dropped to stop them.
+    jobs: Option<Sender<(u64, Vec<u8>)>>,
+    results: Receiver<(u64, Result<Vec<u8>>)>,
+    workers: Vec<JoinHandle<()>>,
+    // Inflated blocks arrived ahead of order.
+    pending: BTreeMap<u64, Result<Vec<u8>>>,
+    // Index of next block to send to workers and to consume.
+    sent: u64,
+    next: u64,
+    // Blocks in flight at most.
+    ahead: u64,
+    eof: bool,
+    eof_block: bool,
+    // Error of reading compressed blocks, kept until blocks before it are consumed.
+    error: Option<Error>,
+    block: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> ParallelBgzfReader<R> {
+    /// Reader inflating with `threads` workers.
+    pub fn new(inner: R, threads: usize) -> Self {
+        let threads = usize::max(threads, 1);
+        let (jobs, job_rx) = channel::<(u64, Vec<u8>)>();
+        let (result_tx, results) = channel();
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let workers = (0..threads)
+            .map(|_| {
+                let job_rx = Arc::clone(&job_rx);
+                let result_tx = result_tx.clone();
+                thread::spawn(move || {
+                    let mut inflater = Inflater::new();
+                    loop {
+                        let job = match job_rx.lock() {
+                            Ok(v) => v.recv(),
+                            Err(_) => return,
+                        };
+                        let (i, cdata) = match job {
+                            Ok(v) => v,
+                            Err(_) => return,
+                        };
+                        let mut block = Vec::new();
+                        let block = inflate_block(&mut inflater, &cdata, &mut block).map(|_| block);
+                        if result_tx.send((i, block)).is_err() {
+                            return;
+                        }
+                    }
+                })
+            })
+            .collect();
+        Self {
+            inner,
+            jobs: Some(jobs),
+            results,
+            workers,
+            pending: BTreeMap::new(),
+            sent: 0,
+            next: 0,
+            ahead: threads as u64 * 4,
+            eof: false,
+            eof_block: false,
+            error: None,
+            block: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Take next non-empty inflated block, return `false` at end of input.
+    fn read_block(&mut self) -> Result<bool> {
+        loop {
+            // Keep workers busy.
+            while !self.eof && self.sent - self.next < self.ahead {
+                let mut cdata = Vec::new();
+                match read_raw_block(&mut self.inner, &mut cdata) {
+                    Ok(Some(bsize)) => self.eof_block = is_eof_block(bsize, &cdata),
+                    Ok(None) => {
+                        self.eof = true;
+                        break;
+                    }
+                    Err(e) => {
+                        self.eof = true;
+                        self.error = Some(e);
+                        break;
+                    }
+                }
+                let jobs = self.jobs.as_ref().ok_or_else(stopped)?;
+                jobs.send((self.sent, cdata)).map_err(|_| stopped())?;
+                self.sent += 1;
+            }
+            if self.next == self.sent {
+                return self.error.take().map_or(Ok(false), Err);
+            }
+            let block = loop {
+                if let Some(v) = self.pending.remove(&self.next) {
+                    break v;
+                }
+                let (i, v) = self.results.recv().map_err(|_| stopped())?;
+                self.pending.insert(i, v);
+            };
+            self.next += 1;
+            self.block = block?;
+            self.pos = 0;
+            if !self.block.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+/// Error of workers gone away.
+fn stopped() -> Error {
+    Error::new(BrokenPipe, "BGZF worker stopped.")
+}
+
+impl<R: Read> Drop for ParallelBgzfReader<R> {
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<R: Read> BufRead for ParallelBgzfReader<R> {
+    fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.block.len() {
+            self.read_block()?;
+        }
+        Ok(&self.block[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = usize::min(self.pos + amt, self.block.len());
+    }
+}
+
+impl<R: Read> Bgzf for ParallelBgzfReader<R> {
+    fn missing_eof(&self) -> bool {
+        // Compressed blocks are read ahead, input ends once all of them are consumed.
+        self.eof && self.next == self.sent && !self.eof_block
+    }
+}
+
+impl<R: Read> Read for ParallelBgzfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = {
+            let data = self.fill_buf()?;
+            let n = usize::min(data.len(), buf.len());
+            buf[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
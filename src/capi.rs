@@ -0,0 +1,240 @@
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::error::{Error, Result};
+use crate::{compute_insert_sizes, Options, Summary};
+
+/// Version of the C ABI in `include/insize.h`, bumped on any incompatible change.
+pub const ISP_ABI_VERSION: u32 = 1;
+
+/// Return codes of C functions.
+pub const ISP_OK: c_int = 0;
+pub const ISP_ERR_INVALID: c_int = 1;
+pub const ISP_ERR_NOT_FOUND: c_int = 2;
+pub const ISP_ERR_IO: c_int = 3;
+pub const ISP_ERR_PANIC: c_int = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Counting options of `isp_compute`, filled with the defaults by `isp_options_default`.
+#[repr(C)]
+pub struct IspOptions {
+    /// Maximum insert size to record, 0 for 500.
+    pub upper: u32,
+    /// Flags all required and any skipped, like `--include-flags` and `--exclude-flags`.
+    pub include_flags: u16,
+    pub exclude_flags: u16,
+    /// Skip duplicates when non-zero.
+    pub no_dups: c_int,
+    /// Count QC-fail reads when non-zero.
+    pub include_qcfail: c_int,
+    /// Threads inflating bam blocks, 0 for 1.
+    pub threads: u32,
+    /// Reference fasta for cram, null without.
+    pub reference: *const c_char,
+}
+
+/// Summary of qualified pairs, and of all pairs for `all_` fields.
+#[repr(C)]
+#[derive(Default)]
+pub struct IspSummary {
+    pub count: u64,
+    pub mean: f64,
+    pub median: f64,
+    pub mode: u64,
+    pub sd: f64,
+    pub mad: f64,
+    pub iqr: u64,
+    pub q1: u64,
+    pub q3: u64,
+    pub trimmed_mean: f64,
+    pub skewness: f64,
+    pub kurtosis: f64,
+    pub min: u64,
+    pub max: u64,
+    pub all_count: u64,
+    pub all_mean: f64,
+    pub all_sd: f64,
+}
+
+impl From<&Summary> for IspSummary {
+    fn from(v: &Summary) -> Self {
+        Self {
+            count: v.count as u64,
+            mean: v.mean,
+            median: v.median,
+            mode: v.mode as u64,
+            sd: v.std,
+            mad: v.mad,
+            iqr: v.iqr as u64,
+            q1: v.q1 as u64,
+            q3: v.q3 as u64,
+            trimmed_mean: v.trimmed_mean,
+            skewness: v.skewness,
+            kurtosis: v.kurtosis,
+            min: v.min as u64,
+            max: v.max as u64,
+            all_count: v.all_count as u64,
+            all_mean: v.all_mean,
+            all_sd: v.all_std,
+        }
+    }
+}
+
+/// Run `f`, keeping the message of an error or panic for `isp_last_error`.
+fn guard<F: FnOnce() -> Result<()>>(f: F) -> c_int {
+    let (code, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => (ISP_OK, None),
+        Ok(Err(e)) => {
+            let code = match e {
+                Error::Invalid(_) => ISP_ERR_INVALID,
+                Error::NotFound(_) => ISP_ERR_NOT_FOUND,
+                _ => ISP_ERR_IO,
+            };
+            (code, Some(e.to_string()))
+        }
+        Err(_) => (ISP_ERR_PANIC, Some("Panicked.".to_string())),
+    };
+    let message = message.and_then(|v| CString::new(v.replace('\0', "")).ok());
+    LAST_ERROR.with(|v| *v.borrow_mut() = message);
+    code
+}
+
+/// Utf-8 string of nul terminated `v`.
+///
+/// # Safety
+///
+/// `v` is null or points to a nul terminated string.
+unsafe fn c_str<'a>(v: *const c_char, name: &str) -> Result<&'a str> {
+    if v.is_null() {
+        return Err(Error::Invalid(format!("{} is null.", name)));
+    }
+    CStr::from_ptr(v)
+        .to_str()
+        .map_err(|_| Error::Invalid(format!("{} is not utf-8.", name)))
+}
+
+/// Options from C options, defaults without.
+///
+/// # Safety
+///
+/// `opts` is null or points to valid options.
+unsafe fn options(opts: *const IspOptions) -> Result<Options> {
+    let mut out = Options::default();
+    if let Some(v) = opts.as_ref() {
+        if v.upper > 0 {
+            out.upper = v.upper as usize;
+        }
+        out.include_flags = v.include_flags;
+        out.exclude_flags = v.exclude_flags;
+        out.no_dups = v.no_dups != 0;
+        out.include_qcfail = v.include_qcfail != 0;
+        out.threads = (v.threads as usize).max(1);
+        if !v.reference.is_null() {
+            out.reference = Some(c_str(v.reference, "reference")?.to_string());
+        }
+    }
+    Ok(out)
+}
+
+/// Version of the C ABI, to check against `ISP_ABI_VERSION` of the header.
+#[no_mangle]
+pub extern "C" fn isp_abi_version() -> u32 {
+    ISP_ABI_VERSION
+}
+
+/// Fill `opts` with the defaults.
+///
+/// # Safety
+///
+/// `opts` is null or points to writable options.
+#[no_mangle]
+pub unsafe extern "C" fn isp_options_default(opts: *mut IspOptions) -> c_int {
+    guard(|| {
+        let opts = opts
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("Options are null.".to_string()))?;
+        let v = Options::default();
+        *opts = IspOptions {
+            upper: v.upper as u32,
+            include_flags: v.include_flags,
+            exclude_flags: v.exclude_flags,
+            no_dups: v.no_dups as c_int,
+            include_qcfail: v.include_qcfail as c_int,
+            threads: v.threads as u32,
+            reference: ptr::null(),
+        };
+        Ok(())
+    })
+}
+
+/// Summarize insert sizes of bam, sam or cram at `path` with `opts`, null for the defaults,
+/// into `summary`. Returns `ISP_OK` or an error code, see `isp_last_error`.
+///
+/// # Safety
+///
+/// `path` points to a nul terminated string, `opts` is null or points to valid options and
+/// `summary` points to a writable summary.
+#[no_mangle]
+pub unsafe extern "C" fn isp_compute(
+    path: *const c_char,
+    opts: *const IspOptions,
+    summary: *mut IspSummary,
+) -> c_int {
+    guard(|| {
+        let path = c_str(path, "Path")?;
+        let summary = summary
+            .as_mut()
+            .ok_or_else(|| Error::Invalid("Summary is null.".to_string()))?;
+        let sum = compute_insert_sizes(path, &options(opts)?)?;
+        *summary = IspSummary::from(&sum);
+        Ok(())
+    })
+}
+
+/// Summary json of bam, sam or cram at `path` with `opts`, null for the defaults, like the
+/// summary json of the command line. Null on error, see `isp_last_error`, free with
+/// `isp_string_free`.
+///
+/// # Safety
+///
+/// `path` points to a nul terminated string and `opts` is null or points to valid options.
+#[no_mangle]
+pub unsafe extern "C" fn isp_compute_json(
+    path: *const c_char,
+    opts: *const IspOptions,
+) -> *mut c_char {
+    let mut json = ptr::null_mut();
+    guard(|| {
+        let sum = compute_insert_sizes(c_str(path, "Path")?, &options(opts)?)?;
+        let v = CString::new(serde_json::to_string_pretty(&sum)?)
+            .map_err(|e| Error::Invalid(e.to_string()))?;
+        json = v.into_raw();
+        Ok(())
+    });
+    json
+}
+
+/// Free a string returned by `isp_compute_json`.
+///
+/// # Safety
+///
+/// `v` is null or was returned by `isp_compute_json` and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn isp_string_free(v: *mut c_char) {
+    if !v.is_null() {
+        drop(CString::from_raw(v));
+    }
+}
+
+/// Message of the last error on the calling thread, null without. Valid until the next call on
+/// the thread.
+#[no_mangle]
+pub extern "C" fn isp_last_error() -> *const c_char {
+    LAST_ERROR.with(|v| v.borrow().as_ref().map_or(ptr::null(), |v| v.as_ptr()))
+}
@@ -0,0 +1,364 @@
+use std::fs;
+use std::hash::Hasher;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::time::UNIX_EPOCH;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::error::{create_file, open_file, Error, Result};
+use crate::hist::InsertSizeHistogram;
+use crate::summary::{Alignments, Unqualified};
+
+/// Seconds between checkpoints by default.
+pub const DEFAULT_CHECKPOINT_EVERY: u64 = 60;
+
+/// Leading bytes of a checkpoint file.
+const MAGIC: &[u8; 8] = b"INSZCKPT";
+/// Version of the checkpoint layout, bumped on any change.
+const VERSION: u32 = 2;
+
+/// What a checkpoint was taken of, resuming needs the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Size and modification time of the input, in nanoseconds since the epoch.
+    pub input_size: u64,
+    pub input_mtime: u64,
+    /// Hash of options filtering and counting pairs, see `Options::counting_hash`.
+    pub options: u64,
+}
+
+impl Fingerprint {
+    /// Fingerprint of file at `path` counted with options hashed to `options`.
+    pub fn of_file(path: &str, options: u64) -> Result<Self> {
+        let meta = fs::metadata(path).map_err(|e| Error::from(e).in_file(path))?;
+        let input_mtime = meta
+            .modified()
+            .ok()
+            .and_then(|v| v.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |v| v.as_nanos() as u64);
+        Ok(Self {
+            input_size: meta.len(),
+            input_mtime,
+            options,
+        })
+    }
+}
+
+/// FNV-1a hasher, stable across runs and releases unlike the std one, for option hashes.
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for v in bytes {
+            self.0 = (self.0 ^ *v as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// Scan state of one bam at a record boundary, saved to resume an interrupted run.
+#[derive(Clone, Default)]
+pub struct ScanState {
+    /// Bgzf virtual offset of the next record.
+    pub voffset: u64,
+    pub records: u64,
+    pub records_skipped: u64,
+    pub pairs: u64,
+    pub duplicates: u64,
+    pub discordant_interchrom: u64,
+    pub tlen_zero: u64,
+    pub wrong_orientation: u64,
+    pub sequenced_pairs: u64,
+    pub short_inserts: u64,
+    pub alignments: Alignments,
+    pub unqualified: Unqualified,
+    /// All qualified pairs, `None` only in an empty state.
+    pub hist: Option<InsertSizeHistogram>,
+    pub orientations: Vec<InsertSizeHistogram>,
+    pub gc_strata: Vec<InsertSizeHistogram>,
+    pub mapq_buckets: Vec<InsertSizeHistogram>,
+    /// Histograms indexed by reference id.
+    pub chromosomes: Vec<Option<InsertSizeHistogram>>,
+}
+
+impl ScanState {
+    /// Save to `path` with `fingerprint` of input and options, through a temporary file
+    /// renamed over it, so a crash while saving keeps the last checkpoint.
+    pub fn save(&self, path: &str, fingerprint: &Fingerprint) -> Result<()> {
+        let tmp = format!("{}.tmp", path);
+        let mut w = BufWriter::new(create_file(&tmp)?);
+        self.write(&mut w, fingerprint)
+            .and_then(|_| Ok(w.into_inner().map_err(|e| e.into_error())?.sync_all()?))
+            .map_err(|e| e.in_file(&tmp))?;
+        fs::rename(&tmp, path).map_err(|e| Error::from(e).in_file(path))
+    }
+
+    /// Load from `path`, saved with the same `fingerprint` of input and options.
+    pub fn load(path: &str, fingerprint: &Fingerprint) -> Result<Self> {
+        let mut r = BufReader::new(open_file(path)?);
+        Self::read(&mut r, fingerprint).map_err(|e| e.in_file(path))
+    }
+
+    fn write<W: Write>(&self, w: &mut W, fingerprint: &Fingerprint) -> Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_u32::<LittleEndian>(VERSION)?;
+        w.write_u64::<LittleEndian>(fingerprint.input_size)?;
+        w.write_u64::<LittleEndian>(fingerprint.input_mtime)?;
+        w.write_u64::<LittleEndian>(fingerprint.options)?;
+        let counts = [
+            self.voffset,
+            self.records,
+            self.records_skipped,
+            self.pairs,
+            self.duplicates,
+            self.discordant_interchrom,
+            self.tlen_zero,
+            self.wrong_orientation,
+            self.sequenced_pairs,
+            self.short_inserts,
+            self.alignments.primary,
+            self.alignments.secondary,
+            self.alignments.supplementary,
+            self.unqualified.unmapped,
+            self.unqualified.mate_unmapped,
+            self.unqualified.interchromosomal,
+            self.unqualified.not_proper_pair,
+        ];
+        for v in counts {
+            w.write_u64::<LittleEndian>(v)?;
+        }
+        let hists = self
+            .hist
+            .iter()
+            .map(Some)
+            .chain(self.orientations.iter().map(Some))
+            .chain(self.gc_strata.iter().map(Some))
+            .chain(self.mapq_buckets.iter().map(Some));
+        // Group sizes first, then each histogram behind a presence byte.
+        for n in [
+            self.hist.iter().count(),
+            self.orientations.len(),
+            self.gc_strata.len(),
+            self.mapq_buckets.len(),
+            self.chromosomes.len(),
+        ] {
+            w.write_u64::<LittleEndian>(n as u64)?;
+        }
+        for hist in hists.chain(self.chromosomes.iter().map(Option::as_ref)) {
+            w.write_u8(hist.is_some() as u8)?;
+            if let Some(v) = hist {
+                v.write_state(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read<R: Read>(r: &mut R, fingerprint: &Fingerprint) -> Result<Self> {
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC || r.read_u32::<LittleEndian>()? != VERSION {
+            return Err(Error::Malformed("Not an insize checkpoint.".to_string()));
+        }
+        let saved = Fingerprint {
+            input_size: r.read_u64::<LittleEndian>()?,
+            input_mtime: r.read_u64::<LittleEndian>()?,
+            options: r.read_u64::<LittleEndian>()?,
+        };
+        if (saved.input_size, saved.input_mtime)
+            != (fingerprint.input_size, fingerprint.input_mtime)
+        {
+            return Err(Error::Invalid(
+                "Checkpoint is of another input, its size or modification time differs."
+                    .to_string(),
+            ));
+        }
+        if saved.options != fingerprint.options {
+            return Err(Error::Invalid(
+                "Checkpoint was saved with other counting options, resume with the same."
+                    .to_string(),
+            ));
+        }
+        let mut counts = [0u64; 17];
+        for v in counts.iter_mut() {
+            *v = r.read_u64::<LittleEndian>()?;
+        }
+        let mut sizes = [0u64; 5];
+        for v in sizes.iter_mut() {
+            *v = r.read_u64::<LittleEndian>()?;
+        }
+        let mut read_group = |n: u64| -> Result<Vec<Option<InsertSizeHistogram>>> {
+            (0..n)
+                .map(|_| match r.read_u8()? {
+                    0 => Ok(None),
+                    _ => InsertSizeHistogram::read_state(r).map(Some),
+                })
+                .collect()
+        };
+        let hist = read_group(sizes[0])?.into_iter().flatten().next();
+        let mut present = |n: u64| -> Result<Vec<InsertSizeHistogram>> {
+            Ok(read_group(n)?.into_iter().flatten().collect())
+        };
+        let orientations = present(sizes[1])?;
+        let gc_strata = present(sizes[2])?;
+        let mapq_buckets = present(sizes[3])?;
+        let chromosomes = read_group(sizes[4])?;
+        Ok(Self {
+            voffset: counts[0],
+            records: counts[1],
+            records_skipped: counts[2],
+            pairs: counts[3],
+            duplicates: counts[4],
+            discordant_interchrom: counts[5],
+            tlen_zero: counts[6],
+            wrong_orientation: counts[7],
+            sequenced_pairs: counts[8],
+            short_inserts: counts[9],
+            alignments: Alignments {
+                primary: counts[10],
+                secondary: counts[11],
+                supplementary: counts[12],
+            },
+            unqualified: Unqualified {
+                unmapped: counts[13],
+                mate_unmapped: counts[14],
+                interchromosomal: counts[15],
+                not_proper_pair: counts[16],
+            },
+            hist,
+            orientations,
+            gc_strata,
+            mapq_buckets,
+            chromosomes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint() -> Fingerprint {
+        Fingerprint {
+            input_size: 1234,
+            input_mtime: 1_700_000_000_000_000_000,
+            options: 42,
+        }
+    }
+
+    fn state() -> ScanState {
+        let mut hist = InsertSizeHistogram::new(500);
+        for v in [150, 180, 180, 220, 700] {
+            hist.add(v);
+        }
+        let mut chr2 = InsertSizeHistogram::new(500);
+        chr2.add(300);
+        ScanState {
+            voffset: 7 << 16 | 12,
+            records: 10,
+            records_skipped: 1,
+            pairs: 5,
+            duplicates: 2,
+            alignments: Alignments {
+                primary: 9,
+                secondary: 1,
+                supplementary: 0,
+            },
+            hist: Some(hist.clone()),
+            orientations: vec![hist, InsertSizeHistogram::new(500)],
+            chromosomes: vec![None, Some(chr2)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = Vec::new();
+        state().write(&mut buf, &fingerprint()).unwrap();
+        let v = ScanState::read(&mut buf.as_slice(), &fingerprint()).unwrap();
+        assert_eq!(v.voffset, 7 << 16 | 12);
+        assert_eq!((v.records, v.records_skipped, v.pairs), (10, 1, 5));
+        assert_eq!(v.duplicates, 2);
+        assert_eq!(v.alignments.primary, 9);
+        let hist = v.hist.unwrap();
+        assert_eq!(hist.upper(), 500);
+        assert_eq!(hist.count(), 4);
+        assert_eq!(hist.get(180), 2);
+        assert_eq!(v.orientations.len(), 2);
+        assert!(v.gc_strata.is_empty());
+        assert!(v.chromosomes[0].is_none());
+        assert_eq!(v.chromosomes[1].as_ref().unwrap().get(300), 1);
+    }
+
+    #[test]
+    fn other_options_or_input() {
+        let mut buf = Vec::new();
+        state().write(&mut buf, &fingerprint()).unwrap();
+        let other = [
+            Fingerprint {
+                options: 43,
+                ..fingerprint()
+            },
+            Fingerprint {
+                input_mtime: 1,
+                ..fingerprint()
+            },
+            Fingerprint {
+                input_size: 1,
+                ..fingerprint()
+            },
+        ];
+        for v in &other {
+            assert!(matches!(
+                ScanState::read(&mut buf.as_slice(), v),
+                Err(Error::Invalid(_))
+            ));
+        }
+        assert!(matches!(
+            ScanState::read(&mut &b"INSZCKPT\x01\0\0\0"[..], &fingerprint()),
+            Err(Error::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn counting_hash() {
+        let opts = crate::Options::default();
+        assert_eq!(
+            opts.counting_hash(),
+            crate::Options::default().counting_hash()
+        );
+        let changed = [
+            crate::Options {
+                no_dups: true,
+                ..Default::default()
+            },
+            crate::Options {
+                exclude_flags: 0,
+                ..Default::default()
+            },
+            crate::Options {
+                count_by: crate::CountBy::Read2,
+                ..Default::default()
+            },
+            crate::Options {
+                clip_adjust: true,
+                ..Default::default()
+            },
+            crate::Options {
+                exclude_contigs: vec!["chrM".to_string()],
+                ..Default::default()
+            },
+        ];
+        for v in &changed {
+            assert_ne!(v.counting_hash(), opts.counting_hash());
+        }
+    }
+}
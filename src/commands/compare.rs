@@ -0,0 +1,37 @@
+use insize::{Comparison, Options, Result};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{check_qc, named, write_json, write_outputs, Outputs, Samples};
+
+/// Summaries of two inputs and their differences.
+struct Compared<'a> {
+    samples: Samples<'a>,
+    comparison: Comparison,
+}
+
+impl Serialize for Compared<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Compared", 2)?;
+        state.serialize_field("Samples", &self.samples)?;
+        state.serialize_field("Comparison", &self.comparison)?;
+        state.end()
+    }
+}
+
+/// Overlaid curves of two inputs, with summaries and differences.
+pub fn compare(bams: &[&str; 2], out: &Outputs, opts: &Options) -> Result<()> {
+    let (hists, sums) = write_outputs(&named(bams), out, opts)?;
+    let comparison = Comparison::new(&hists[0].1.all, &hists[1].1.all);
+    write_json(
+        &Compared {
+            samples: Samples(&sums),
+            comparison,
+        },
+        out,
+    )?;
+    check_qc(&sums, out);
+    Ok(())
+}
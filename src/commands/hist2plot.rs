@@ -0,0 +1,24 @@
+use insize::plot::plot;
+use insize::table::read_histograms;
+use insize::Result;
+
+use crate::{named, opterr, Outputs};
+
+/// Curves of histograms written by `--hist`, tables of one sample are named by file.
+pub fn hist2plot(tables: &[&str], out: &Outputs) -> Result<()> {
+    let mut samples: Vec<(String, Vec<u32>)> = Vec::new();
+    for (stem, path) in named(tables) {
+        for (name, hist) in read_histograms(path)? {
+            let name = if name.is_empty() { stem.clone() } else { name };
+            samples.push((name, hist.into_counts()));
+        }
+    }
+    let upper = samples.iter().map(|(_, v)| v.len() - 1).max().unwrap_or(0);
+    if out.pics.is_empty() {
+        return Err(opterr());
+    }
+    for (pic, format) in &out.pics {
+        plot(pic, &upper, format, &samples, &out.plot)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::io::BufWriter;
+
+use insize::error::create_file;
+use insize::table::{read_histograms, write_histograms as write_table, write_histograms_json};
+use insize::{Histograms, InsertSizeHistogram, Result, Summary};
+
+use crate::{named, opterr, sample_name, write_json, Named, Outputs, Samples};
+
+/// Sum histograms at `paths` into one, or one per sample name `by_sample`, written to `merged`,
+/// with summaries recomputed. Histograms are cut at the smallest upper.
+pub fn merge(
+    paths: &[&str],
+    merged: &str,
+    name: Option<&str>,
+    by_sample: bool,
+    out: &Outputs,
+) -> Result<()> {
+    let mut samples: Vec<(String, InsertSizeHistogram)> = Vec::new();
+    for (stem, path) in named(paths) {
+        for (sample, hist) in read_histograms(path)? {
+            let sample = if sample.is_empty() {
+                stem.clone()
+            } else {
+                sample
+            };
+            samples.push((sample, hist));
+        }
+    }
+    let upper = samples
+        .iter()
+        .map(|(_, v)| v.upper())
+        .min()
+        .ok_or_else(opterr)?;
+    if samples.iter().any(|(_, v)| v.upper() != upper) {
+        warn!("histograms of different upper are merged up to {}.", upper);
+    }
+    // Sample of the merged histogram, e.g. `sample` of `sample.hist.json`.
+    let merged_name = name.map(String::from).unwrap_or_else(|| {
+        let stem = sample_name(merged);
+        stem.strip_suffix(".hist").unwrap_or(&stem).to_string()
+    });
+    let mut hists: Named<Histograms> = Vec::new();
+    for (sample, hist) in samples {
+        let sample = if by_sample {
+            sample
+        } else {
+            merged_name.clone()
+        };
+        match hists.iter_mut().find(|(k, _)| *k == sample) {
+            Some((_, v)) => v.all.merge(&hist),
+            None => {
+                let mut all = InsertSizeHistogram::new(upper);
+                all.merge(&hist);
+                hists.push((sample, Histograms::new(all)));
+            }
+        }
+    }
+    let file = BufWriter::new(create_file(merged)?);
+    if merged.ends_with(".json") {
+        write_histograms_json(file, &hists, 1)?;
+    } else {
+        let sep = if merged.ends_with(".csv") { ',' } else { '\t' };
+        write_table(file, &hists, sep, 1)?;
+    }
+    let sums: Named<Summary> = hists
+        .iter()
+        .map(|(k, v)| (k.clone(), v.summary()))
+        .collect();
+    if sums.len() == 1 {
+        write_json(&sums[0].1, out)
+    } else {
+        write_json(&Samples(&sums), out)
+    }
+}
@@ -0,0 +1,5 @@
+pub mod compare;
+pub mod hist2plot;
+pub mod merge;
+pub mod serve;
+pub mod watch;
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use insize::error::open_file;
+use insize::metrics::CONTENT_TYPE as METRICS_TYPE;
+use insize::plot::{plot, PicFormat};
+use insize::serve::{base64, body_timeout, respond, Deadline, SpoolDir, HEADER_TIMEOUT};
+use insize::{Error, Metrics, Options, Request, Result, Summary};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::{curves, opterr, sample_name, titled, write_outputs, Outputs};
+
+/// Status, content type and body of a response.
+type Response = (u16, &'static str, Vec<u8>);
+
+/// Json error response of `status`.
+fn error_response(status: u16, message: &str) -> Response {
+    let body = serde_json::to_vec(&HashMap::from([("error", message)])).unwrap_or_default();
+    (status, "application/json", body)
+}
+
+/// Serve analyses over http on `bind`:`port`, one request at a time, inputs by path are only
+/// read under `root`.
+///
+/// `GET /health` answers `ok` and `GET /metrics` gives metrics of samples analyzed so far.
+/// `GET /analyze?path=` or `POST /analyze` with the input as body returns json of the summary
+/// and the pic as base64, `/plot` returns the pic itself. Both take `format` of the pic, `name`
+/// of the sample and `path` relative to `root`.
+pub fn serve(
+    bind: &str,
+    port: u16,
+    root: &str,
+    max_upload: u64,
+    mut out: Outputs,
+    opts: &Options,
+) -> Result<()> {
+    let mut server = Server {
+        root: Path::new(root)
+            .canonicalize()
+            .map_err(|e| Error::from(e).in_file(root))?,
+        spool: SpoolDir::create()?,
+        max_upload,
+        metrics: Metrics::default(),
+    };
+    // Responses carry the outputs, nothing is written to files.
+    out.clear_files();
+    let listener = TcpListener::bind((bind, port))?;
+    info!("serving on http://{}", listener.local_addr()?);
+    for (n, stream) in listener.incoming().enumerate() {
+        let mut stream = match stream {
+            Ok(v) => v,
+            Err(e) => {
+                warn!("connection failed: {}", e);
+                continue;
+            }
+        };
+        let mut r = BufReader::new(Deadline::new(stream.try_clone()?, HEADER_TIMEOUT));
+        let (status, content_type, body) = match Request::read(&mut r) {
+            Ok(req) => {
+                let started = Instant::now();
+                r.get_mut().extend(body_timeout(req.content_length));
+                let response = handle(&req, &mut r, n, &mut server, &out, opts);
+                info!(
+                    "{} {}: {} in {:.2?}",
+                    req.method,
+                    req.path,
+                    response.0,
+                    started.elapsed()
+                );
+                response
+            }
+            Err(e) => error_response(400, &e.to_string()),
+        };
+        if let Err(e) = respond(&mut stream, status, content_type, &body) {
+            warn!("response failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// State of `serve` across requests.
+struct Server {
+    /// Canonical directory of inputs given by path.
+    root: PathBuf,
+    /// Private directory of uploads and pics.
+    spool: SpoolDir,
+    /// Largest request body accepted.
+    max_upload: u64,
+    metrics: Metrics,
+}
+
+/// Response to request `req` with body in `r`, the `n`th served.
+fn handle<R: BufRead>(
+    req: &Request,
+    r: &mut R,
+    n: usize,
+    server: &mut Server,
+    out: &Outputs,
+    opts: &Options,
+) -> Response {
+    let raw = match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/health") => return (200, "text/plain", b"ok\n".to_vec()),
+        ("GET", "/metrics") => return (200, METRICS_TYPE, server.metrics.render().into_bytes()),
+        ("GET" | "POST", "/analyze") => false,
+        ("GET" | "POST", "/plot") => true,
+        (_, "/health" | "/metrics" | "/analyze" | "/plot") => {
+            return error_response(405, "Method not allowed.")
+        }
+        _ => return error_response(404, "No such endpoint."),
+    };
+    let format = match req.param("format").map(PicFormat::from_name).transpose() {
+        Ok(v) => v.unwrap_or(out.format),
+        Err(e) => return error_response(400, &e.to_string()),
+    };
+    if req.content_length > server.max_upload {
+        return error_response(
+            413,
+            &format!("Uploads are limited to {} bytes.", server.max_upload),
+        );
+    }
+    // Uploads are spooled to a private temporary directory, removed after analysis.
+    let spool = &server.spool;
+    let upload = format!("{}.in", n);
+    let (name, input) = if req.method == "POST" && req.content_length > 0 {
+        let copied = spool
+            .create_file(&upload)
+            .and_then(|mut file| Ok(std::io::copy(&mut r.take(req.content_length), &mut file)?));
+        match copied {
+            Ok(v) if v == req.content_length => {}
+            Ok(_) => {
+                spool.remove_file(&upload);
+                return error_response(400, "Upload ended early.");
+            }
+            Err(e) => {
+                spool.remove_file(&upload);
+                return error_response(500, &e.to_string());
+            }
+        }
+        (
+            req.param("name").unwrap_or("upload").to_string(),
+            spool.path(&upload),
+        )
+    } else {
+        let path = match req.param("path") {
+            Some(v) => v,
+            None => return error_response(400, "Give an input by path or as request body."),
+        };
+        let input = match served_path(&server.root, path) {
+            Ok(v) => v,
+            Err(e) => return e,
+        };
+        let name = req
+            .param("name")
+            .map_or_else(|| sample_name(path), String::from);
+        (name, input)
+    };
+    let pic = format!("{}.{}", n, format.extension());
+    let analyzed = analyze(&name, &input, &spool.path(&pic), format, out, opts);
+    spool.remove_file(&upload);
+    spool.remove_file(&pic);
+    let (sum, pic) = match analyzed {
+        Ok(v) => v,
+        Err(e) => {
+            server.metrics.add_failure();
+            return error_response(422, &e.to_string());
+        }
+    };
+    let failures = out.fail_if.as_ref().map(|v| v.failures(&sum));
+    server
+        .metrics
+        .add(&name, &sum, failures.as_ref().map(|v| !v.is_empty()));
+    if raw {
+        return (200, content_type(format), pic);
+    }
+    let body = Analysis {
+        name: &name,
+        sum: &sum,
+        failures,
+        format,
+        pic: &pic,
+    };
+    match serde_json::to_vec_pretty(&body) {
+        Ok(v) => (200, "application/json", v),
+        Err(e) => error_response(500, &e.to_string()),
+    }
+}
+
+/// Input at `path` under canonical `root`, paths must not leave root, e.g. by `..` or symbolic
+/// links.
+fn served_path(root: &Path, path: &str) -> std::result::Result<PathBuf, Response> {
+    let input = match root.join(path).canonicalize() {
+        Ok(v) => v,
+        Err(_) => return Err(error_response(404, &format!("{}: not found", path))),
+    };
+    if !input.starts_with(root) {
+        return Err(error_response(
+            403,
+            &format!("{}: outside of served root", path),
+        ));
+    }
+    Ok(input)
+}
+
+/// Summary of sample `name` at `input` and its pic of `format`, drawn to `pic`.
+fn analyze(
+    name: &str,
+    input: &Path,
+    pic: &Path,
+    format: PicFormat,
+    out: &Outputs,
+    opts: &Options,
+) -> Result<(Summary, Vec<u8>)> {
+    let input = input.to_str().ok_or_else(opterr)?;
+    let (mut hists, mut sums) = write_outputs(&[(name.to_string(), input)], out, opts)?;
+    let (hist, sum) = (hists.remove(0).1, sums.remove(0).1);
+    let samples = curves(Some(name), &hist, out);
+    let upper = hist.all.counts().len() - 1;
+    let path = pic.to_str().ok_or_else(opterr)?;
+    plot(path, &upper, &format, &samples, &titled(&out.plot, [&hist]))?;
+    let mut bytes = Vec::new();
+    open_file(path)?.read_to_end(&mut bytes)?;
+    Ok((sum, bytes))
+}
+
+/// Summary and pic of a served analysis.
+struct Analysis<'a> {
+    name: &'a str,
+    sum: &'a Summary,
+    /// Failed `--fail-if` thresholds, `None` without.
+    failures: Option<Vec<String>>,
+    format: PicFormat,
+    pic: &'a [u8],
+}
+
+impl Serialize for Analysis<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let pic = HashMap::from([
+            ("format", self.format.extension().to_string()),
+            ("content_type", content_type(self.format).to_string()),
+            ("base64", base64(self.pic)),
+        ]);
+        let mut state = serializer.serialize_struct("Analysis", 4)?;
+        state.serialize_field("name", self.name)?;
+        state.serialize_field("summary", self.sum)?;
+        state.serialize_field("qc_failures", &self.failures)?;
+        state.serialize_field("pic", &pic)?;
+        state.end()
+    }
+}
+
+/// Media type of pics of `format`.
+fn content_type(format: PicFormat) -> &'static str {
+    match format {
+        PicFormat::Svg => "image/svg+xml",
+        PicFormat::Png => "image/png",
+        PicFormat::Pdf => "application/pdf",
+        PicFormat::Html => "text/html",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn paths_stay_under_root() {
+        let dir = std::env::temp_dir().join(format!("insize-root-{}", std::process::id()));
+        let root = dir.join("root");
+        fs::create_dir_all(root.join("runs")).unwrap();
+        fs::write(root.join("runs/a.bam"), b"").unwrap();
+        fs::write(dir.join("secret.bam"), b"").unwrap();
+        let root = root.canonicalize().unwrap();
+        let status = |path: &str| served_path(&root, path).map_err(|e| e.0);
+        assert_eq!(status("runs/a.bam"), Ok(root.join("runs/a.bam")));
+        assert_eq!(status("runs/../runs/a.bam"), Ok(root.join("runs/a.bam")));
+        assert_eq!(status("runs/b.bam"), Err(404));
+        assert_eq!(status("../secret.bam"), Err(403));
+        assert_eq!(status("runs/../../secret.bam"), Err(403));
+        assert_eq!(status(dir.join("secret.bam").to_str().unwrap()), Err(403));
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("secret.bam"), root.join("link.bam")).unwrap();
+            assert_eq!(status("link.bam"), Err(403));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
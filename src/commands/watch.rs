@@ -0,0 +1,97 @@
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use insize::error::create_file;
+use insize::table::write_summaries as write_summary_table;
+use insize::{Error, Metrics, Options, Result, Summary, Watcher};
+
+use crate::{opterr, sample_stem, write_outputs, write_sample, Named, Outputs};
+
+/// Process finished files of `pattern` in `dir` as they appear, a pic and summary json of each
+/// and a table of all summaries so far into `out.outdir`, polled every `interval` seconds or
+/// only once, with `metrics` of processed samples.
+pub fn watch(
+    dir: &str,
+    pattern: &str,
+    interval: u64,
+    once: bool,
+    metrics: Option<Arc<Mutex<Metrics>>>,
+    mut out: Outputs,
+    opts: &Options,
+) -> Result<()> {
+    let outdir = PathBuf::from(out.outdir.take().ok_or_else(opterr)?);
+    std::fs::create_dir_all(&outdir)
+        .map_err(|e| Error::from(e).in_file(&outdir.display().to_string()))?;
+    // Each sample would overwrite files of a run, outputs go to outdir per sample only.
+    if out.clear_files() {
+        warn!("pic, table and metrics files are not written by watch, see --outdir");
+    }
+    let mut watcher = Watcher::new(dir, pattern, Duration::from_secs(interval));
+    let mut sums: Named<Summary> = Vec::new();
+    info!("watching {} for {}", dir, pattern);
+    loop {
+        // A failing poll, e.g. of an unmounted run directory, is retried.
+        let finished = watcher.poll().unwrap_or_else(|e| {
+            error!("{}: {}", dir, e);
+            Vec::new()
+        });
+        for path in finished {
+            let name = path
+                .file_stem()
+                .map(|v| v.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            // Samples done by an earlier run are not redone.
+            if outdir.join(format!("{}.json", sample_stem(&name))).exists() {
+                info!("{}: already summarized, skipped", path.display());
+                continue;
+            }
+            let input = match path.to_str() {
+                Some(v) => v,
+                None => {
+                    error!("{}: not a utf-8 path, skipped", path.display());
+                    continue;
+                }
+            };
+            // A bad file is reported and watching goes on.
+            let done = write_outputs(&[(name.clone(), input)], &out, opts).and_then(
+                |(mut hists, mut done)| {
+                    let (hist, sum) = (hists.remove(0).1, done.remove(0).1);
+                    write_sample(&outdir, &name, &hist, &sum, &out)?;
+                    Ok(sum)
+                },
+            );
+            match done {
+                Ok(sum) => {
+                    let failures = out.fail_if.as_ref().map(|v| v.failures(&sum));
+                    if let Some(failures) = failures.as_ref().filter(|v| !v.is_empty()) {
+                        error!("QC failed for {}: {}", name, failures.join(" || "));
+                    }
+                    if let Some(metrics) = &metrics {
+                        if let Ok(mut v) = metrics.lock() {
+                            v.add(&name, &sum, failures.map(|v| !v.is_empty()));
+                        }
+                    }
+                    sums.push((name, sum));
+                    let table = create_file(outdir.join("summary.tsv"))
+                        .and_then(|v| Ok(write_summary_table(BufWriter::new(v), &sums, '\t')?));
+                    if let Err(e) = table {
+                        error!("summary.tsv: {}", e);
+                    }
+                    info!("{}: done", input);
+                }
+                Err(e) => {
+                    error!("{}: {}", input, e);
+                    if let Some(Ok(mut v)) = metrics.as_ref().map(|v| v.lock()) {
+                        v.add_failure();
+                    }
+                }
+            }
+        }
+        if once {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_secs(interval));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::error::{open_file, Error, Result};
+use crate::hist::{bins_median, InsertSizeHistogram};
+use crate::summary::{round2, round4};
+
+/// Differences between two insert size distributions, of pairs within upper.
+pub struct Comparison {
+    /// Kolmogorov-Smirnov statistic, largest distance between the two CDFs.
+    pub ks: f64,
+    /// Median of the second minus median of the first.
+    pub median_difference: f64,
+    /// Sum of the smaller proportion of each insert size, 1 for identical distributions.
+    pub overlap: f64,
+}
+
+impl Comparison {
+    pub fn new(first: &InsertSizeHistogram, second: &InsertSizeHistogram) -> Self {
+        let median = |hist: &InsertSizeHistogram| {
+            let bins = hist.bins().map(|(k, v)| (k as f64, v as u64));
+            bins_median(bins, hist.count() as u64)
+        };
+        let (a, b) = (weights(first), weights(second));
+        Self {
+            ks: ks_statistic(&a, &b),
+            median_difference: median(second) - median(first),
+            overlap: overlap_coefficient(&a, &b),
+        }
+    }
+}
+
+impl Serialize for Comparison {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Comparison", 3)?;
+        state.serialize_field("KS statistic", &round4(self.ks))?;
+        state.serialize_field("Median difference", &round2(self.median_difference))?;
+        state.serialize_field("Overlap coefficient", &round4(self.overlap))?;
+        state.end()
+    }
+}
+
+/// Pair count of each insert size within upper, as weights.
+fn weights(hist: &InsertSizeHistogram) -> Vec<(usize, f64)> {
+    hist.bins().map(|(k, v)| (k, v as f64)).collect()
+}
+
+/// Proportions of both distributions at each insert size of either, sorted by insert size.
+fn proportions(first: &[(usize, f64)], second: &[(usize, f64)]) -> Vec<(f64, f64)> {
+    let total = |bins: &[(usize, f64)]| bins.iter().map(|v| v.1).sum::<f64>();
+    let (first_total, second_total) = (total(first), total(second));
+    let mut merged: BTreeMap<usize, (f64, f64)> = BTreeMap::new();
+    for (k, v) in first {
+        merged.entry(*k).or_default().0 += v / first_total;
+    }
+    for (k, v) in second {
+        merged.entry(*k).or_default().1 += v / second_total;
+    }
+    merged.into_values().collect()
+}
+
+/// Largest distance between CDFs of two distributions of `(insert size, weight)` bins, weights
+/// are counts or fractions.
+pub fn ks_statistic(first: &[(usize, f64)], second: &[(usize, f64)]) -> f64 {
+    let (mut a, mut b, mut d) = (0.0, 0.0, 0.0);
+    for (p, q) in proportions(first, second) {
+        a += p;
+        b += q;
+        d = f64::max(d, (a - b).abs());
+    }
+    d
+}
+
+/// Overlapping area of two distributions of `(insert size, weight)` bins.
+pub fn overlap_coefficient(first: &[(usize, f64)], second: &[(usize, f64)]) -> f64 {
+    proportions(first, second)
+        .into_iter()
+        .map(|(p, q)| f64::min(p, q))
+        .sum()
+}
+
+/// Asymptotic p-value of KS statistic `d` with effective size `n`, as `probks` of Numerical
+/// Recipes, NaN without pairs.
+pub fn ks_p_value(d: f64, n: f64) -> f64 {
+    if n <= 0.0 {
+        return f64::NAN;
+    }
+    let sqrt_n = n.sqrt();
+    let lambda = (sqrt_n + 0.12 + 0.11 / sqrt_n) * d;
+    let a2 = -2.0 * lambda * lambda;
+    let (mut fac, mut sum, mut previous) = (2.0, 0.0, 0.0);
+    for j in 1..=100 {
+        let term = fac * (a2 * (j * j) as f64).exp();
+        sum += term;
+        if term.abs() <= 0.001 * previous || term.abs() <= 1e-8 * sum {
+            return sum.clamp(0.0, 1.0);
+        }
+        fac = -fac;
+        previous = term.abs();
+    }
+    // Not converging, `d` is too small to reject anything.
+    1.0
+}
+
+/// Expected distribution to test samples against, read from a `--hist` table.
+pub struct Reference {
+    /// Count, or fraction without a count column, of each insert size.
+    bins: Vec<(usize, f64)>,
+    /// Whether weights are counts, the test is then two sample.
+    counted: bool,
+}
+
+impl Reference {
+    /// Read a table with `insert_size` and `count` or `fraction` columns, comma separated for
+    /// `.csv`, tab separated otherwise. A `sample` column must hold a single sample.
+    pub fn from_path(v: &str) -> Result<Self> {
+        let sep = if v.ends_with(".csv") { ',' } else { '\t' };
+        let mut lines = BufReader::new(open_file(v)?).lines();
+        let header = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| Error::Malformed(format!("Empty reference histogram: {}", v)))?;
+        let column = |name: &str| header.split(sep).position(|v| v.trim() == name);
+        let key = column("insert_size").ok_or_else(|| {
+            Error::Malformed(format!(
+                "No insert_size column in reference histogram: {}",
+                v
+            ))
+        })?;
+        let (weight, counted) = match (column("count"), column("fraction")) {
+            (Some(i), _) => (i, true),
+            (None, Some(i)) => (i, false),
+            _ => {
+                return Err(Error::Malformed(format!(
+                    "No count or fraction column in reference histogram: {}",
+                    v
+                )))
+            }
+        };
+        let sample = column("sample");
+        let mut name: Option<String> = None;
+        let mut bins = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let err = || Error::Malformed(format!("Malformed reference line: {}", line));
+            let fields: Vec<&str> = line.split(sep).map(str::trim).collect();
+            if let Some(i) = sample {
+                let this = fields.get(i).ok_or_else(err)?;
+                match &name {
+                    Some(v) if v != this => {
+                        return Err(Error::Malformed(
+                            "Reference histogram has several samples.".to_string(),
+                        ))
+                    }
+                    _ => name = Some(this.to_string()),
+                }
+            }
+            let k: usize = fields
+                .get(key)
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(err)?;
+            let w: f64 = fields
+                .get(weight)
+                .and_then(|v| v.parse().ok())
+                .filter(|v: &f64| *v >= 0.0)
+                .ok_or_else(err)?;
+            if w > 0.0 {
+                bins.push((k, w));
+            }
+        }
+        if bins.is_empty() {
+            return Err(Error::Malformed(format!(
+                "No pairs in reference histogram: {}",
+                v
+            )));
+        }
+        bins.sort_by_key(|v| v.0);
+        Ok(Self { bins, counted })
+    }
+}
+
+/// Kolmogorov-Smirnov test of a sample against a reference distribution.
+#[derive(Clone, Copy, Debug)]
+pub struct KsTest {
+    pub statistic: f64,
+    pub p_value: f64,
+}
+
+impl KsTest {
+    /// Two sample test against counts, one sample test against fractions, of pairs within upper.
+    /// `None` without pairs, an empty sample tests nothing.
+    pub fn new(hist: &InsertSizeHistogram, reference: &Reference) -> Option<Self> {
+        if hist.count() == 0 {
+            return None;
+        }
+        let statistic = ks_statistic(&weights(hist), &reference.bins);
+        let n = hist.count() as f64;
+        let n = if reference.counted {
+            let m: f64 = reference.bins.iter().map(|v| v.1).sum();
+            n * m / (n + m)
+        } else {
+            n
+        };
+        Some(Self {
+            statistic,
+            p_value: ks_p_value(statistic, n),
+        })
+    }
+}
+
+impl Serialize for KsTest {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("KsTest", 2)?;
+        state.serialize_field("KS statistic", &round4(self.statistic))?;
+        state.serialize_field("p-value", &self.p_value)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    fn hist(sizes: &[usize]) -> InsertSizeHistogram {
+        let mut hist = InsertSizeHistogram::new(500);
+        for v in sizes {
+            hist.add(*v);
+        }
+        hist
+    }
+
+    /// Reference table written to a temporary file.
+    fn reference(name: &str, text: &str) -> Result<Reference> {
+        let path = std::env::temp_dir().join(format!("insize-{}-{}", std::process::id(), name));
+        fs::write(&path, text).unwrap();
+        let v = Reference::from_path(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        v
+    }
+
+    #[test]
+    fn ks_statistic_of_cdfs() {
+        let a = [(100, 1.0), (200, 1.0)];
+        assert_eq!(ks_statistic(&a, &a), 0.0);
+        // Counts and fractions of one distribution are the same.
+        assert_eq!(ks_statistic(&a, &[(100, 0.5), (200, 0.5)]), 0.0);
+        assert_eq!(ks_statistic(&a, &[(300, 4.0)]), 1.0);
+        assert!((ks_statistic(&a, &[(100, 1.0), (150, 1.0), (200, 2.0)]) - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn overlap_of_distributions() {
+        let a = [(100, 1.0), (200, 3.0)];
+        assert!((overlap_coefficient(&a, &a) - 1.0).abs() < 1e-12);
+        assert_eq!(overlap_coefficient(&a, &[(300, 1.0)]), 0.0);
+        assert!((overlap_coefficient(&a, &[(100, 1.0), (200, 1.0)]) - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn ks_p_values() {
+        // Critical value 1.358 of the Kolmogorov distribution at 5%.
+        assert!((ks_p_value(1.358 / 1000.0, 1e6) - 0.05).abs() < 1e-3);
+        assert!((ks_p_value(0.2, 50.0) - 0.031377).abs() < 1e-5);
+        assert!((ks_p_value(0.5, 10.0) - 0.008162).abs() < 1e-5);
+        assert_eq!(ks_p_value(0.0, 100.0), 1.0);
+        assert!(ks_p_value(1.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn empty_sample_is_not_tested() {
+        let reference = reference("empty.tsv", "insert_size\tcount\n150\t10\n").unwrap();
+        assert!(KsTest::new(&hist(&[]), &reference).is_none());
+        let test = KsTest::new(&hist(&[150, 150]), &reference).unwrap();
+        assert_eq!(test.statistic, 0.0);
+        assert_eq!(test.p_value, 1.0);
+    }
+
+    #[test]
+    fn reference_tables() {
+        let v = reference(
+            "counts.csv",
+            "sample,insert_size,count\na,200,3\na,100,1\n\na,150,0\n",
+        )
+        .unwrap();
+        assert!(v.counted);
+        assert_eq!(v.bins, [(100, 1.0), (200, 3.0)]);
+        let v = reference(
+            "fractions.tsv",
+            "insert_size\tfraction\n100\t0.25\n200\t0.75\n",
+        )
+        .unwrap();
+        assert!(!v.counted);
+        assert_eq!(v.bins, [(100, 0.25), (200, 0.75)]);
+    }
+
+    #[test]
+    fn bad_reference_tables() {
+        for (name, text) in [
+            ("empty.tsv", ""),
+            ("no-size.tsv", "size\tcount\n100\t1\n"),
+            ("no-count.tsv", "insert_size\tpairs\n100\t1\n"),
+            ("no-pairs.tsv", "insert_size\tcount\n100\t0\n"),
+            ("negative.tsv", "insert_size\tcount\n100\t-1\n"),
+            ("short.tsv", "insert_size\tcount\n100\n"),
+            (
+                "samples.tsv",
+                "sample\tinsert_size\tcount\na\t100\t1\nb\t100\t1\n",
+            ),
+        ] {
+            assert!(reference(name, text).is_err(), "{}", name);
+        }
+    }
+}
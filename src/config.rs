@@ -0,0 +1,92 @@
+use std::io::Read;
+
+use toml::{Table, Value};
+
+use crate::error::{open_file, Error, Result};
+
+/// Option defaults from a TOML file, keyed by long option name, e.g. `upper = 800` or
+/// `exclude-contigs = ["chrM", "HLA-*"]`.
+///
+/// Tables only group keys, `[plot]` holding `theme = "dark"` sets `theme`. Strings and numbers
+/// are values as given on the command line, arrays are lists and `true` sets a flag.
+pub struct Config {
+    options: Vec<ConfigOption>,
+}
+
+/// One option, its values and their comma separated list.
+struct ConfigOption {
+    name: String,
+    value: String,
+    values: Vec<String>,
+}
+
+impl Config {
+    /// Read the TOML file at `path`.
+    pub fn from_path(path: &str) -> Result<Self> {
+        let mut text = String::new();
+        open_file(path)?.read_to_string(&mut text)?;
+        let table: Table = text
+            .parse()
+            .map_err(|e: toml::de::Error| Error::Malformed(e.to_string()).in_file(path))?;
+        let mut options = Vec::new();
+        flatten(table, &mut options).map_err(|e| e.in_file(path))?;
+        Ok(Self { options })
+    }
+
+    fn get(&self, name: &str) -> Option<&ConfigOption> {
+        self.options.iter().find(|v| v.name == name)
+    }
+
+    /// Value of option `name`, a list comma separated.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.get(name).map(|v| v.value.as_str())
+    }
+
+    /// Values of repeatable option `name`.
+    pub fn values(&self, name: &str) -> Option<&[String]> {
+        self.get(name).map(|v| v.values.as_slice())
+    }
+
+    /// Option names set.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.options.iter().map(|v| v.name.as_str())
+    }
+}
+
+/// Collect keys of `table` and its tables, flags set to `false` are left out.
+fn flatten(table: Table, options: &mut Vec<ConfigOption>) -> Result<()> {
+    for (key, value) in table {
+        let values: Vec<String> = match value {
+            Value::Table(v) => {
+                flatten(v, options)?;
+                continue;
+            }
+            Value::Boolean(false) => continue,
+            Value::Array(v) => v
+                .into_iter()
+                .map(|v| scalar(&key, v))
+                .collect::<Result<_>>()?,
+            v => vec![scalar(&key, v)?],
+        };
+        if options.iter().any(|v| v.name == key) {
+            return Err(Error::Malformed(format!("Duplicate config key: {}", key)));
+        }
+        options.push(ConfigOption {
+            name: key,
+            value: values.join(","),
+            values,
+        });
+    }
+    Ok(())
+}
+
+/// Text of a single value, as given on the command line.
+fn scalar(key: &str, value: Value) -> Result<String> {
+    match value {
+        Value::String(v) => Ok(v),
+        Value::Integer(v) => Ok(v.to_string()),
+        Value::Float(v) => Ok(v.to_string()),
+        Value::Boolean(v) => Ok(v.to_string()),
+        _ => Err(Error::Malformed(format!("Wrong config value of {}", key))),
+    }
+}
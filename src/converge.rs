@@ -0,0 +1,81 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::hist::InsertSizeHistogram;
+use crate::summary::round4;
+
+/// Pairs counted before reading may stop on convergence by default.
+pub const DEFAULT_CONVERGE_MIN_PAIRS: u64 = 100_000;
+/// Pairs between convergence checks.
+const CONVERGE_EVERY: u64 = 10_000;
+
+/// Watches the running median and SD, reading may stop once both change by less than a
+/// tolerance between checks, the histogram is then as good as the whole input's for QC.
+pub struct Converge {
+    tolerance: f64,
+    min_pairs: u64,
+    last: Option<(f64, f64)>,
+}
+
+impl Converge {
+    /// Stop on relative changes below `tolerance`, after `min_pairs` pairs at least.
+    pub fn new(tolerance: f64, min_pairs: u64) -> Self {
+        Self {
+            tolerance,
+            min_pairs,
+            last: None,
+        }
+    }
+
+    /// Whether estimates of `hist` after `pairs` pairs have settled.
+    pub fn settled(&mut self, hist: &InsertSizeHistogram, pairs: u64) -> bool {
+        if pairs == 0 || !pairs.is_multiple_of(CONVERGE_EVERY) {
+            return false;
+        }
+        let current = (hist.percentile(50.0) as f64, sd(hist));
+        let last = self.last.replace(current);
+        let close = |a: f64, b: f64| (a - b).abs() <= self.tolerance * b.abs();
+        pairs >= self.min_pairs
+            && last.is_some_and(|v| close(current.0, v.0) && close(current.1, v.1))
+    }
+}
+
+/// Insert size SD within upper.
+fn sd(hist: &InsertSizeHistogram) -> f64 {
+    let (mut n, mut total, mut squares) = (0.0, 0.0, 0.0);
+    for (k, v) in hist.bins() {
+        let (k, v) = (k as f64, v as f64);
+        n += v;
+        total += k * v;
+        squares += k * k * v;
+    }
+    if n > 0.0 {
+        let mean = total / n;
+        (squares / n - mean * mean).max(0.0).sqrt()
+    } else {
+        0.0
+    }
+}
+
+/// Where reading stopped on convergence.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EarlyStop {
+    pub records: u64,
+    pub pairs: u64,
+    /// Bytes read and their fraction of the file, `None` when unknown, e.g. of stdin.
+    pub bytes: Option<u64>,
+    pub fraction: Option<f64>,
+}
+
+impl Serialize for EarlyStop {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("EarlyStop", 4)?;
+        state.serialize_field("records_read", &self.records)?;
+        state.serialize_field("pairs_counted", &self.pairs)?;
+        state.serialize_field("bytes_read", &self.bytes)?;
+        state.serialize_field("file_fraction", &self.fraction.map(round4))?;
+        state.end()
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::BufReader;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+use crate::error::{Error, Result};
+use crate::header::Header;
+use crate::reader::RecordReader;
+use crate::record::Record;
+use crate::sam::SamReader;
+
+/// Cram reader, decoded to sam by `samtools view`, which must be in PATH.
+pub struct CramReader {
+    child: Child,
+    sam: SamReader<BufReader<ChildStdout>>,
+}
+
+impl CramReader {
+    /// Read cram at `path` with `reference` fasta passed as `-T`, decoded by `threads` threads.
+    pub fn new(path: &str, reference: Option<&str>, threads: usize) -> Result<Self> {
+        let mut cmd = Command::new("samtools");
+        cmd.args(["view", "-h"]);
+        if threads > 1 {
+            cmd.arg("-@").arg((threads - 1).to_string());
+        }
+        if let Some(v) = reference {
+            cmd.arg("-T").arg(v);
+        }
+        let mut child = cmd
+            .arg(path)
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::Command(format!("Failed to run samtools for cram: {}", e)))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| Error::Command("No samtools output.".to_string()))?;
+        Ok(Self {
+            child,
+            sam: SamReader::new(BufReader::new(stdout)),
+        })
+    }
+}
+
+impl Drop for CramReader {
+    /// Stop samtools when not read to the end, e.g. on an early stop or error, and reap it.
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl RecordReader for CramReader {
+    /// Read a record, check samtools exit status at end of input.
+    fn read_into(&mut self, record: &mut Record) -> Result<bool> {
+        if self.sam.read_into(record)? {
+            return Ok(true);
+        }
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(Error::Command(format!(
+                "Failed to decode cram, samtools {}.",
+                status
+            )));
+        }
+        Ok(false)
+    }
+
+    fn reference_name(&self, id: i32) -> Option<&str> {
+        self.sam.reference_name(id)
+    }
+
+    fn read_groups(&self) -> &[String] {
+        self.sam.read_groups()
+    }
+
+    fn header(&self) -> &Header {
+        self.sam.header()
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::HashSet;
+
+use crate::record::Record;
+
+/// Keys held at most by default, some 30 MB.
+pub const DEFAULT_DEDUP_CAPACITY: usize = 1 << 20;
+
+/// Strand bits of read and mate.
+const STRANDS: u16 = 0x30;
+
+/// Approximate duplicate collapse of reads not marked as duplicates, pairs of the same
+/// reference, start, mate start and orientation are counted once.
+///
+/// Duplicates of coordinate sorted input start together, so keys are dropped whenever the
+/// start moves on. Once a start goes backwards input is taken as unsorted, keys are then
+/// dropped when `capacity` are held and duplicates further apart are missed.
+pub struct Dedup {
+    seen: HashSet<(i32, i32, i32, u16)>,
+    capacity: usize,
+    // Reference and start of last pair.
+    last: (i32, i32),
+    sorted: bool,
+}
+
+impl Dedup {
+    /// Collapse holding at most `capacity` keys.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            seen: HashSet::new(),
+            capacity: capacity.max(1),
+            last: (-1, -1),
+            sorted: true,
+        }
+    }
+
+    /// Whether pair of `record` was seen before, it is remembered otherwise.
+    pub fn is_duplicate(&mut self, record: &Record) -> bool {
+        let start = (*record.ref_id(), *record.pos());
+        if start < self.last {
+            self.sorted = false;
+        }
+        if self.sorted && start > self.last || self.seen.len() >= self.capacity {
+            self.seen.clear();
+        }
+        self.last = start;
+        !self.seen.insert((
+            start.0,
+            start.1,
+            *record.mate_pos(),
+            record.flag() & STRANDS,
+        ))
+    }
+}
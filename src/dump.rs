@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use crate::error::{create_file, Error, Result};
+
+/// Absolute insert size of every counted pair, one per line, for fitting models downstream.
+/// Gzip compressed for `.gz` paths, with reference name and 1-based position of the counted
+/// read in tab separated columns before it when `positions`.
+pub struct TlenDump {
+    out: Output,
+    positions: bool,
+    path: String,
+}
+
+/// Plain or gzip compressed file, gzip needs finishing.
+enum Output {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl TlenDump {
+    /// Dump to `path`, with positions when `positions`.
+    pub fn create(path: &str, positions: bool) -> Result<Self> {
+        let file = BufWriter::new(create_file(path)?);
+        let out = if path.ends_with(".gz") {
+            Output::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            Output::Plain(file)
+        };
+        Ok(Self {
+            out,
+            positions,
+            path: path.to_string(),
+        })
+    }
+
+    /// Write insert size `tlen` of a pair counted by a read at 0-based `pos` of `reference`.
+    pub fn write(&mut self, tlen: usize, reference: Option<&str>, pos: i32) -> Result<()> {
+        let out: &mut dyn Write = match &mut self.out {
+            Output::Plain(v) => v,
+            Output::Gzip(v) => v,
+        };
+        let written = if self.positions {
+            writeln!(out, "{}\t{}\t{}", reference.unwrap_or("*"), pos + 1, tlen)
+        } else {
+            writeln!(out, "{}", tlen)
+        };
+        written.map_err(|e| Error::from(e).in_file(&self.path))
+    }
+
+    /// Flush the file, writing the gzip trailer.
+    pub fn finish(self) -> Result<()> {
+        let Self { out, path, .. } = self;
+        let flushed = match out {
+            Output::Plain(mut v) => v.flush(),
+            Output::Gzip(v) => v.finish().and_then(|mut v| v.flush()),
+        };
+        flushed.map_err(|e| Error::from(e).in_file(&path))
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+/// Errors of reading inputs, parsing options and writing outputs.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// Error about a file, e.g. an input or an index.
+    #[error("{path}: {source}")]
+    File { path: String, source: Box<Error> },
+    /// Gzip compressed input without bam magic.
+    #[error("not a BAM file (is it SAM or CRAM?)")]
+    NotBam,
+    /// Input ending inside a record or a compressed block, `offset` is of the input file when
+    /// known.
+    #[error("truncated file, {records} records read{}", byte("before", .offset))]
+    Truncated { records: u64, offset: Option<u64> },
+    /// Bgzf input ended without the EOF block.
+    #[error("missing BGZF EOF marker, file is likely truncated, {records} records read")]
+    NoEof { records: u64 },
+    /// Malformed data of the `record`th record, 1-based.
+    #[error("record {record}{}: {source}", byte("near", .offset))]
+    Record {
+        record: u64,
+        offset: Option<u64>,
+        source: Box<Error>,
+    },
+    /// Malformed content, e.g. a bgzf block, a sam line or a bed line.
+    #[error("{0}")]
+    Malformed(String),
+    /// Wrong option or argument.
+    #[error("{0}")]
+    Invalid(String),
+    /// Missing file or remote object.
+    #[error("{0}: not found")]
+    NotFound(String),
+    /// Remote object missing or not accessible, cloud storage does not tell.
+    #[error("{0}: access denied or not found")]
+    Denied(String),
+    /// Failed request to a server.
+    #[error("{url}: {message}")]
+    Http { url: String, message: String },
+    /// Failed external command, e.g. samtools for cram.
+    #[error("{0}")]
+    Command(String),
+    /// Failed drawing of a pic.
+    #[error("{0}")]
+    Plot(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn byte(word: &str, offset: &Option<u64>) -> String {
+    offset.map_or_else(String::new, |v| format!(" {} byte {}", word, v))
+}
+
+impl Error {
+    /// Error about file at `path`, kept as is when it names a file already.
+    pub fn in_file(self, path: &str) -> Self {
+        match self {
+            Self::File { .. } | Self::NotFound(_) | Self::Denied(_) | Self::Http { .. } => self,
+            e => Self::File {
+                path: path.to_string(),
+                source: Box::new(e),
+            },
+        }
+    }
+
+    fn is_truncated(&self) -> bool {
+        match self {
+            Self::Io(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+            Self::Truncated { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Error of reading a header, truncation is before any record.
+    pub fn in_header(self) -> Self {
+        if self.is_truncated() {
+            Self::Truncated {
+                records: 0,
+                offset: None,
+            }
+        } else {
+            self
+        }
+    }
+
+    /// Error of reading on after `records` records, truncation or malformed data of the next.
+    pub fn at_record(self, records: u64, offset: Option<u64>) -> Self {
+        if self.is_truncated() {
+            Self::Truncated { records, offset }
+        } else {
+            Self::Record {
+                record: records + 1,
+                offset,
+                source: Box::new(self),
+            }
+        }
+    }
+}
+
+/// Errors of `Read` and `Write` impls keep their message, and truncation stays `UnexpectedEof`.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::NotFound(_) => io::Error::new(io::ErrorKind::NotFound, e),
+            Error::Truncated { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Open file at `path` for reading, errors name the path.
+pub fn open_file(path: &str) -> Result<File> {
+    File::open(path).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => Error::NotFound(path.to_string()),
+        _ => Error::from(e).in_file(path),
+    })
+}
+
+/// Create file at `path` for writing, errors name the path.
+pub fn create_file<P: AsRef<Path>>(path: P) -> Result<File> {
+    let path = path.as_ref();
+    File::create(path).map_err(|e| Error::from(e).in_file(&path.display().to_string()))
+}
@@ -0,0 +1,206 @@
+use std::f64::consts::PI;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::summary::{round2, round4};
+
+/// EM iterations at most.
+const MAX_ITERATIONS: usize = 500;
+/// Fitting stops once the log likelihood gains less than this.
+const TOLERANCE: f64 = 1e-6;
+/// Smallest component SD in bp, so a component can not collapse onto one insert size.
+const MIN_SD: f64 = 1.0;
+
+/// One Gaussian of a mixture.
+#[derive(Clone, Copy, Debug)]
+pub struct Component {
+    pub weight: f64,
+    pub mean: f64,
+    pub sd: f64,
+}
+
+impl Component {
+    /// Density of the component at `x`, scaled by its weight.
+    pub fn density(&self, x: f64) -> f64 {
+        let z = (x - self.mean) / self.sd;
+        self.weight * (-0.5 * z * z).exp() / (self.sd * (2.0 * PI).sqrt())
+    }
+}
+
+/// Gaussian mixture fitted to insert size counts by expectation maximization, e.g. of
+/// nucleosome ladders of cfDNA.
+#[derive(Clone, Debug)]
+pub struct Mixture {
+    /// Components by ascending mean.
+    pub components: Vec<Component>,
+    pub log_likelihood: f64,
+    pub iterations: usize,
+}
+
+impl Mixture {
+    /// Mixture of `k` components fitted to `counts` indexed by insert size, none without
+    /// counts or components.
+    pub fn fit(counts: &[u32], k: usize) -> Option<Self> {
+        let bins: Vec<(f64, f64)> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| **v > 0)
+            .map(|(x, v)| (x as f64, *v as f64))
+            .collect();
+        let total: f64 = bins.iter().map(|v| v.1).sum();
+        if k == 0 || total == 0.0 {
+            return None;
+        }
+        let mean = bins.iter().map(|(x, n)| x * n).sum::<f64>() / total;
+        let var = bins
+            .iter()
+            .map(|(x, n)| n * (x - mean).powi(2))
+            .sum::<f64>()
+            / total;
+        // Means start at evenly spaced quantiles, each as wide as its share of the spread.
+        let mut components: Vec<Component> = (0..k)
+            .map(|i| Component {
+                weight: 1.0 / k as f64,
+                mean: quantile(&bins, total, (i as f64 + 0.5) / k as f64),
+                sd: (var.sqrt() / k as f64).max(MIN_SD),
+            })
+            .collect();
+        let mut log_likelihood = f64::NEG_INFINITY;
+        let mut iterations = 0;
+        let mut resp = vec![0.0; k];
+        while iterations < MAX_ITERATIONS {
+            iterations += 1;
+            // Sums of responsibilities, and of them times insert size and its square.
+            let mut sums = vec![(0.0, 0.0, 0.0); k];
+            let mut ll = 0.0;
+            for (x, n) in &bins {
+                let mut sum = 0.0;
+                for (r, c) in resp.iter_mut().zip(&components) {
+                    *r = c.density(*x);
+                    sum += *r;
+                }
+                if sum <= 0.0 {
+                    continue;
+                }
+                ll += n * sum.ln();
+                for (r, s) in resp.iter().zip(sums.iter_mut()) {
+                    let w = n * r / sum;
+                    s.0 += w;
+                    s.1 += w * x;
+                    s.2 += w * x * x;
+                }
+            }
+            for (c, (w, wx, wxx)) in components.iter_mut().zip(&sums) {
+                if *w <= 0.0 {
+                    continue;
+                }
+                c.weight = w / total;
+                c.mean = wx / w;
+                c.sd = (wxx / w - c.mean * c.mean).max(0.0).sqrt().max(MIN_SD);
+            }
+            let gain = ll - log_likelihood;
+            log_likelihood = ll;
+            if gain.abs() < TOLERANCE * ll.abs() {
+                break;
+            }
+        }
+        components.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        Some(Self {
+            components,
+            log_likelihood,
+            iterations,
+        })
+    }
+}
+
+/// Insert size at fraction `p` of `(insert size, count)` bins totalling `total`.
+fn quantile(bins: &[(f64, f64)], total: f64, p: f64) -> f64 {
+    let mut accum = 0.0;
+    for (x, n) in bins {
+        accum += n;
+        if accum >= p * total {
+            return *x;
+        }
+    }
+    bins.last().map_or(0.0, |v| v.0)
+}
+
+impl Serialize for Component {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Component", 3)?;
+        state.serialize_field("weight", &round4(self.weight))?;
+        state.serialize_field("mean", &round2(self.mean))?;
+        state.serialize_field("sd", &round2(self.sd))?;
+        state.end()
+    }
+}
+
+impl Serialize for Mixture {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Mixture", 3)?;
+        state.serialize_field("components", &self.components)?;
+        state.serialize_field("log_likelihood", &round2(self.log_likelihood))?;
+        state.serialize_field("iterations", &self.iterations)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts of `n` pairs from a mixture, rounded per insert size.
+    fn counts(components: &[Component], n: f64, upper: usize) -> Vec<u32> {
+        (0..=upper)
+            .map(|x| {
+                let density: f64 = components.iter().map(|c| c.density(x as f64)).sum();
+                (n * density).round() as u32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fit_two_components() {
+        // Mono- and di-nucleosomes, the larger one first to check the sort.
+        let truth = [
+            Component {
+                weight: 0.3,
+                mean: 340.0,
+                sd: 25.0,
+            },
+            Component {
+                weight: 0.7,
+                mean: 167.0,
+                sd: 15.0,
+            },
+        ];
+        let fit = Mixture::fit(&counts(&truth, 1e6, 600), 2).unwrap();
+        assert_eq!(fit.components.len(), 2);
+        assert!(fit.components[0].mean < fit.components[1].mean);
+        for (c, t) in fit.components.iter().zip([truth[1], truth[0]].iter()) {
+            assert!((c.mean - t.mean).abs() < 0.5, "{:?}", c);
+            assert!((c.sd - t.sd).abs() < 0.5, "{:?}", c);
+            assert!((c.weight - t.weight).abs() < 0.005, "{:?}", c);
+        }
+        assert!(fit.iterations < MAX_ITERATIONS);
+        assert!(fit.log_likelihood.is_finite());
+    }
+
+    #[test]
+    fn nothing_to_fit() {
+        let one = [Component {
+            weight: 1.0,
+            mean: 200.0,
+            sd: 20.0,
+        }];
+        assert!(Mixture::fit(&counts(&one, 1e4, 400), 0).is_none());
+        assert!(Mixture::fit(&[], 2).is_none());
+        assert!(Mixture::fit(&[0; 500], 2).is_none());
+    }
+}
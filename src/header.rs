@@ -0,0 +1,101 @@
+/// Sam header text and reference dictionary of an input.
+#[derive(Clone, Default)]
+pub struct Header {
+    text: String,
+    // Reference names and lengths.
+    references: Vec<(String, u32)>,
+}
+
+/// Extensions of fasta files, compressed ones first.
+const FASTA_EXTENSIONS: &[&str] = &[
+    ".fa.gz",
+    ".fasta.gz",
+    ".fna.gz",
+    ".fa",
+    ".fasta",
+    ".fna",
+    ".fas",
+];
+
+/// Value of tag `tag` of a tab separated header line.
+fn tag<'a>(line: &'a str, tag: &str) -> Option<&'a str> {
+    line.split('\t')
+        .skip(1)
+        .find_map(|v| v.strip_prefix(tag)?.strip_prefix(':'))
+}
+
+/// File name of fasta path, uri or `key=path` argument `v` without directories and extension,
+/// `None` for other files.
+fn fasta_stem(v: &str) -> Option<&str> {
+    let name = v.rsplit(['/', '\\', '=']).next()?;
+    FASTA_EXTENSIONS
+        .iter()
+        .find_map(|ext| name.strip_suffix(ext))
+        .filter(|v| !v.is_empty())
+}
+
+impl Header {
+    /// Header of bam, its text and the reference dictionary of its binary part.
+    pub fn new(text: String, references: Vec<(String, u32)>) -> Self {
+        Self { text, references }
+    }
+
+    /// Add a header line of sam, a `@SQ` line adds a reference.
+    pub fn push_line(&mut self, line: &str) {
+        if line.starts_with("@SQ") {
+            if let Some(name) = tag(line, "SN") {
+                let length = tag(line, "LN").and_then(|v| v.parse().ok()).unwrap_or(0);
+                self.references.push((name.to_string(), length));
+            }
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+
+    /// Header text, sam header lines.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Reference names and lengths.
+    pub fn references(&self) -> &[(String, u32)] {
+        &self.references
+    }
+
+    /// Header lines of record type `kind`, e.g. `@RG`.
+    fn lines<'a>(&'a self, kind: &'a str) -> impl Iterator<Item = &'a str> {
+        self.text
+            .lines()
+            .filter(move |v| v.split('\t').next() == Some(kind))
+    }
+
+    /// Sort order of `@HD SO`, e.g. `coordinate`.
+    pub fn sort_order(&self) -> Option<&str> {
+        self.lines("@HD").find_map(|v| tag(v, "SO"))
+    }
+
+    /// Sample names of `@RG SM` in order, joined by commas when read groups differ.
+    pub fn sample_name(&self) -> Option<String> {
+        let mut names: Vec<&str> = Vec::new();
+        for v in self.lines("@RG").filter_map(|v| tag(v, "SM")) {
+            if !names.contains(&v) {
+                names.push(v);
+            }
+        }
+        (!names.is_empty()).then(|| names.join(","))
+    }
+
+    /// Reference genome from `@SQ AS` or the fasta of `@SQ UR`, else a fasta on a `@PG CL`
+    /// command line, e.g. of the aligner.
+    pub fn reference_genome(&self) -> Option<String> {
+        let sq = || self.lines("@SQ");
+        sq().find_map(|v| tag(v, "AS"))
+            .or_else(|| sq().find_map(|v| tag(v, "UR").and_then(fasta_stem)))
+            .or_else(|| {
+                self.lines("@PG")
+                    .filter_map(|v| tag(v, "CL"))
+                    .find_map(|v| v.split_whitespace().find_map(fasta_stem))
+            })
+            .map(String::from)
+    }
+}
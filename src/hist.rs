@@ -0,0 +1,1083 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::converge::EarlyStop;
+use crate::error::{Error, Result};
+use crate::gmm::Mixture;
+use crate::plot::Kernel;
+use crate::record::PairOrientation;
+use crate::summary::{
+    Alignments, AtacSummary, CfdnaSummary, Excluded, ExpectedSize, FragmentSummary, Summary,
+    TailSummary, Unqualified,
+};
+use crate::LibraryType;
+
+/// Histograms of one input, overall and split by groups.
+pub struct Histograms {
+    /// All qualified pairs.
+    pub all: InsertSizeHistogram,
+    /// Pairs of each reference, when counted per chromosome.
+    pub chromosomes: Vec<(String, InsertSizeHistogram)>,
+    /// Pairs of each read group, when counted per read group.
+    pub read_groups: Vec<(String, InsertSizeHistogram)>,
+    /// Pairs of each GC stratum of the counted read, in `GC_STRATA` order, when counted per GC.
+    pub gc_strata: Vec<(String, InsertSizeHistogram)>,
+    /// Pairs of each MAPQ bucket of the counted read, when counted by MAPQ.
+    pub mapq_buckets: Vec<(String, InsertSizeHistogram)>,
+    /// Pairs of each orientation, in `PairOrientation::ALL` order.
+    pub orientations: Vec<(PairOrientation, InsertSizeHistogram)>,
+    /// Fraction trimmed from each end for trimmed means.
+    pub trim: f64,
+    /// Extra percentiles to summarize, in percent.
+    pub percentiles: Vec<f64>,
+    /// Expected insert sizes, e.g. of amplicons, and bp around each counted as it.
+    pub expected_sizes: Vec<usize>,
+    pub expected_tolerance: usize,
+    /// Components of a Gaussian mixture fitted to qualified pairs.
+    pub fit_gmm: Option<usize>,
+    /// Summarize nucleosome peaks and short fragments of cfDNA.
+    pub cfdna: bool,
+    /// Summarize nucleosome-free and nucleosomal fragments of ATAC-seq.
+    pub atac: bool,
+    /// Qualified pairs with mates on different references.
+    pub discordant_interchrom: u64,
+    /// Qualified pairs with TLEN 0.
+    pub tlen_zero: u64,
+    /// Qualified pairs of other orientations than the library's.
+    pub wrong_orientation: u64,
+    /// Library type counted.
+    pub library_type: LibraryType,
+    /// Malformed records skipped in lenient mode.
+    pub records_skipped: u64,
+    /// Records read by kind of alignment.
+    pub alignments: Alignments,
+    /// Reads of counted mates failing include flags, by reason.
+    pub unqualified: Unqualified,
+    /// Counted pairs of known read length, and those with insert size below it.
+    pub sequenced_pairs: u64,
+    pub short_inserts: u64,
+    /// Estimated fragment sizes and inner distances, in fragment mode.
+    pub fragments: Option<Fragments>,
+    /// Pairs collapsed as unmarked duplicates.
+    pub duplicates: u64,
+    /// Insert sizes above upper, for the tail report.
+    pub tail: Option<Tail>,
+    /// Where reading stopped once estimates converged.
+    pub early_stop: Option<EarlyStop>,
+    /// Sample name from `@RG SM` of the header.
+    pub sample_name: Option<String>,
+    /// Reference genome from `@SQ` or `@PG` of the header.
+    pub reference_genome: Option<String>,
+}
+
+impl Histograms {
+    /// Histograms of all pairs only, e.g. merged from saved ones.
+    pub fn new(all: InsertSizeHistogram) -> Self {
+        Self {
+            all,
+            chromosomes: Vec::new(),
+            read_groups: Vec::new(),
+            gc_strata: Vec::new(),
+            mapq_buckets: Vec::new(),
+            orientations: Vec::new(),
+            trim: DEFAULT_TRIM,
+            percentiles: Vec::new(),
+            expected_sizes: Vec::new(),
+            expected_tolerance: DEFAULT_EXPECTED_TOLERANCE,
+            fit_gmm: None,
+            cfdna: false,
+            atac: false,
+            discordant_interchrom: 0,
+            tlen_zero: 0,
+            wrong_orientation: 0,
+            library_type: LibraryType::PairedEnd,
+            records_skipped: 0,
+            alignments: Alignments::default(),
+            unqualified: Unqualified::default(),
+            sequenced_pairs: 0,
+            short_inserts: 0,
+            fragments: None,
+            duplicates: 0,
+            tail: None,
+            early_stop: None,
+            sample_name: None,
+            reference_genome: None,
+        }
+    }
+
+    /// Set upper of all histograms, see `InsertSizeHistogram::set_upper`.
+    pub fn set_upper(&mut self, upper: usize) {
+        // Counts above upper are only kept until it is settled.
+        if let Some(v) = &mut self.tail {
+            *v = Tail::default();
+            for (k, n) in self.all.all_bins().filter(|(k, _)| *k > upper) {
+                v.add_count(k, n);
+            }
+        }
+        self.all.set_upper(upper);
+        let groups = self
+            .chromosomes
+            .iter_mut()
+            .chain(&mut self.read_groups)
+            .chain(&mut self.gc_strata)
+            .chain(&mut self.mapq_buckets);
+        for (_, hist) in groups {
+            hist.set_upper(upper);
+        }
+        for (_, hist) in &mut self.orientations {
+            hist.set_upper(upper);
+        }
+        if let Some(v) = &mut self.fragments {
+            v.sizes.set_upper(upper + 2 * v.adapter_length);
+        }
+    }
+
+    /// Overall summary with summaries of groups.
+    pub fn summary(&self) -> Summary {
+        let summarize = |hist: &InsertSizeHistogram| {
+            let mut sum = hist.summary_trimmed(self.trim);
+            sum.percentiles = self
+                .percentiles
+                .iter()
+                .map(|v| (*v, hist.percentile(*v)))
+                .collect();
+            sum
+        };
+        let mut sum = summarize(&self.all);
+        sum.excluded = Excluded {
+            discordant_interchrom: self.discordant_interchrom,
+            tlen_zero: self.tlen_zero,
+            duplicates: self.duplicates,
+            wrong_orientation: self.wrong_orientation,
+            above_upper: (self.all.all_count - self.all.count()) as u64,
+        };
+        sum.records_skipped = self.records_skipped;
+        sum.sample_name = self.sample_name.clone();
+        sum.reference_genome = self.reference_genome.clone();
+        sum.early_stop = self.early_stop;
+        sum.mixture = self
+            .fit_gmm
+            .and_then(|k| Mixture::fit(self.all.counts(), k));
+        sum.cfdna = self.cfdna.then(|| self.all.cfdna());
+        sum.atac = self.atac.then(|| self.all.atac());
+        sum.alignments = self.alignments;
+        sum.unqualified = self.unqualified;
+        sum.short_inserts = (self.sequenced_pairs > 0)
+            .then(|| self.short_inserts as f64 / self.sequenced_pairs as f64);
+        if self.library_type == LibraryType::MatePair {
+            // Orientation histograms hold all pairs of known insert size.
+            let total: u32 = self.orientations.iter().map(|(_, v)| v.all_count).sum();
+            let fr = self.orientations[PairOrientation::FR as usize].1.all_count;
+            sum.fr_contamination = Some(if total > 0 {
+                fr as f64 / total as f64
+            } else {
+                0.0
+            });
+        }
+        sum.fragment = self.fragments.as_ref().map(|v| {
+            let sizes = v.sizes.summary_trimmed(self.trim);
+            FragmentSummary {
+                adapter_length: v.adapter_length,
+                count: sizes.count,
+                mean: sizes.mean,
+                median: sizes.q2,
+                sd: sizes.std,
+                inner_mean: (v.inner.weight > 0.0).then_some(v.inner.mean),
+                inner_sd: (v.inner.weight > 0.0).then(|| v.inner.std()),
+            }
+        });
+        sum.tail = self.tail.as_ref().map(|v| v.summary(self.all.upper));
+        let count = self.all.count();
+        sum.expected = self
+            .expected_sizes
+            .iter()
+            .map(|size| {
+                let tolerance = self.expected_tolerance;
+                let near: u32 = (size.saturating_sub(tolerance)..=size + tolerance)
+                    .map(|k| self.all.get(k))
+                    .sum();
+                let fraction = if count > 0 {
+                    near as f64 / count as f64
+                } else {
+                    0.0
+                };
+                ExpectedSize {
+                    size: *size,
+                    count: near,
+                    fraction,
+                    missing: fraction * (self.expected_sizes.len() as f64) < MISSING_SHARE,
+                }
+            })
+            .collect();
+        sum.peaks = self.all.peaks();
+        // A short peak beside one of the library, however high either is.
+        sum.short_peak = sum.peaks.iter().any(|k| *k < SHORT_PEAK_LIMIT)
+            && sum.peaks.iter().any(|k| *k >= SHORT_PEAK_LIMIT);
+        sum.chromosomes = self
+            .chromosomes
+            .iter()
+            .map(|(k, v)| (k.clone(), summarize(v)))
+            .collect();
+        sum.read_groups = self
+            .read_groups
+            .iter()
+            .map(|(k, v)| (k.clone(), summarize(v)))
+            .collect();
+        sum.gc_strata = self
+            .gc_strata
+            .iter()
+            .map(|(k, v)| (k.clone(), summarize(v)))
+            .collect();
+        sum.mapq_buckets = self
+            .mapq_buckets
+            .iter()
+            .map(|(k, v)| (k.clone(), summarize(v)))
+            .collect();
+        sum.orientations = self
+            .orientations
+            .iter()
+            .map(|(k, v)| (k.name().to_string(), summarize(v)))
+            .collect();
+        sum
+    }
+}
+
+/// Median of sorted `(value, count)` bins the way htsjdk does, mean of the middle two.
+pub(crate) fn bins_median<I: Iterator<Item = (f64, u64)>>(bins: I, total: u64) -> f64 {
+    let (mid_low, mid_high) = if total.is_multiple_of(2) {
+        (total / 2, total / 2 + 1)
+    } else {
+        (total / 2 + 1, total / 2 + 1)
+    };
+    let mut accum = 0;
+    let mut low = None;
+    for (k, v) in bins {
+        accum += v;
+        if low.is_none() && accum >= mid_low {
+            low = Some(k);
+        }
+        if accum >= mid_high {
+            return (low.unwrap_or(k) + k) / 2.0;
+        }
+    }
+    low.unwrap_or(0.0)
+}
+
+/// Median absolute deviation of `(value, count)` bins from `median`.
+pub(crate) fn bins_mad<I: Iterator<Item = (f64, u64)>>(bins: I, median: f64, total: u64) -> f64 {
+    let mut deviations: Vec<(f64, u64)> = bins.map(|(k, v)| ((k - median).abs(), v)).collect();
+    deviations.sort_by(|a, b| a.0.total_cmp(&b.0));
+    bins_median(deviations.into_iter(), total)
+}
+
+/// Percents of pairs for width-of-percent metrics.
+pub const WIDTH_PERCENTS: [u32; 11] = [10, 20, 30, 40, 50, 60, 70, 80, 90, 95, 99];
+
+/// Picard style `WIDTH_OF_XX_PERCENT`, width of the window centered at `median` holding each
+/// of `WIDTH_PERCENTS` of pairs in non-empty `(insert size, count)` bins.
+pub(crate) fn widths_of_percent<I: Iterator<Item = (usize, u64)>>(
+    bins: I,
+    median: f64,
+) -> Vec<usize> {
+    let mut widths = vec![0usize; WIDTH_PERCENTS.len()];
+    // The window grows one size each side of the median, a bin joins at its distance.
+    let center = median.max(0.0) as usize;
+    let mut bins: Vec<(usize, u64)> = bins.map(|(k, v)| (k.abs_diff(center), v)).collect();
+    bins.sort_by_key(|v| v.0);
+    let total: u64 = bins.iter().map(|v| v.1).sum();
+    let mut covered = 0u64;
+    for (i, (distance, v)) in bins.iter().enumerate() {
+        covered += v;
+        if bins.get(i + 1).is_some_and(|next| next.0 == *distance) {
+            continue;
+        }
+        let fraction = covered as f64 / total as f64;
+        for (width, pct) in widths.iter_mut().zip(WIDTH_PERCENTS.iter()) {
+            if *width == 0 && fraction >= *pct as f64 / 100.0 {
+                *width = distance * 2 + 1;
+            }
+        }
+        if widths.iter().all(|v| *v > 0) {
+            break;
+        }
+    }
+    widths
+}
+
+/// Bp around an expected size counted as it by default.
+pub const DEFAULT_EXPECTED_TOLERANCE: usize = 10;
+/// Expected sizes with less than this share of an even split of pairs are missing.
+const MISSING_SHARE: f64 = 0.1;
+
+/// Window in bp of the gaussian smoothing before peak detection.
+const PEAK_SMOOTH_WINDOW: usize = 21;
+/// Peaks less prominent than this fraction of the highest one are noise.
+const MIN_PEAK_PROMINENCE: f64 = 0.05;
+/// Secondary peaks below this insert size are likely adapter dimers or short fragments.
+pub const SHORT_PEAK_LIMIT: usize = 150;
+
+/// Inclusive windows in bp of mono-, di- and tri-nucleosome peaks of cfDNA.
+pub const NUCLEOSOME_WINDOWS: [(usize, usize); 3] = [(120, 250), (251, 450), (451, 650)];
+/// cfDNA fragments below this are short, enriched for tumor derived DNA.
+pub const CFDNA_SHORT_LIMIT: usize = 150;
+
+/// ATAC-seq fragments below this are nucleosome-free.
+pub const NUCLEOSOME_FREE_LIMIT: usize = 100;
+/// Inclusive windows in bp of mono- and di-nucleosome fragments of ATAC-seq, as ATACseqQC.
+pub const ATAC_NUCLEOSOME_WINDOWS: [(usize, usize); 2] = [(180, 247), (315, 473)];
+/// Inclusive window in bp searched for the helical periodicity of ATAC-seq fragments.
+const PERIODICITY_WINDOW: (usize, usize) = (50, 400);
+/// Periods in bp counted as the ~10.5 bp helical pitch of DNA.
+const HELICAL_PERIODS: (f64, f64) = (10.0, 11.0);
+
+/// Positions of peaks in `values`, local maxima standing out by `MIN_PEAK_PROMINENCE` of the
+/// highest value from the lowest point before a higher value on either side.
+fn find_peaks(values: &[f64]) -> Vec<usize> {
+    let highest = values.iter().copied().fold(0.0, f64::max);
+    if highest <= 0.0 {
+        return Vec::new();
+    }
+    let mut peaks = Vec::new();
+    let mut i = 1;
+    while i + 1 < values.len() {
+        if values[i] <= values[i - 1] {
+            i += 1;
+            continue;
+        }
+        // Plateau top is at its middle.
+        let mut end = i;
+        while end + 1 < values.len() && values[end + 1] == values[i] {
+            end += 1;
+        }
+        if end + 1 < values.len() && values[end + 1] < values[i] {
+            let height = values[i];
+            let left = values[..i]
+                .iter()
+                .rev()
+                .take_while(|v| **v <= height)
+                .copied()
+                .fold(height, f64::min);
+            let right = values[end + 1..]
+                .iter()
+                .take_while(|v| **v <= height)
+                .copied()
+                .fold(height, f64::min);
+            if height - f64::max(left, right) >= highest * MIN_PEAK_PROMINENCE {
+                peaks.push((i + end) / 2);
+            }
+        }
+        i = end + 1;
+    }
+    peaks
+}
+
+/// Fraction trimmed from each end for the trimmed mean by default.
+pub const DEFAULT_TRIM: f64 = 0.05;
+
+/// Insert sizes below this are counted in a dense array, larger ones in a sorted map.
+const DENSE_LIMIT: usize = 1 << 16;
+
+/// GC strata of reads by GC fraction, 20% wide.
+pub const GC_STRATA: [&str; 5] = [
+    "GC 0-20%",
+    "GC 20-40%",
+    "GC 40-60%",
+    "GC 60-80%",
+    "GC 80-100%",
+];
+
+/// Index into `GC_STRATA` of GC fraction.
+pub fn gc_stratum(gc: f64) -> usize {
+    ((gc * GC_STRATA.len() as f64) as usize).min(GC_STRATA.len() - 1)
+}
+
+/// Names of MAPQ buckets from ascending lower bounds, e.g. `MAPQ 1-29` of 1 before 30 and
+/// `MAPQ 60+` of the last.
+pub fn mapq_bucket_names(bounds: &[u8]) -> Vec<String> {
+    bounds
+        .iter()
+        .enumerate()
+        .map(|(k, v)| match bounds.get(k + 1) {
+            Some(next) if *next == v + 1 => format!("MAPQ {}", v),
+            Some(next) => format!("MAPQ {}-{}", v, next - 1),
+            None if *v == u8::MAX => format!("MAPQ {}", v),
+            None => format!("MAPQ {}+", v),
+        })
+        .collect()
+}
+
+/// Index into ascending lower `bounds` of the MAPQ bucket of `mapq`, none below the first.
+pub fn mapq_bucket(bounds: &[u8], mapq: u8) -> Option<usize> {
+    bounds.iter().rposition(|v| *v <= mapq)
+}
+
+/// Physical fragment sizes estimated from insert sizes, library molecules with adapters
+/// ligated at both ends, and inner distances, insert size less both reads.
+pub struct Fragments {
+    /// Adapter bases at each end of a fragment.
+    pub adapter_length: usize,
+    /// Insert size plus adapters at both ends.
+    pub sizes: InsertSizeHistogram,
+    // Unsequenced bases between mates, negative for overlapping mates.
+    inner: Welford,
+}
+
+impl Fragments {
+    /// Fragments of `adapter_length` adapters at each end, sizes up to insert size `upper`
+    /// plus adapters, or kept above it too with `overflow`.
+    pub fn new(adapter_length: usize, upper: usize, overflow: bool) -> Self {
+        let upper = upper + 2 * adapter_length;
+        Self {
+            adapter_length,
+            sizes: if overflow {
+                InsertSizeHistogram::with_overflow(upper)
+            } else {
+                InsertSizeHistogram::new(upper)
+            },
+            inner: Welford::default(),
+        }
+    }
+
+    /// Add a pair of `insert` size and reads of `read_length`, mates are taken as long, 0
+    /// leaves the pair out of inner distances.
+    pub fn add(&mut self, insert: usize, read_length: usize) {
+        self.sizes.add(insert + 2 * self.adapter_length);
+        if read_length > 0 {
+            self.inner
+                .add(insert as f64 - 2.0 * read_length as f64, 1.0);
+        }
+    }
+}
+
+/// Insert sizes above the upper bound, kept sparsely to report the long tail, e.g. of SV
+/// oriented or linked-read libraries.
+#[derive(Default)]
+pub struct Tail {
+    sizes: BTreeMap<usize, u32>,
+}
+
+/// Percentile of the tail reported.
+const TAIL_PERCENTILE: f64 = 99.0;
+
+impl Tail {
+    /// Add insert size of one pair above upper.
+    pub fn add(&mut self, tlen: usize) {
+        self.add_count(tlen, 1);
+    }
+
+    fn add_count(&mut self, tlen: usize, count: u32) {
+        *self.sizes.entry(tlen).or_insert(0) += count;
+    }
+
+    /// Count, mean, 99th percentile and largest of sizes above `upper`.
+    pub fn summary(&self, upper: usize) -> TailSummary {
+        let count: u64 = self.sizes.values().map(|v| *v as u64).sum();
+        let total: f64 = self.sizes.iter().map(|(k, v)| *k as f64 * *v as f64).sum();
+        // Same rule as `InsertSizeHistogram::percentile`.
+        let index = (count as f64 * TAIL_PERCENTILE / 100.0) as u64;
+        let mut accum = 0u64;
+        let mut p99 = 0;
+        for (k, v) in &self.sizes {
+            accum += *v as u64;
+            p99 = *k;
+            if accum > index {
+                break;
+            }
+        }
+        TailSummary {
+            upper,
+            count,
+            mean: if count > 0 { total / count as f64 } else { 0.0 },
+            p99,
+            max: self.sizes.keys().next_back().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// Running mean and variance by Welford's algorithm, stable in one pass.
+#[derive(Clone, Copy, Debug, Default)]
+struct Welford {
+    weight: f64,
+    mean: f64,
+    // Sum of squared deviations from the running mean.
+    m2: f64,
+}
+
+impl Welford {
+    /// Add value `x` seen `weight` times.
+    fn add(&mut self, x: f64, weight: f64) {
+        self.weight += weight;
+        let delta = x - self.mean;
+        self.mean += delta * weight / self.weight;
+        self.m2 += delta * (x - self.mean) * weight;
+    }
+
+    /// Combine with moments of other values, by Chan's parallel algorithm.
+    fn merge(&mut self, other: &Welford) {
+        let weight = self.weight + other.weight;
+        if weight == 0.0 {
+            return;
+        }
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.weight / weight;
+        self.m2 += other.m2 + delta * delta * self.weight * other.weight / weight;
+        self.weight = weight;
+    }
+
+    /// Population standard deviation, 0 when empty.
+    fn std(&self) -> f64 {
+        if self.weight > 0.0 {
+            (self.m2 / self.weight).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Histogram of insert sizes up to an upper bound, larger ones are only counted in total.
+///
+/// Sizes up to `DENSE_LIMIT` are kept in an array and larger ones sparsely, so a big upper
+/// costs memory only for sizes actually seen.
+#[derive(Clone)]
+pub struct InsertSizeHistogram {
+    dense: Vec<u32>,
+    sparse: BTreeMap<usize, u32>,
+    upper: usize,
+    // Keep counts above upper in `sparse` until the upper is settled.
+    overflow: bool,
+    // Pair count all.
+    all_count: u32,
+    // Insert size mean and variance in all, streamed.
+    all_moments: Welford,
+    // Smallest and largest insert size in all.
+    all_min: usize,
+    all_max: usize,
+}
+
+impl InsertSizeHistogram {
+    /// Empty histogram recording insert sizes in `0..=upper`.
+    pub fn new(upper: usize) -> Self {
+        Self {
+            dense: vec![0u32; usize::min(upper, DENSE_LIMIT) + 1],
+            sparse: BTreeMap::new(),
+            upper,
+            overflow: false,
+            all_count: 0,
+            all_moments: Welford::default(),
+            all_min: usize::MAX,
+            all_max: 0,
+        }
+    }
+
+    /// Empty histogram also keeping counts above `upper`, to settle the upper later.
+    pub fn with_overflow(upper: usize) -> Self {
+        Self {
+            overflow: true,
+            ..Self::new(upper)
+        }
+    }
+
+    /// Smallest insert size covering `fraction` of pairs, counts above upper included.
+    pub fn covering_upper(&self, fraction: f64) -> usize {
+        let bins = self.all_bins();
+        let total: u64 = bins.clone().map(|(_, v)| v as u64).sum();
+        if total == 0 {
+            return self.upper;
+        }
+        let target = (total as f64 * fraction).ceil() as u64;
+        let mut accum = 0u64;
+        let mut last = 0;
+        for (k, v) in bins {
+            accum += v as u64;
+            last = k;
+            if accum >= target {
+                break;
+            }
+        }
+        last
+    }
+
+    /// Record insert sizes in `0..=upper` from now, kept counts above it are moved in and
+    /// the rest only stay in total.
+    pub fn set_upper(&mut self, upper: usize) {
+        self.dense.resize(usize::min(upper, DENSE_LIMIT) + 1, 0);
+        let sparse = std::mem::take(&mut self.sparse);
+        for (k, v) in sparse.into_iter().filter(|(k, _)| *k <= upper) {
+            match self.dense.get_mut(k) {
+                Some(count) => *count += v,
+                None => {
+                    self.sparse.insert(k, v);
+                }
+            }
+        }
+        self.upper = upper;
+        self.overflow = false;
+    }
+
+    /// Maximum insert size recorded.
+    pub fn upper(&self) -> usize {
+        self.upper
+    }
+
+    /// Add pairs of `other`, those above upper only to total count, mean, sd, smallest and
+    /// largest insert size.
+    pub fn merge(&mut self, other: &InsertSizeHistogram) {
+        let upper = self.upper;
+        for (k, v) in other.bins().filter(|(k, _)| *k <= upper) {
+            match self.dense.get_mut(k) {
+                Some(count) => *count += v,
+                None => *self.sparse.entry(k).or_insert(0) += v,
+            }
+        }
+        self.all_count += other.all_count;
+        self.all_moments.merge(&other.all_moments);
+        self.all_min = usize::min(self.all_min, other.all_min);
+        self.all_max = usize::max(self.all_max, other.all_max);
+    }
+
+    /// Set count, mean, sd, smallest and largest insert size of all pairs, above upper
+    /// included, of a saved histogram whose bins within upper are added.
+    pub fn set_totals(&mut self, count: u32, mean: f64, sd: f64, min: usize, max: usize) {
+        self.all_count = count;
+        self.all_moments = Welford {
+            weight: count as f64,
+            mean,
+            m2: sd * sd * count as f64,
+        };
+        self.all_min = if count > 0 { min } else { usize::MAX };
+        self.all_max = max;
+    }
+
+    /// Add insert size of one pair.
+    pub fn add(&mut self, tlen: usize) {
+        self.add_count(tlen, 1);
+    }
+
+    /// Add insert size of `count` pairs, e.g. of a saved histogram.
+    pub fn add_count(&mut self, tlen: usize, count: u32) {
+        if count == 0 {
+            return;
+        }
+        self.all_moments.add(tlen as f64, count as f64);
+        self.all_count += count;
+        self.all_min = usize::min(self.all_min, tlen);
+        self.all_max = usize::max(self.all_max, tlen);
+        if tlen > self.upper && !self.overflow {
+            return;
+        }
+        match self.dense.get_mut(tlen) {
+            Some(v) => *v += count,
+            None => *self.sparse.entry(tlen).or_insert(0) += count,
+        }
+    }
+
+    /// Non-empty `(insert size, count)` bins in order, above upper included.
+    fn all_bins(&self) -> impl Iterator<Item = (usize, u32)> + Clone + '_ {
+        let dense = self.dense.iter().copied().enumerate();
+        let sparse = self.sparse.iter().map(|(k, v)| (*k, *v));
+        dense.chain(sparse).filter(|(_, v)| *v > 0)
+    }
+
+    /// Non-empty `(insert size, count)` bins within upper, in order.
+    pub fn bins(&self) -> impl Iterator<Item = (usize, u32)> + Clone + '_ {
+        let upper = self.upper;
+        self.all_bins().take_while(move |(k, _)| *k <= upper)
+    }
+
+    /// Pair count of insert size `k`, 0 above upper.
+    pub fn get(&self, k: usize) -> u32 {
+        if k > self.upper {
+            return 0;
+        }
+        match self.dense.get(k) {
+            Some(v) => *v,
+            None => self.sparse.get(&k).copied().unwrap_or(0),
+        }
+    }
+
+    /// Pair count of each insert size from 0 up to upper, or up to `DENSE_LIMIT` for a larger
+    /// upper, sparse sizes are in `bins` and `rows`.
+    pub fn counts(&self) -> &[u32] {
+        &self.dense
+    }
+
+    pub fn into_counts(self) -> Vec<u32> {
+        self.dense
+    }
+
+    /// Dense bins including empty ones, then non-empty sparse bins, as `(insert size, count)`.
+    pub fn rows(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        let sparse = self.sparse.range(..=self.upper).map(|(k, v)| (*k, *v));
+        self.dense.iter().copied().enumerate().chain(sparse)
+    }
+
+    /// Pair count within upper.
+    pub fn count(&self) -> u32 {
+        self.bins().map(|(_, v)| v).sum()
+    }
+
+    /// Insert sizes of modes, peaks of the smoothed histogram within upper.
+    pub fn peaks(&self) -> Vec<usize> {
+        find_peaks(&self.smoothed())
+    }
+
+    /// Dense counts within upper, smoothed for peak detection.
+    fn smoothed(&self) -> Vec<f64> {
+        let counts: Vec<f64> = self
+            .dense
+            .iter()
+            .take(self.upper + 1)
+            .map(|v| *v as f64)
+            .collect();
+        Kernel::Gaussian.smooth(&counts, PEAK_SMOOTH_WINDOW)
+    }
+
+    /// Fractions of nucleosome-free, mono- and di-nucleosome pairs, their ratios, and the
+    /// helical periodicity score.
+    pub fn atac(&self) -> AtacSummary {
+        let count = self.count();
+        let fraction = |low: usize, high: usize| {
+            let n: u32 = (low..=high).map(|k| self.get(k)).sum();
+            if count > 0 {
+                n as f64 / count as f64
+            } else {
+                0.0
+            }
+        };
+        let nucleosome_free = fraction(0, NUCLEOSOME_FREE_LIMIT - 1);
+        let [(mono_low, mono_high), (di_low, di_high)] = ATAC_NUCLEOSOME_WINDOWS;
+        let mono = fraction(mono_low, mono_high);
+        let di = fraction(di_low, di_high);
+        AtacSummary {
+            nucleosome_free,
+            mono,
+            di,
+            free_mono_ratio: (mono > 0.0).then(|| nucleosome_free / mono),
+            mono_di_ratio: (di > 0.0).then(|| mono / di),
+            periodicity: self.periodicity(),
+        }
+    }
+
+    /// Share of the power spectrum of counts relative to the smoothed histogram in
+    /// `PERIODICITY_WINDOW` at `HELICAL_PERIODS`, near 0 for a featureless histogram, `None`
+    /// when upper cuts the window short or it holds no pairs.
+    fn periodicity(&self) -> Option<f64> {
+        let (low, high) = PERIODICITY_WINDOW;
+        if self.upper < high {
+            return None;
+        }
+        let smoothed = self.smoothed();
+        // Relative deviation from the smoothed trend, ripples of any height weigh the same.
+        let ripples: Vec<f64> = (low..=high)
+            .map(|k| {
+                if smoothed[k] > 0.0 {
+                    self.get(k) as f64 / smoothed[k] - 1.0
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let n = ripples.len();
+        let (mut helical, mut total) = (0.0, 0.0);
+        for j in 1..=n / 2 {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (k, v) in ripples.iter().enumerate() {
+                let angle = 2.0 * std::f64::consts::PI * (j * k) as f64 / n as f64;
+                re += v * angle.cos();
+                im -= v * angle.sin();
+            }
+            let power = re * re + im * im;
+            total += power;
+            let period = n as f64 / j as f64;
+            if (HELICAL_PERIODS.0..=HELICAL_PERIODS.1).contains(&period) {
+                helical += power;
+            }
+        }
+        (total > 0.0).then(|| helical / total)
+    }
+
+    /// Highest points of the smoothed histogram in `NUCLEOSOME_WINDOWS`, their heights
+    /// relative to the mono-nucleosome one, and the fraction of pairs below
+    /// `CFDNA_SHORT_LIMIT`.
+    pub fn cfdna(&self) -> CfdnaSummary {
+        let smoothed = self.smoothed();
+        let peaks: Vec<Option<(usize, f64)>> = NUCLEOSOME_WINDOWS
+            .iter()
+            .map(|(low, high)| {
+                smoothed
+                    .iter()
+                    .enumerate()
+                    .take(high + 1)
+                    .skip(*low)
+                    .filter(|(_, v)| **v > 0.0)
+                    // Lowest size of the highest point.
+                    .fold(None, |top: Option<(usize, f64)>, (k, v)| match top {
+                        Some(t) if t.1 >= *v => Some(t),
+                        _ => Some((k, *v)),
+                    })
+            })
+            .collect();
+        let ratio = |peak: Option<(usize, f64)>| Some(peak?.1 / peaks[0]?.1);
+        let count = self.count();
+        let short: u32 = (0..CFDNA_SHORT_LIMIT).map(|k| self.get(k)).sum();
+        CfdnaSummary {
+            mono_peak: peaks[0].map(|v| v.0),
+            di_peak: peaks[1].map(|v| v.0),
+            tri_peak: peaks[2].map(|v| v.0),
+            di_mono_ratio: ratio(peaks[1]),
+            tri_mono_ratio: ratio(peaks[2]),
+            short_fraction: if count > 0 {
+                short as f64 / count as f64
+            } else {
+                0.0
+            },
+        }
+    }
+
+    /// Insert size at percentile `p` (0 to 100), by the same rule as quartiles.
+    pub fn percentile(&self, p: f64) -> usize {
+        let index = (self.count() as f64 * p / 100.0) as u32;
+        let mut accum = 0u32;
+        let mut last = 0;
+        for (k, v) in self.bins() {
+            accum += v;
+            last = k;
+            if accum > index {
+                break;
+            }
+        }
+        last
+    }
+
+    /// Mean after dropping `trim` fraction of pairs from each end.
+    pub fn trimmed_mean(&self, trim: f64) -> f64 {
+        let count = self.count() as f64;
+        let (low, high) = (count * trim, count * (1.0 - trim));
+        let (mut weight, mut total) = (0f64, 0f64);
+        let mut accum = 0f64;
+        for (k, v) in self.bins() {
+            let start = accum;
+            accum += v as f64;
+            // Part of this bin inside the kept rank range.
+            let w = f64::min(accum, high) - f64::max(start, low);
+            if w > 0.0 {
+                weight += w;
+                total += w * k as f64;
+            }
+        }
+        total / weight
+    }
+
+    /// Calculate mean, quantiles and std, trimmed mean with `DEFAULT_TRIM`.
+    pub fn summary(&self) -> Summary {
+        self.summary_trimmed(DEFAULT_TRIM)
+    }
+
+    /// Calculate mean, quantiles and std, trimmed mean with `trim`.
+    pub fn summary_trimmed(&self, trim: f64) -> Summary {
+        let mut sum = Summary {
+            all_count: self.all_count,
+            all_mean: self.all_moments.mean,
+            all_std: self.all_moments.std(),
+            count: self.count(),
+            min: if self.all_count > 0 { self.all_min } else { 0 },
+            max: self.all_max,
+            ..Default::default()
+        };
+        let bins = self.bins();
+        sum.mean = bins
+            .clone()
+            .map(|(k, v)| (k as f64) * (v as f64))
+            .sum::<f64>()
+            / sum.count as f64;
+
+        let mut quantiles = {
+            let tmp = sum.count as f64;
+            vec![
+                ((tmp * 0.25f64) as u32, 0usize),
+                ((tmp * 0.5f64) as u32, 0usize),
+                ((tmp * 0.75f64) as u32, 0usize),
+            ]
+        };
+
+        let mut ri = 0usize;
+        let mut accum: u32 = 0;
+        for (k, v) in bins.clone() {
+            accum += v;
+            // One bin may hold several quantiles.
+            while ri < quantiles.len() && accum > quantiles[ri].0 {
+                quantiles[ri].1 = k;
+                ri += 1;
+            }
+            if ri == quantiles.len() {
+                break;
+            }
+        }
+        let mut moments = Welford::default();
+        for (k, v) in bins.clone() {
+            moments.add(k as f64, v as f64);
+        }
+        sum.std = moments.std();
+        // Shape from second to fourth central moments.
+        let (mut m2, mut m3, mut m4) = (0.0, 0.0, 0.0);
+        for (k, v) in bins.clone() {
+            let d = k as f64 - sum.mean;
+            let v = v as f64 / sum.count as f64;
+            m2 += v * d * d;
+            m3 += v * d * d * d;
+            m4 += v * d * d * d * d;
+        }
+        if m2 > 0.0 {
+            sum.skewness = m3 / m2.powf(1.5);
+            sum.kurtosis = m4 / (m2 * m2) - 3.0;
+        }
+        sum.q1 = quantiles[0].1;
+        sum.q2 = quantiles[1].1;
+        sum.q3 = quantiles[2].1;
+        sum.median = bins_median(
+            bins.clone().map(|(k, v)| (k as f64, v as u64)),
+            sum.count as u64,
+        );
+        sum.mad = bins_mad(
+            bins.clone().map(|(k, v)| (k as f64, v as u64)),
+            sum.median,
+            sum.count as u64,
+        );
+        sum.iqr = sum.q3 - sum.q1;
+        sum.trimmed_mean = self.trimmed_mean(trim);
+        let widths = widths_of_percent(bins.clone().map(|(k, v)| (k, v as u64)), sum.median);
+        sum.widths = WIDTH_PERCENTS.iter().copied().zip(widths).collect();
+        // First insert size with highest count.
+        sum.mode = bins
+            .fold((0, 0), |a, (k, v)| if v > a.1 { (k, v) } else { a })
+            .0;
+        sum
+    }
+
+    /// Write whole state in little endian binary, for checkpoints.
+    pub(crate) fn write_state<W: Write>(&self, w: &mut W) -> Result<()> {
+        w.write_u64::<LittleEndian>(self.upper as u64)?;
+        w.write_u8(self.overflow as u8)?;
+        w.write_u32::<LittleEndian>(self.all_count)?;
+        w.write_f64::<LittleEndian>(self.all_moments.weight)?;
+        w.write_f64::<LittleEndian>(self.all_moments.mean)?;
+        w.write_f64::<LittleEndian>(self.all_moments.m2)?;
+        w.write_u64::<LittleEndian>(self.all_min as u64)?;
+        w.write_u64::<LittleEndian>(self.all_max as u64)?;
+        w.write_u64::<LittleEndian>(self.dense.len() as u64)?;
+        for v in &self.dense {
+            w.write_u32::<LittleEndian>(*v)?;
+        }
+        w.write_u64::<LittleEndian>(self.sparse.len() as u64)?;
+        for (k, v) in &self.sparse {
+            w.write_u64::<LittleEndian>(*k as u64)?;
+            w.write_u32::<LittleEndian>(*v)?;
+        }
+        Ok(())
+    }
+
+    /// Read state written by `write_state`.
+    pub(crate) fn read_state<R: Read>(r: &mut R) -> Result<Self> {
+        let upper = r.read_u64::<LittleEndian>()? as usize;
+        let mut hist = Self::new(upper);
+        hist.overflow = r.read_u8()? != 0;
+        hist.all_count = r.read_u32::<LittleEndian>()?;
+        hist.all_moments = Welford {
+            weight: r.read_f64::<LittleEndian>()?,
+            mean: r.read_f64::<LittleEndian>()?,
+            m2: r.read_f64::<LittleEndian>()?,
+        };
+        hist.all_min = r.read_u64::<LittleEndian>()? as usize;
+        hist.all_max = r.read_u64::<LittleEndian>()? as usize;
+        if r.read_u64::<LittleEndian>()? != hist.dense.len() as u64 {
+            return Err(Error::Malformed("Wrong histogram size.".to_string()));
+        }
+        for v in hist.dense.iter_mut() {
+            *v = r.read_u32::<LittleEndian>()?;
+        }
+        for _ in 0..r.read_u64::<LittleEndian>()? {
+            let k = r.read_u64::<LittleEndian>()? as usize;
+            hist.sparse.insert(k, r.read_u32::<LittleEndian>()?);
+        }
+        Ok(hist)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Port of the width loop of Picard `InsertSizeMetricsCollector`, the window grows from the
+    /// median one size each side, truncating a half median, while inside the histogram.
+    fn picard_widths(bins: &BTreeMap<usize, u64>, median: f64) -> Vec<usize> {
+        let total = bins.values().sum::<u64>() as f64;
+        let (min, max) = (
+            *bins.keys().next().unwrap() as f64,
+            *bins.keys().last().unwrap() as f64,
+        );
+        let count = |v: f64| bins.get(&(v as usize)).copied().unwrap_or(0) as f64;
+        let mut widths = vec![0usize; WIDTH_PERCENTS.len()];
+        let (mut low, mut high, mut covered) = (median, median, 0.0);
+        while low >= min || high <= max {
+            covered += count(low);
+            if low != high {
+                covered += count(high);
+            }
+            let distance = (high - low) as usize + 1;
+            for (width, pct) in widths.iter_mut().zip(WIDTH_PERCENTS.iter()) {
+                if covered / total >= *pct as f64 / 100.0 && *width == 0 {
+                    *width = distance;
+                }
+            }
+            low -= 1.0;
+            high += 1.0;
+        }
+        widths
+    }
+
+    /// Skewed histogram with gaps, counts from a fixed linear congruential sequence.
+    fn skewed() -> BTreeMap<usize, u64> {
+        let mut state = 12345u64;
+        (60..600)
+            .filter_map(|k| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                let noise = state >> 60;
+                let shape = (1000.0 * (-((k as f64 - 180.0) / 60.0).powi(2)).exp()) as u64;
+                (k % 7 != 0).then(|| (k, shape + noise))
+            })
+            .filter(|v| v.1 > 0)
+            .collect()
+    }
+
+    #[test]
+    fn widths_by_hand() {
+        let bins = [(100, 1), (101, 2), (102, 4), (103, 2), (104, 1)];
+        assert_eq!(
+            widths_of_percent(bins.iter().copied(), 102.0),
+            [1, 1, 1, 1, 3, 3, 3, 3, 5, 5, 5]
+        );
+    }
+
+    #[test]
+    fn widths_as_picard() {
+        let bins = skewed();
+        for median in [179.0, 179.5, 150.0, 260.5] {
+            assert_eq!(
+                widths_of_percent(bins.iter().map(|(k, v)| (*k, *v)), median),
+                picard_widths(&bins, median),
+                "median {}",
+                median
+            );
+        }
+        // One sided, median at the edge.
+        let bins: BTreeMap<usize, u64> = (100..110).map(|k| (k, 110 - k as u64)).collect();
+        assert_eq!(
+            widths_of_percent(bins.iter().map(|(k, v)| (*k, *v)), 100.0),
+            picard_widths(&bins, 100.0)
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::Write;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::error::Result;
+use crate::plot::Labels;
+
+/// Page template, `__DATA__` and `__LABELS__` are replaced by the series and labels json.
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Insert size</title>
+<style>
+body { font-family: sans-serif; margin: 20px; }
+#chart { position: relative; display: inline-block; }
+canvas { border: 1px solid #ddd; cursor: crosshair; }
+#tip { position: absolute; display: none; pointer-events: none; white-space: nowrap;
+  background: rgba(255, 255, 255, 0.9); border: 1px solid #999; padding: 4px 6px; font-size: 12px; }
+#legend span { cursor: pointer; margin-right: 14px; user-select: none; }
+#legend span.off { opacity: 0.3; }
+#legend i { display: inline-block; width: 20px; height: 3px; vertical-align: middle; margin-right: 4px; }
+p { color: #666; font-size: 12px; }
+#title:empty { display: none; }
+</style>
+</head>
+<body>
+<h3 id="title"></h3>
+<div id="legend"></div>
+<div id="chart"><canvas id="canvas" width="900" height="500"></canvas><div id="tip"></div></div>
+<p>Drag to zoom, double click to reset, click a legend entry to toggle its curve.</p>
+<script>
+const SERIES = __DATA__;
+const LABELS = __LABELS__;
+const L = 70, R = 20, T = 20, B = 50;
+const canvas = document.getElementById("canvas");
+const ctx = canvas.getContext("2d");
+const tip = document.getElementById("tip");
+const W = canvas.width, H = canvas.height;
+const len = Math.max(1, ...SERIES.map(s => s.counts.length));
+SERIES.forEach(s => {
+  s.on = true;
+  s.total = s.counts.reduce((a, b) => a + b, 0);
+  s.fractions = s.counts.map(v => s.total > 0 ? v / s.total : 0);
+});
+let x0 = 0, x1 = len - 1, drag = null, hover = null;
+if (LABELS.title) {
+  document.title = LABELS.title;
+  document.getElementById("title").textContent = LABELS.title;
+}
+
+const px = x => L + (x - x0) / Math.max(1, x1 - x0) * (W - L - R);
+const py = (y, ymax) => H - B - y / ymax * (H - T - B);
+const esc = t => String(t).replace(/[&<>"]/g, c => ({ "&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;" })[c]);
+const at = e => Math.round(x0 + (e.offsetX - L) / (W - L - R) * (x1 - x0));
+
+function draw() {
+  ctx.clearRect(0, 0, W, H);
+  let ymax = 0;
+  SERIES.filter(s => s.on).forEach(s => {
+    for (let x = x0; x <= x1; x++) ymax = Math.max(ymax, s.fractions[x] || 0);
+  });
+  ymax = ymax > 0 ? ymax * 1.05 : 1;
+  ctx.strokeStyle = "#000";
+  ctx.fillStyle = "#000";
+  ctx.font = "12px sans-serif";
+  ctx.beginPath();
+  ctx.moveTo(L, T);
+  ctx.lineTo(L, H - B);
+  ctx.lineTo(W - R, H - B);
+  ctx.stroke();
+  for (let i = 0; i <= 5; i++) {
+    const x = Math.round(x0 + (x1 - x0) * i / 5), y = ymax * i / 5;
+    ctx.textAlign = "center";
+    ctx.fillText(x, px(x), H - B + 16);
+    ctx.textAlign = "right";
+    ctx.fillText(y.toFixed(4), L - 6, py(y, ymax) + 4);
+  }
+  ctx.textAlign = "center";
+  ctx.fillText(LABELS.x, (L + W - R) / 2, H - 10);
+  ctx.save();
+  ctx.translate(16, (T + H - B) / 2);
+  ctx.rotate(-Math.PI / 2);
+  ctx.fillText(LABELS.y, 0, 0);
+  ctx.restore();
+  SERIES.filter(s => s.on).forEach(s => {
+    ctx.strokeStyle = s.color;
+    ctx.lineWidth = 1.5;
+    ctx.beginPath();
+    for (let x = x0; x <= x1; x++) {
+      const y = py(s.fractions[x] || 0, ymax);
+      x === x0 ? ctx.moveTo(px(x), y) : ctx.lineTo(px(x), y);
+    }
+    ctx.stroke();
+  });
+  ctx.lineWidth = 1;
+  if (drag !== null && hover !== null) {
+    ctx.fillStyle = "rgba(100, 100, 255, 0.15)";
+    ctx.fillRect(Math.min(px(drag), px(hover)), T, Math.abs(px(hover) - px(drag)), H - T - B);
+  } else if (hover !== null) {
+    ctx.strokeStyle = "#999";
+    ctx.beginPath();
+    ctx.moveTo(px(hover), T);
+    ctx.lineTo(px(hover), H - B);
+    ctx.stroke();
+  }
+}
+
+const legend = document.getElementById("legend");
+SERIES.forEach(s => {
+  const item = document.createElement("span");
+  const mark = document.createElement("i");
+  mark.style.background = s.color;
+  item.appendChild(mark);
+  item.appendChild(document.createTextNode(s.name));
+  item.onclick = () => {
+    s.on = !s.on;
+    item.className = s.on ? "" : "off";
+    draw();
+  };
+  legend.appendChild(item);
+});
+
+canvas.onmousemove = e => {
+  const x = at(e);
+  if (x < x0 || x > x1) {
+    hover = null;
+    tip.style.display = "none";
+    draw();
+    return;
+  }
+  hover = x;
+  const rows = SERIES.filter(s => s.on).map(s =>
+    `<span style="color:${s.color}">${esc(s.name)}</span>: ${s.counts[x] || 0} (${((s.fractions[x] || 0) * 100).toFixed(2)}%)`);
+  tip.innerHTML = `<b>${x} bp</b><br>` + rows.join("<br>");
+  tip.style.display = "block";
+  tip.style.left = (e.offsetX + 12) + "px";
+  tip.style.top = (e.offsetY + 12) + "px";
+  draw();
+};
+canvas.onmouseleave = () => {
+  hover = null;
+  drag = null;
+  tip.style.display = "none";
+  draw();
+};
+canvas.onmousedown = e => {
+  drag = Math.min(x1, Math.max(x0, at(e)));
+};
+canvas.onmouseup = e => {
+  const x = Math.min(x1, Math.max(x0, at(e)));
+  if (drag !== null && Math.abs(x - drag) >= 2) {
+    x0 = Math.min(x, drag);
+    x1 = Math.max(x, drag);
+  }
+  drag = null;
+  draw();
+};
+canvas.ondblclick = () => {
+  x0 = 0;
+  x1 = len - 1;
+  draw();
+};
+draw();
+</script>
+</body>
+</html>
+"##;
+
+/// One curve, name, color and counts.
+struct Series<'a> {
+    name: &'a str,
+    color: String,
+    counts: &'a [u32],
+}
+
+impl Serialize for Series<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Series", 3)?;
+        state.serialize_field("name", self.name)?;
+        state.serialize_field("color", &self.color)?;
+        state.serialize_field("counts", self.counts)?;
+        state.end()
+    }
+}
+
+impl Serialize for Labels {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Labels", 3)?;
+        state.serialize_field("title", &self.title)?;
+        state.serialize_field("x", &self.x)?;
+        state.serialize_field("y", &self.y)?;
+        state.end()
+    }
+}
+
+/// Write a self-contained interactive html chart of samples, one color each, data is inlined as json.
+pub fn write_html<W: Write>(
+    mut out: W,
+    samples: &[(String, Vec<u32>)],
+    labels: &Labels,
+    colors: &[(u8, u8, u8)],
+) -> Result<()> {
+    let series: Vec<Series> = samples
+        .iter()
+        .zip(colors)
+        .map(|((name, counts), (r, g, b))| Series {
+            name,
+            color: format!("#{:02X}{:02X}{:02X}", r, g, b),
+            counts,
+        })
+        .collect();
+    let data = serde_json::to_string(&series)?;
+    // Keep names like `</script>` from closing the script block.
+    let data = data.replace("</", "<\\/");
+    let labels = serde_json::to_string(labels)?.replace("</", "<\\/");
+    out.write_all(
+        TEMPLATE
+            .replace("__DATA__", &data)
+            .replace("__LABELS__", &labels)
+            .as_bytes(),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use std::collections::VecDeque;
+use std::env;
+use std::io::{self, BufReader, Read};
+
+use serde_json::Value;
+
+use crate::bam::BamReader;
+use crate::bgzf::BgzfReader;
+use crate::error::{Error, Result};
+use crate::header::Header;
+use crate::reader::RecordReader;
+use crate::record::{Fields, Record};
+use crate::region::Region;
+
+/// Url of a block and headers to send, a `data:` url holds the block itself.
+struct Block {
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+/// Blocks of a ticket read one after another, each fetched when the previous one ends.
+struct Blocks {
+    agent: ureq::Agent,
+    blocks: VecDeque<Block>,
+    current: Box<dyn Read + Send + Sync>,
+}
+
+impl Read for Blocks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            let block = match self.blocks.pop_front() {
+                Some(v) => v,
+                None => return Ok(0),
+            };
+            self.current = match block.url.strip_prefix("data:") {
+                Some(v) => Box::new(io::Cursor::new(data_url(v)?)),
+                None => {
+                    let mut request = self.agent.get(&block.url);
+                    for (k, v) in &block.headers {
+                        request = request.set(k, v);
+                    }
+                    request
+                        .call()
+                        .map_err(|e| Error::Http {
+                            url: block.url.clone(),
+                            message: e.to_string(),
+                        })?
+                        .into_reader()
+                }
+            };
+        }
+    }
+}
+
+/// Bytes of a `data:` url without the scheme, base64 or percent encoded.
+fn data_url(v: &str) -> Result<Vec<u8>> {
+    let err = || Error::Malformed("Malformed data url in htsget ticket.".to_string());
+    let (meta, data) = v.split_once(',').ok_or_else(err)?;
+    if !meta.ends_with(";base64") {
+        let mut out = Vec::with_capacity(data.len());
+        let mut bytes = data.bytes();
+        while let Some(b) = bytes.next() {
+            out.push(match b {
+                b'%' => {
+                    let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+                    std::str::from_utf8(&hex)
+                        .ok()
+                        .and_then(|v| u8::from_str_radix(v, 16).ok())
+                        .ok_or_else(err)?
+                }
+                b => b,
+            });
+        }
+        return Ok(out);
+    }
+    let mut out = Vec::with_capacity(data.len() / 4 * 3);
+    let (mut acc, mut bits) = (0u32, 0);
+    for b in data
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace() && *b != b'=')
+    {
+        let v = match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(err()),
+        };
+        acc = acc << 6 | u32::from(v);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Request a ticket of reads at `url` and return its blocks as one bam stream.
+///
+/// A bearer token in `HTSGET_TOKEN` is sent with the ticket request.
+fn open_ticket(agent: &ureq::Agent, url: &str, region: Option<&Region>) -> Result<Blocks> {
+    let mut request = agent.get(url).query("format", "BAM");
+    if let Some(region) = region {
+        request = request.query("referenceName", &region.name);
+        if region.start > 0 {
+            request = request.query("start", &region.start.to_string());
+        }
+        if region.end < i32::MAX {
+            request = request.query("end", &region.end.to_string());
+        }
+    }
+    if let Ok(token) = env::var("HTSGET_TOKEN") {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    let text = match request.call() {
+        Ok(v) => v.into_string()?,
+        // Errors are json with an error name and message.
+        Err(ureq::Error::Status(status, response)) => {
+            let json: Value = serde_json::from_str(&response.into_string().unwrap_or_default())
+                .unwrap_or(Value::Null);
+            let error = &json["htsget"];
+            return Err(Error::Http {
+                url: url.to_string(),
+                message: format!(
+                    "htsget error {} {}: {}",
+                    status,
+                    error["error"].as_str().unwrap_or_default(),
+                    error["message"].as_str().unwrap_or_default()
+                ),
+            });
+        }
+        Err(e) => {
+            return Err(Error::Http {
+                url: url.to_string(),
+                message: e.to_string(),
+            })
+        }
+    };
+    let json: Value = serde_json::from_str(&text).map_err(|e| Error::Http {
+        url: url.to_string(),
+        message: format!("malformed htsget ticket, {}", e),
+    })?;
+    let ticket = &json["htsget"];
+    match ticket["format"].as_str() {
+        None | Some("BAM") => {}
+        Some(v) => {
+            return Err(Error::Http {
+                url: url.to_string(),
+                message: format!("htsget ticket format {} is not bam", v),
+            })
+        }
+    }
+    let urls = ticket["urls"].as_array().ok_or_else(|| Error::Http {
+        url: url.to_string(),
+        message: "no urls in htsget ticket".to_string(),
+    })?;
+    let mut blocks = VecDeque::with_capacity(urls.len());
+    for v in urls {
+        let url = v["url"]
+            .as_str()
+            .ok_or_else(|| Error::Malformed("No url of htsget block.".to_string()))?;
+        let headers = v["headers"]
+            .as_object()
+            .map(|v| {
+                v.iter()
+                    .filter_map(|(k, v)| v.as_str().map(|v| (k.to_string(), v.to_string())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        blocks.push_back(Block {
+            url: url.to_string(),
+            headers,
+        });
+    }
+    Ok(Blocks {
+        agent: agent.clone(),
+        blocks,
+        current: Box::new(io::empty()),
+    })
+}
+
+/// Reads of a GA4GH htsget server, one ticket per region.
+///
+/// Blocks may hold reads outside the region, so reads are filtered again, and a read of
+/// overlapping regions only counts for the first.
+pub struct HtsgetReader {
+    agent: ureq::Agent,
+    url: String,
+    regions: Vec<Region>,
+    // Index of region of the current ticket.
+    ri: usize,
+    bam: BamReader<BgzfReader<BufReader<Blocks>>>,
+    // Optional fields parsed, kept for the reader of each ticket.
+    fields: Fields,
+}
+
+impl HtsgetReader {
+    /// Reads of all `regions` at htsget reads `url`, e.g. `https://server/reads/ID`, all reads
+    /// without regions.
+    pub fn new(url: &str, regions: &[Region]) -> Result<Self> {
+        let agent = ureq::agent();
+        let blocks = open_ticket(&agent, url, regions.first())?;
+        let mut bam = BamReader::new(BufReader::new(blocks))?;
+        // Names with colons are resolved by the header, the first ticket again if it changed.
+        let resolved: Vec<Region> = regions
+            .iter()
+            .map(|v| v.resolve(bam.references()))
+            .collect();
+        if let (Some(first), Some(v)) = (regions.first(), resolved.first()) {
+            if first.name != v.name {
+                bam = BamReader::new(BufReader::new(open_ticket(&agent, url, Some(v))?))?;
+            }
+        }
+        Ok(Self {
+            bam,
+            agent,
+            url: url.to_string(),
+            regions: resolved,
+            ri: 0,
+            fields: Fields::ALL,
+        })
+    }
+
+    fn overlaps(&self, region: &Region, record: &Record) -> bool {
+        self.bam.reference_name(*record.ref_id()) == Some(region.name.as_str())
+            && *record.pos() < region.end
+            && *record.end() > region.start
+    }
+}
+
+impl RecordReader for HtsgetReader {
+    fn read_into(&mut self, record: &mut Record) -> Result<bool> {
+        loop {
+            if !self.bam.read_into(record)? {
+                self.ri += 1;
+                match self.regions.get(self.ri) {
+                    Some(region) => {
+                        let blocks = open_ticket(&self.agent, &self.url, Some(region))?;
+                        self.bam = BamReader::new(BufReader::new(blocks))?;
+                        self.bam.set_fields(self.fields);
+                        continue;
+                    }
+                    None => return Ok(false),
+                }
+            }
+            let region = match self.regions.get(self.ri) {
+                Some(v) => v,
+                None => return Ok(true),
+            };
+            if self.overlaps(region, record)
+                && !self.regions[..self.ri]
+                    .iter()
+                    .any(|v| self.overlaps(v, record))
+            {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn reference_name(&self, id: i32) -> Option<&str> {
+        self.bam.reference_name(id)
+    }
+
+    fn read_groups(&self) -> &[String] {
+        self.bam.read_groups()
+    }
+
+    fn header(&self) -> &Header {
+        self.bam.header()
+    }
+
+    fn set_fields(&mut self, fields: Fields) {
+        self.fields = fields;
+        self.bam.set_fields(fields)
+    }
+}
@@ -0,0 +1,155 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::cloud::{self, is_cloud_url};
+use crate::error::{Error, Result};
+
+/// Whether input is a `http://`, `https://`, `s3://` or `gs://` url rather than a path.
+pub fn is_url(v: &str) -> bool {
+    v.starts_with("http://") || v.starts_with("https://") || is_cloud_url(v)
+}
+
+fn http_err(url: &str, e: ureq::Error) -> Error {
+    match e {
+        ureq::Error::Status(404, _) => Error::NotFound(url.to_string()),
+        // Missing objects of buckets not listable by the caller.
+        ureq::Error::Status(403, _) if is_cloud_url(url) => Error::Denied(url.to_string()),
+        e => Error::Http {
+            url: url.to_string(),
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Seekable reader of a remote file, reading on from any offset by http range requests.
+///
+/// A request streams from the offset to the end and is only reopened after a seek, so
+/// sequential reading costs a single request.
+pub struct HttpReader {
+    agent: ureq::Agent,
+    url: String,
+    // Url as given, without any presigned query to leak in messages.
+    name: String,
+    // Extra request headers, e.g. authorization.
+    headers: Vec<(String, String)>,
+    pos: u64,
+    len: Option<u64>,
+    body: Option<Box<dyn Read + Send + Sync>>,
+}
+
+impl HttpReader {
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_headers(url, Vec::new())
+    }
+
+    /// Reader sending `headers` with every request, `s3://` and `gs://` urls are signed
+    /// with credentials of the environment.
+    pub fn with_headers(url: &str, mut headers: Vec<(String, String)>) -> Result<Self> {
+        let (https, auth) = cloud::resolve(url)?;
+        headers.extend(auth);
+        let mut reader = Self {
+            agent: ureq::agent(),
+            url: https,
+            name: url.to_string(),
+            headers,
+            pos: 0,
+            len: None,
+            body: None,
+        };
+        // Fail early on missing files.
+        reader.open()?;
+        Ok(reader)
+    }
+
+    /// Start streaming from the current offset.
+    fn open(&mut self) -> Result<()> {
+        let mut request = self
+            .agent
+            .get(&self.url)
+            .set("Range", &format!("bytes={}-", self.pos));
+        for (k, v) in &self.headers {
+            request = request.set(k, v);
+        }
+        let response = match request.call() {
+            Ok(v) => v,
+            // Range starting at or after the end.
+            Err(ureq::Error::Status(416, _)) => {
+                self.body = Some(Box::new(io::empty()));
+                return Ok(());
+            }
+            Err(e) => return Err(http_err(&self.name, e)),
+        };
+        // Total size is after the slash of `bytes start-end/total`.
+        let total = response
+            .header("Content-Range")
+            .and_then(|v| v.rsplit_once('/'))
+            .and_then(|(_, v)| v.parse().ok());
+        if let Some(v) = total {
+            self.len = Some(v);
+        }
+        match response.status() {
+            206 => {}
+            // Range ignored by the server, the whole file is sent.
+            200 if self.pos == 0 => {
+                self.len = response
+                    .header("Content-Length")
+                    .and_then(|v| v.parse().ok());
+            }
+            status => {
+                return Err(Error::Http {
+                    url: self.name.clone(),
+                    message: format!("range request answered with status {}", status),
+                })
+            }
+        }
+        self.body = Some(response.into_reader());
+        Ok(())
+    }
+
+    /// Size of the remote file.
+    fn len(&mut self) -> Result<u64> {
+        if let Some(v) = self.len {
+            return Ok(v);
+        }
+        let mut request = self.agent.head(&self.url);
+        for (k, v) in &self.headers {
+            request = request.set(k, v);
+        }
+        let response = request.call().map_err(|e| http_err(&self.name, e))?;
+        let len = response
+            .header("Content-Length")
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(|| Error::Http {
+                url: self.name.clone(),
+                message: "size is unknown".to_string(),
+            })?;
+        self.len = Some(len);
+        Ok(len)
+    }
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.body.is_none() {
+            self.open()?;
+        }
+        let n = self.body.as_mut().map_or(Ok(0), |v| v.read(buf))?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for HttpReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(v) => Some(v),
+            SeekFrom::Current(v) => self.pos.checked_add_signed(v),
+            SeekFrom::End(v) => self.len()?.checked_add_signed(v),
+        }
+        .ok_or_else(|| Error::Invalid("Seek before start of file.".to_string()))?;
+        if pos != self.pos {
+            self.pos = pos;
+            self.body = None;
+        }
+        Ok(pos)
+    }
+}
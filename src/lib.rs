@@ -0,0 +1,939 @@
+//! Fast insert size distribution from bam, sam or cram.
+//!
+//! ```no_run
+//! let sum = insize::compute_insert_sizes("tests/test.bam", &insize::Options::default()).unwrap();
+//! println!("{:.2}", sum.mean);
+//! ```
+extern crate byteorder;
+#[cfg(feature = "pdf")]
+extern crate cairo;
+extern crate flate2;
+#[cfg(not(target_arch = "wasm32"))]
+extern crate font_kit;
+extern crate hmac;
+extern crate image;
+extern crate indicatif;
+#[cfg(feature = "libdeflate")]
+extern crate libdeflater;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "python")]
+extern crate numpy;
+extern crate plotters;
+#[cfg(feature = "pdf")]
+extern crate plotters_cairo;
+#[cfg(feature = "python")]
+extern crate pyo3;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate sha2;
+extern crate thiserror;
+extern crate toml;
+extern crate ureq;
+#[cfg(target_arch = "wasm32")]
+extern crate wasm_bindgen;
+#[cfg(target_arch = "wasm32")]
+extern crate web_time;
+
+pub mod bai;
+pub mod bam;
+pub mod bed;
+pub mod bgzf;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checkpoint;
+pub mod cloud;
+pub mod compare;
+pub mod config;
+pub mod converge;
+pub mod cram;
+pub mod dedup;
+pub mod dump;
+pub mod error;
+pub mod gmm;
+pub mod header;
+pub mod hist;
+pub mod html;
+pub mod htsget;
+pub mod http;
+pub mod metrics;
+pub mod multiqc;
+pub mod names;
+pub mod picard;
+pub mod plot;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod qc;
+pub mod reader;
+pub mod record;
+pub mod region;
+pub mod report;
+pub mod sam;
+pub mod sample;
+pub mod samtools;
+pub mod serve;
+pub mod sheet;
+pub mod stream;
+pub mod summary;
+pub mod table;
+#[cfg(target_arch = "wasm32")]
+pub mod wasm;
+pub mod watch;
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+pub use bam::{BamReader, Records};
+pub use bed::Targets;
+pub use bgzf::BgzfReader;
+pub use checkpoint::{Fingerprint, ScanState};
+pub use compare::{Comparison, KsTest, Reference};
+pub use config::Config;
+pub use converge::{Converge, EarlyStop};
+pub use cram::CramReader;
+pub use dedup::Dedup;
+pub use dump::TlenDump;
+pub use error::{Error, Result};
+pub use gmm::Mixture;
+pub use header::Header;
+pub use hist::{
+    gc_stratum, mapq_bucket, mapq_bucket_names, Fragments, Histograms, InsertSizeHistogram, Tail,
+    GC_STRATA,
+};
+pub use htsget::HtsgetReader;
+pub use http::HttpReader;
+pub use metrics::Metrics;
+pub use names::ReadNames;
+pub use progress::Progress;
+pub use qc::FailIf;
+pub use reader::{
+    open_bytes, open_counting_reader, open_reader, open_resumable_reader, CountingReader,
+    RecordReader,
+};
+pub use record::{parse_flags, Fields, PairOrientation, Record};
+pub use region::{glob_match, GenomeBuild, Region, RegionReader};
+pub use report::Cohort;
+pub use sam::SamReader;
+pub use sample::Sampler;
+pub use serve::Request;
+pub use sheet::SampleSheet;
+pub use stream::NdjsonStream;
+pub use summary::{
+    Alignments, Excluded, ExpectedSize, FragmentSummary, Summary, TailSummary, Unqualified,
+};
+pub use watch::Watcher;
+
+/// Read is paired and properly mapped, all required by default.
+pub const DEFAULT_INCLUDE_FLAGS: u16 = 0x1 + 0x2;
+/// Read is secondary, QC-fail or supplementary, any skipped by default.
+pub const DEFAULT_EXCLUDE_FLAGS: u16 = SECONDARY_FLAG + Q_FLAG + SUPPLEMENTARY_FLAG;
+/// Read is unmapped.
+pub(crate) const UNMAPPED_FLAG: u16 = 0x4;
+/// Mate is unmapped.
+pub(crate) const MATE_UNMAPPED_FLAG: u16 = 0x8;
+/// Alignment is secondary.
+pub(crate) const SECONDARY_FLAG: u16 = 0x100;
+/// Alignment is supplementary, a part of a chimeric alignment.
+pub(crate) const SUPPLEMENTARY_FLAG: u16 = 0x800;
+/// Read is PCR or optical duplicate.
+const D_FLAG: u16 = 0x400;
+/// Read fails platform/vendor quality checks.
+const Q_FLAG: u16 = 0x200;
+
+/// First read in pair.
+const READ1: u16 = 0x40;
+/// Second read in pair.
+const READ2: u16 = 0x80;
+
+/// Which read of a pair is counted, so each pair is counted once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CountBy {
+    /// First read (0x40), pairs whose first read is filtered out are missed.
+    Read1,
+    /// Second read (0x80).
+    Read2,
+    /// Either read with positive TLEN, like samtools stats, first read when TLEN is 0.
+    EitherDedup,
+}
+
+impl CountBy {
+    /// Whether the pair is counted by this record.
+    fn selects(&self, record: &Record) -> bool {
+        match self {
+            Self::Read1 => record.flag() & READ1 != 0,
+            Self::Read2 => record.flag() & READ2 != 0,
+            // TLEN 0 (e.g. mates on different references) has no sign, first read counts it.
+            Self::EitherDedup => {
+                *record.tlen() > 0 || (*record.tlen() == 0 && record.flag() & READ1 != 0)
+            }
+        }
+    }
+}
+
+impl FromStr for CountBy {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "read1" => Ok(Self::Read1),
+            "read2" => Ok(Self::Read2),
+            "either-dedup" => Ok(Self::EitherDedup),
+            _ => Err(Error::Invalid(format!(
+                "Wrong count mode: {}, use read1, read2 or either-dedup.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Insert size bound of mate-pair libraries by default.
+pub const MATE_PAIR_UPPER: usize = 20_000;
+/// Insert size bound in cfDNA mode by default, past the tri-nucleosome peak.
+pub const CFDNA_UPPER: usize = 700;
+/// Insert size bound in ATAC-seq mode by default, the span of the usual fragment size plot.
+pub const ATAC_UPPER: usize = 1000;
+
+/// Library type, deciding which pair orientation makes the distribution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LibraryType {
+    /// Paired-end library, pairs of any orientation are counted.
+    PairedEnd,
+    /// Mate-pair (jumping) library of circularized multi-kb fragments, only RF pairs are
+    /// counted, FR pairs are short paired-end contamination.
+    MatePair,
+}
+
+impl LibraryType {
+    /// Whether pairs of `orientation` make the distribution.
+    fn expects(&self, orientation: PairOrientation) -> bool {
+        match self {
+            Self::PairedEnd => true,
+            Self::MatePair => orientation == PairOrientation::RF,
+        }
+    }
+
+    /// Maximum insert size to record by default.
+    pub fn default_upper(&self) -> usize {
+        match self {
+            Self::PairedEnd => 500,
+            Self::MatePair => MATE_PAIR_UPPER,
+        }
+    }
+}
+
+impl FromStr for LibraryType {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "paired-end" => Ok(Self::PairedEnd),
+            "mate-pair" => Ok(Self::MatePair),
+            _ => Err(Error::Invalid(format!(
+                "Wrong library type: {}, use paired-end or mate-pair.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Options of insert size counting.
+pub struct Options {
+    /// Maximum insert size to record, bigger number costs more memory.
+    pub upper: usize,
+    /// Reference fasta for decoding cram.
+    pub reference: Option<String>,
+    /// Skip duplicate reads.
+    pub no_dups: bool,
+    /// Count QC-fail reads, even when in `exclude_flags`.
+    pub include_qcfail: bool,
+    /// Only count reads with all these flag bits set.
+    pub include_flags: u16,
+    /// Skip reads with any of these flag bits set.
+    pub exclude_flags: u16,
+    /// Which read of a pair is counted.
+    pub count_by: CountBy,
+    /// Library type, pairs of other orientations are left out of mate-pair libraries.
+    pub library_type: LibraryType,
+    /// Settle upper at the insert size covering this fraction of pairs, after counting.
+    pub auto_upper: Option<f64>,
+    /// Summarize pairs above upper too, instead of only counting them in total.
+    pub tail_report: bool,
+    /// Extend insert size by soft clips at outer ends of the pair.
+    pub clip_adjust: bool,
+    /// Only count reads overlapping these regions, needs bam index.
+    pub regions: Vec<Region>,
+    /// Inputs are htsget reads urls, reads of `regions` are requested from the server.
+    pub htsget: bool,
+    /// Only count pairs whose alignment start falls inside these targets.
+    pub targets: Option<Targets>,
+    /// Skip reads on references matching any of these glob patterns, e.g. `chrM` or `HLA-*`.
+    pub exclude_contigs: Vec<String>,
+    /// Only count reads on these references, e.g. primary chromosomes.
+    pub contigs: Option<Vec<String>>,
+    /// Only count reads of these names.
+    pub read_names: Option<ReadNames>,
+    /// Also count pairs of each reference.
+    pub per_chromosome: bool,
+    /// Also count pairs of each read group.
+    pub per_read_group: bool,
+    /// Also count pairs by GC of the counted read, in 20% strata.
+    pub per_gc: bool,
+    /// Also count pairs by MAPQ of the counted read, in buckets from these ascending lower
+    /// bounds.
+    pub mapq_buckets: Vec<u8>,
+    /// Fraction trimmed from each end for the trimmed mean.
+    pub trim: f64,
+    /// Extra percentiles to summarize, in percent.
+    pub percentiles: Vec<f64>,
+    /// Expected insert sizes, e.g. of amplicons, pairs near each are summarized.
+    pub expected_sizes: Vec<usize>,
+    /// Bp around an expected size counted as it.
+    pub expected_tolerance: usize,
+    /// Fit a Gaussian mixture of this many components to qualified pairs.
+    pub fit_gmm: Option<usize>,
+    /// Summarize nucleosome peaks and short fragments of cfDNA.
+    pub cfdna: bool,
+    /// Summarize nucleosome-free and nucleosomal fragments of ATAC-seq.
+    pub atac: bool,
+    /// Threads for bam decompression, 1 inflates on the reading thread.
+    pub threads: usize,
+    /// Randomly keep this fraction of qualified pairs.
+    pub sample_fraction: Option<f64>,
+    /// Stop after counting this many pairs.
+    pub max_pairs: Option<u64>,
+    /// Stop once running median and SD change relatively by less than this between checks.
+    pub converge_tolerance: Option<f64>,
+    /// Pairs counted before stopping on convergence.
+    pub converge_min_pairs: u64,
+    /// Seed of pair sampling, same seed keeps same pairs.
+    pub seed: u64,
+    /// Show records, bytes read, throughput and ETA on stderr.
+    pub progress: bool,
+    /// Fail on bam without the bgzf EOF block instead of warning.
+    pub strict: bool,
+    /// Skip malformed records instead of failing, counted in the summary.
+    pub lenient: bool,
+    /// Print summaries as newline delimited json on stdout, a partial one every this many
+    /// records.
+    pub stream_every: Option<u64>,
+    /// Adapter bases at each end of a fragment, estimated fragment sizes are also counted when
+    /// this or `read_length` is set.
+    pub adapter_length: Option<usize>,
+    /// Read length of inner distances, SEQ length of the counted read otherwise.
+    pub read_length: Option<usize>,
+    /// Collapse duplicates not marked in input, holding at most this many keys.
+    pub dedup_capacity: Option<usize>,
+    /// Write the insert size of every counted pair to this path, gzip compressed for `.gz`.
+    pub dump_tlens: Option<String>,
+    /// Also write reference name and position of the counted read to `dump_tlens`.
+    pub dump_positions: bool,
+    /// Save the scan state of a bam file to this path every `checkpoint_every` seconds.
+    pub checkpoint: Option<String>,
+    /// Seconds between checkpoints, `DEFAULT_CHECKPOINT_EVERY` by default.
+    pub checkpoint_every: u64,
+    /// Resume from the scan state saved to this path by an interrupted run.
+    pub resume: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            upper: 500,
+            reference: None,
+            no_dups: false,
+            include_qcfail: false,
+            include_flags: DEFAULT_INCLUDE_FLAGS,
+            exclude_flags: DEFAULT_EXCLUDE_FLAGS,
+            count_by: CountBy::Read1,
+            library_type: LibraryType::PairedEnd,
+            auto_upper: None,
+            tail_report: false,
+            clip_adjust: false,
+            regions: Vec::new(),
+            htsget: false,
+            targets: None,
+            exclude_contigs: Vec::new(),
+            contigs: None,
+            read_names: None,
+            per_chromosome: false,
+            per_read_group: false,
+            per_gc: false,
+            mapq_buckets: Vec::new(),
+            trim: hist::DEFAULT_TRIM,
+            percentiles: Vec::new(),
+            expected_sizes: Vec::new(),
+            expected_tolerance: hist::DEFAULT_EXPECTED_TOLERANCE,
+            fit_gmm: None,
+            cfdna: false,
+            atac: false,
+            threads: 1,
+            sample_fraction: None,
+            max_pairs: None,
+            converge_tolerance: None,
+            converge_min_pairs: converge::DEFAULT_CONVERGE_MIN_PAIRS,
+            seed: 0,
+            progress: false,
+            strict: false,
+            lenient: false,
+            stream_every: None,
+            adapter_length: None,
+            read_length: None,
+            dedup_capacity: None,
+            dump_tlens: None,
+            dump_positions: false,
+            checkpoint: None,
+            checkpoint_every: checkpoint::DEFAULT_CHECKPOINT_EVERY,
+            resume: None,
+        }
+    }
+}
+
+impl Options {
+    /// Optional record fields the counting options need, a reader may skip parsing others.
+    pub fn fields(&self) -> Fields {
+        let mut v = Fields::NONE;
+        if self.per_gc {
+            v = v | Fields::GC;
+        }
+        if self.read_names.is_some() {
+            v = v | Fields::NAME;
+        }
+        if self.clip_adjust {
+            v = v | Fields::CLIPS;
+        }
+        if self.per_read_group {
+            v = v | Fields::READ_GROUP;
+        }
+        v
+    }
+
+    /// Stable hash of options deciding which pairs are counted and into which histograms, a
+    /// checkpoint resumes only with the same.
+    pub fn counting_hash(&self) -> u64 {
+        let mut h = checkpoint::Fnv::default();
+        self.upper.hash(&mut h);
+        self.auto_upper.map(f64::to_bits).hash(&mut h);
+        (self.no_dups, self.include_qcfail).hash(&mut h);
+        (self.include_flags, self.exclude_flags).hash(&mut h);
+        (self.count_by, self.library_type, self.clip_adjust).hash(&mut h);
+        self.targets.is_some().hash(&mut h);
+        if let Some(v) = &self.targets {
+            v.hash_into(&mut h);
+        }
+        (&self.exclude_contigs, &self.contigs).hash(&mut h);
+        self.read_names.is_some().hash(&mut h);
+        if let Some(v) = &self.read_names {
+            v.hash_into(&mut h);
+        }
+        (self.per_chromosome, self.per_gc, &self.mapq_buckets).hash(&mut h);
+        h.finish()
+    }
+}
+
+/// Insert size histogram of bam, sam or cram at `path`, `-` for stdin.
+pub fn compute_histogram(path: &str, opts: &Options) -> Result<InsertSizeHistogram> {
+    Ok(compute_histograms(path, opts)?.all)
+}
+
+/// Insert size histograms of bam, sam or cram at `path`, overall and split by groups.
+pub fn compute_histograms(path: &str, opts: &Options) -> Result<Histograms> {
+    scan(path, None, opts)
+}
+
+/// Insert size histograms of records of `reader`, named `name` in logs and errors, e.g. of
+/// bytes in memory. Progress, checkpoints and htsget of `opts` are left out.
+pub fn compute_histograms_from(
+    name: &str,
+    reader: Box<dyn RecordReader>,
+    opts: &Options,
+) -> Result<Histograms> {
+    scan(name, Some(reader), opts)
+}
+
+/// Histograms of records of `input`, or else of opening `path`.
+fn scan(path: &str, input: Option<Box<dyn RecordReader>>, opts: &Options) -> Result<Histograms> {
+    // Histograms keep counts above upper until it is settled by `auto_upper`.
+    let new_hist = || match opts.auto_upper {
+        Some(_) => InsertSizeHistogram::with_overflow(opts.upper),
+        None => InsertSizeHistogram::new(opts.upper),
+    };
+    let mut hist = new_hist();
+    // Histograms indexed by reference id.
+    let mut chromosomes: Vec<Option<InsertSizeHistogram>> = Vec::new();
+    // Histograms of read groups and their order of appearance.
+    let mut read_groups: HashMap<Vec<u8>, InsertSizeHistogram> = HashMap::new();
+    let mut rg_order: Vec<Vec<u8>> = Vec::new();
+    let mut gc_strata: Vec<InsertSizeHistogram> = if opts.per_gc {
+        GC_STRATA.iter().map(|_| new_hist()).collect()
+    } else {
+        Vec::new()
+    };
+    let mut mapq_buckets: Vec<InsertSizeHistogram> =
+        opts.mapq_buckets.iter().map(|_| new_hist()).collect();
+    let mut orientations: Vec<(PairOrientation, InsertSizeHistogram)> = PairOrientation::ALL
+        .iter()
+        .map(|v| (*v, new_hist()))
+        .collect();
+    let mut fragments = (opts.adapter_length.is_some() || opts.read_length.is_some()).then(|| {
+        Fragments::new(
+            opts.adapter_length.unwrap_or(0),
+            opts.upper,
+            opts.auto_upper.is_some(),
+        )
+    });
+    // Counts above upper are kept by histograms until `auto_upper` settles it.
+    let mut tail = (opts.tail_report && opts.auto_upper.is_none()).then(Tail::default);
+    let mut record = Record::default();
+    let mut progress = if opts.progress && input.is_none() {
+        Some(Progress::new(path)?)
+    } else {
+        None
+    };
+    let stream = opts.stream_every.map(|v| NdjsonStream::new(path, v));
+    let mut dump = opts
+        .dump_tlens
+        .as_deref()
+        .map(|v| TlenDump::create(v, opts.dump_positions))
+        .transpose()?;
+    // Bytes read of whole inputs, for progress and positions in errors.
+    let bytes = progress
+        .as_ref()
+        .map_or_else(|| Arc::new(AtomicU64::new(0)), Progress::counter);
+    let counted = input.is_none() && !opts.htsget && opts.regions.is_empty();
+    // Blocks inflated by workers are read far ahead, offsets would be off.
+    let offset = || (counted && opts.threads <= 1).then(|| bytes.load(Ordering::Relaxed));
+    let resumable = input.is_none() && (opts.checkpoint.is_some() || opts.resume.is_some());
+    if resumable {
+        check_resumable(path, opts)?;
+    }
+    let fingerprint = if resumable {
+        Fingerprint::of_file(path, opts.counting_hash())?
+    } else {
+        Fingerprint::default()
+    };
+    let resumed = opts
+        .resume
+        .as_deref()
+        .map(|v| ScanState::load(v, &fingerprint))
+        .transpose()?;
+    let started = Instant::now();
+    let mut reader: Box<dyn RecordReader> = if let Some(v) = input {
+        v
+    } else if resumable {
+        let voffset = resumed.as_ref().map_or(0, |v| v.voffset);
+        open_resumable_reader(path, voffset, bytes.clone()).map_err(|e| e.in_file(path))?
+    } else if opts.htsget {
+        Box::new(HtsgetReader::new(path, &opts.regions)?)
+    } else if opts.regions.is_empty() {
+        open_counting_reader(
+            path,
+            opts.reference.as_deref(),
+            opts.threads,
+            Some(bytes.clone()),
+        )
+        .map_err(|e| e.in_file(path))?
+    } else {
+        Box::new(RegionReader::new(path, &opts.regions).map_err(|e| e.in_file(path))?)
+    };
+    info!("{}: header parsed in {:.2?}", path, started.elapsed());
+    reader.set_fields(opts.fields());
+    // Target intervals of the current reference and whether it is excluded.
+    let mut last_ref = -1;
+    let mut intervals = None;
+    let mut excluded = false;
+    let mut exclude_flags = opts.exclude_flags;
+    if opts.no_dups {
+        exclude_flags |= D_FLAG;
+    }
+    if opts.include_qcfail {
+        exclude_flags &= !Q_FLAG;
+    }
+    let mut converge = opts
+        .converge_tolerance
+        .map(|v| Converge::new(v, opts.converge_min_pairs));
+    let mut early_stop = None;
+    let mut sampler = opts.sample_fraction.map(|v| Sampler::new(v, opts.seed));
+    let mut dedup = opts.dedup_capacity.map(Dedup::new);
+    let mut duplicates = 0u64;
+    let mut pairs = 0u64;
+    let mut discordant_interchrom = 0u64;
+    let mut tlen_zero = 0u64;
+    let mut wrong_orientation = 0u64;
+    let mut sequenced_pairs = 0u64;
+    let mut alignments = Alignments::default();
+    let mut unqualified = Unqualified::default();
+    let mut short_inserts = 0u64;
+
+    let mut records = 0u64;
+    let mut records_skipped = 0u64;
+    if let Some(state) = resumed {
+        let all = state.hist.ok_or_else(resume_mismatch)?;
+        if all.upper() != opts.upper
+            || state.orientations.len() != orientations.len()
+            || state.gc_strata.len() != gc_strata.len()
+            || state.mapq_buckets.len() != mapq_buckets.len()
+            || !opts.per_chromosome && !state.chromosomes.is_empty()
+        {
+            return Err(resume_mismatch());
+        }
+        hist = all;
+        for (v, saved) in orientations.iter_mut().zip(state.orientations) {
+            v.1 = saved;
+        }
+        gc_strata = state.gc_strata;
+        mapq_buckets = state.mapq_buckets;
+        chromosomes = state.chromosomes;
+        records = state.records;
+        records_skipped = state.records_skipped;
+        pairs = state.pairs;
+        duplicates = state.duplicates;
+        discordant_interchrom = state.discordant_interchrom;
+        tlen_zero = state.tlen_zero;
+        wrong_orientation = state.wrong_orientation;
+        sequenced_pairs = state.sequenced_pairs;
+        short_inserts = state.short_inserts;
+        alignments = state.alignments;
+        unqualified = state.unqualified;
+        info!("{}: resumed after {} records", path, records);
+    }
+    let mut saved = Instant::now();
+    let started = Instant::now();
+    loop {
+        // Between records everything read is counted, a checkpoint resumes at the next.
+        if let Some(checkpoint) = opts.checkpoint.as_deref().filter(|_| {
+            records.is_multiple_of(CHECKPOINT_CHECK_EVERY)
+                && saved.elapsed().as_secs() >= opts.checkpoint_every
+        }) {
+            let state = ScanState {
+                voffset: reader.virtual_offset().ok_or_else(|| {
+                    Error::Invalid("Checkpoints need bam read on one thread.".to_string())
+                })?,
+                records,
+                records_skipped,
+                pairs,
+                duplicates,
+                discordant_interchrom,
+                tlen_zero,
+                wrong_orientation,
+                sequenced_pairs,
+                short_inserts,
+                alignments,
+                unqualified,
+                hist: Some(hist.clone()),
+                orientations: orientations.iter().map(|v| v.1.clone()).collect(),
+                gc_strata: gc_strata.clone(),
+                mapq_buckets: mapq_buckets.clone(),
+                chromosomes: chromosomes.clone(),
+            };
+            state.save(checkpoint, &fingerprint)?;
+            debug!("{}: checkpoint saved after {} records", path, records);
+            saved = Instant::now();
+        }
+        match reader.read_into(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // Malformed records are read whole, reading goes on with the next.
+            Err(e @ Error::Malformed(_)) if opts.lenient => {
+                if records_skipped == 0 {
+                    let e = e.at_record(records, offset()).in_file(path);
+                    warn!("skipped {}", e);
+                }
+                records += 1;
+                records_skipped += 1;
+                continue;
+            }
+            Err(e) => return Err(e.at_record(records, offset()).in_file(path)),
+        }
+        records += 1;
+        if let Some(v) = progress.as_mut() {
+            v.record();
+        }
+        alignments.add(*record.flag());
+        if let Some(v) = stream.as_ref().filter(|v| v.due(records)) {
+            v.emit(records, false, &hist.summary_trimmed(opts.trim))?;
+        }
+        if opts.max_pairs.is_some_and(|v| pairs >= v) {
+            break;
+        }
+        if record.flag() & exclude_flags != 0 || !opts.count_by.selects(&record) {
+            continue;
+        };
+        if record.flag() & opts.include_flags != opts.include_flags {
+            unqualified.add(&record);
+            continue;
+        }
+        if opts
+            .read_names
+            .as_ref()
+            .is_some_and(|v| !v.matches(record.name()))
+        {
+            continue;
+        }
+        if *record.ref_id() != last_ref {
+            last_ref = *record.ref_id();
+            let name = reader.reference_name(last_ref);
+            intervals = opts
+                .targets
+                .as_ref()
+                .and_then(|targets| name.and_then(|v| targets.get(v)));
+            excluded = name.is_some_and(|v| {
+                opts.exclude_contigs
+                    .iter()
+                    .any(|pattern| glob_match(pattern, v))
+                    || opts
+                        .contigs
+                        .as_ref()
+                        .is_some_and(|contigs| !contigs.iter().any(|c| c == v))
+            });
+        }
+        if excluded
+            || opts.targets.is_some() && !intervals.is_some_and(|v| bed::contains(v, *record.pos()))
+        {
+            continue;
+        }
+        if dedup.as_mut().is_some_and(|v| v.is_duplicate(&record)) {
+            duplicates += 1;
+            continue;
+        }
+        if sampler.as_mut().is_some_and(|v| !v.keep()) {
+            continue;
+        }
+        if record.ref_id() != record.mate_ref_id() {
+            discordant_interchrom += 1;
+            continue;
+        }
+        if *record.tlen() == 0 {
+            tlen_zero += 1;
+            continue;
+        }
+        let tlen = if opts.clip_adjust {
+            record.clip_adjusted_tlen()
+        } else {
+            *record.tlen()
+        };
+        let tlen = tlen.unsigned_abs() as usize;
+        let orientation = record.orientation();
+        orientations[orientation as usize].1.add(tlen);
+        if !opts.library_type.expects(orientation) {
+            wrong_orientation += 1;
+            continue;
+        }
+        pairs += 1;
+        hist.add(tlen);
+        if converge.as_mut().is_some_and(|v| v.settled(&hist, pairs)) {
+            let bytes = counted.then(|| bytes.load(Ordering::Relaxed));
+            let size = std::fs::metadata(path).ok().map(|v| v.len());
+            early_stop = Some(EarlyStop {
+                records,
+                pairs,
+                bytes,
+                fraction: bytes
+                    .zip(size.filter(|v| *v > 0))
+                    .map(|(read, size)| (read as f64 / size as f64).min(1.0)),
+            });
+        }
+        if let Some(v) = dump.as_mut() {
+            v.write(tlen, reader.reference_name(*record.ref_id()), *record.pos())?;
+        }
+        // Mates overlap wholly and read into adapter below the read length.
+        let read_length = opts.read_length.unwrap_or(*record.seq_len() as usize);
+        if read_length > 0 {
+            sequenced_pairs += 1;
+            short_inserts += (tlen < read_length) as u64;
+        }
+        if let Some(v) = tail.as_mut().filter(|_| tlen > opts.upper) {
+            v.add(tlen);
+        }
+        if let Some(v) = fragments.as_mut() {
+            v.add(tlen, opts.read_length.unwrap_or(*record.seq_len() as usize));
+        }
+        if opts.per_chromosome && *record.ref_id() >= 0 {
+            let rid = *record.ref_id() as usize;
+            if rid >= chromosomes.len() {
+                chromosomes.resize_with(rid + 1, || None);
+            }
+            chromosomes[rid].get_or_insert_with(&new_hist).add(tlen);
+        }
+        if let Some(gc) = record.gc().filter(|_| opts.per_gc) {
+            gc_strata[gc_stratum(gc)].add(tlen);
+        }
+        if let Some(k) = mapq_bucket(&opts.mapq_buckets, *record.mapq()) {
+            mapq_buckets[k].add(tlen);
+        }
+        if opts.per_read_group && !record.read_group().is_empty() {
+            match read_groups.get_mut(record.read_group()) {
+                Some(v) => v.add(tlen),
+                None => {
+                    let mut v = new_hist();
+                    v.add(tlen);
+                    rg_order.push(record.read_group().to_vec());
+                    read_groups.insert(record.read_group().to_vec(), v);
+                }
+            }
+        }
+        if early_stop.is_some() {
+            break;
+        }
+    }
+    if let Some(v) = &progress {
+        v.finish();
+    }
+    if let Some(v) = dump {
+        v.finish()?;
+    }
+    info!(
+        "{}: scanned {} records, {} pairs counted in {:.2?}",
+        path,
+        records,
+        pairs,
+        started.elapsed()
+    );
+    debug!(
+        "{}: {} duplicates, {} interchromosomal, {} TLEN zero, {} wrong orientation",
+        path, duplicates, discordant_interchrom, tlen_zero, wrong_orientation
+    );
+    if let Some(v) = &early_stop {
+        info!(
+            "{}: converged after {} pairs, {} of the file read",
+            path,
+            v.pairs,
+            v.fraction.map_or_else(
+                || "unknown part".to_string(),
+                |v| format!("{:.1}%", v * 100.0)
+            )
+        );
+        if reader.header().sort_order() == Some("coordinate") {
+            warn!(
+                "{}: coordinate sorted, converged on the first references only, the estimate may be biased.",
+                path
+            );
+        }
+    }
+    if records_skipped > 0 {
+        warn!("{}: {} malformed records skipped.", path, records_skipped);
+    }
+    if reader.missing_eof() {
+        let e = Error::NoEof { records }.in_file(path);
+        if opts.strict {
+            return Err(e);
+        }
+        warn!("{}", e);
+    }
+
+    let chromosomes = chromosomes
+        .into_iter()
+        .enumerate()
+        .filter_map(|(k, v)| {
+            let name = reader.reference_name(k as i32).unwrap_or("*").to_string();
+            v.map(|v| (name, v))
+        })
+        .collect();
+    // Read groups in header order, empty ones included, then those missing from header.
+    let mut groups = Vec::new();
+    if opts.per_read_group {
+        for id in reader.read_groups() {
+            let hist = read_groups.remove(id.as_bytes()).unwrap_or_else(new_hist);
+            groups.push((id.clone(), hist));
+        }
+        for id in rg_order {
+            if let Some(hist) = read_groups.remove(&id) {
+                groups.push((String::from_utf8_lossy(&id).into_owned(), hist));
+            }
+        }
+    }
+    let mut hists = Histograms {
+        all: hist,
+        chromosomes,
+        read_groups: groups,
+        gc_strata: GC_STRATA
+            .iter()
+            .map(|v| v.to_string())
+            .zip(gc_strata)
+            .collect(),
+        mapq_buckets: mapq_bucket_names(&opts.mapq_buckets)
+            .into_iter()
+            .zip(mapq_buckets)
+            .collect(),
+        orientations,
+        trim: opts.trim,
+        percentiles: opts.percentiles.clone(),
+        expected_sizes: opts.expected_sizes.clone(),
+        expected_tolerance: opts.expected_tolerance,
+        fit_gmm: opts.fit_gmm,
+        cfdna: opts.cfdna,
+        atac: opts.atac,
+        discordant_interchrom,
+        tlen_zero,
+        wrong_orientation,
+        library_type: opts.library_type,
+        records_skipped,
+        alignments,
+        unqualified,
+        sequenced_pairs,
+        short_inserts,
+        fragments,
+        duplicates,
+        tail: tail.or_else(|| opts.tail_report.then(Tail::default)),
+        early_stop,
+        sample_name: reader.header().sample_name(),
+        reference_genome: reader.header().reference_genome(),
+    };
+    if let Some(fraction) = opts.auto_upper {
+        let upper = hists.all.covering_upper(fraction);
+        debug!("{}: upper settled at {}", path, upper);
+        hists.set_upper(upper);
+    }
+    if let Some(v) = &stream {
+        v.emit(records, true, &hists.summary())?;
+    }
+    Ok(hists)
+}
+
+/// Records between checks whether a checkpoint is due.
+const CHECKPOINT_CHECK_EVERY: u64 = 1 << 16;
+
+/// Error of resuming with other options than the checkpoint was saved with.
+fn resume_mismatch() -> Error {
+    Error::Invalid("Checkpoint was saved with other options, resume with the same.".to_string())
+}
+
+/// Fail for inputs and options whose scan state checkpoints can not hold.
+fn check_resumable(path: &str, opts: &Options) -> Result<()> {
+    let unsupported = [
+        (
+            path == "-" || http::is_url(path) || opts.htsget,
+            "a local bam file",
+        ),
+        (!opts.regions.is_empty(), "no regions"),
+        (opts.threads > 1, "one thread"),
+        (opts.per_read_group, "no per read group counts"),
+        (opts.dedup_capacity.is_some(), "no dedup on the fly"),
+        (opts.sample_fraction.is_some(), "no sampling"),
+        (
+            opts.adapter_length.is_some() || opts.read_length.is_some(),
+            "no fragment sizes",
+        ),
+        (opts.tail_report, "no tail report"),
+        (opts.dump_tlens.is_some(), "no insert size dump"),
+        (opts.converge_tolerance.is_some(), "no convergence stop"),
+    ];
+    match unsupported.iter().find(|v| v.0) {
+        Some((_, need)) => Err(Error::Invalid(format!("Checkpoints need {}.", need))),
+        None => Ok(()),
+    }
+}
+
+/// Insert size summary of bam, sam or cram at `path`, `-` for stdin.
+pub fn compute_insert_sizes(path: &str, opts: &Options) -> Result<Summary> {
+    Ok(compute_histograms(path, opts)?.summary())
+}
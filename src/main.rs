@@ -1,403 +1,1361 @@
-extern crate byteorder;
+extern crate insize;
 #[macro_use]
 extern crate clap;
-extern crate flate2;
-extern crate plotlib;
-extern crate plotters;
+extern crate env_logger;
+#[macro_use]
+extern crate log;
+extern crate rayon;
 extern crate serde;
 extern crate serde_json;
 
-use std::fs::File;
-use std::io::ErrorKind::{InvalidData, UnexpectedEof};
-use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result};
+mod commands;
 
-use byteorder::{LittleEndian, ReadBytesExt};
-use clap::{App, AppSettings};
-use flate2::read::MultiGzDecoder;
-use plotters::prelude::*;
-use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use plotlib::page::Page;
-use plotlib::repr::Plot;
-use plotlib::style::{LineJoin, LineStyle};
-use plotlib::view::ContinuousView;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use commands::{compare::compare, hist2plot::hist2plot, merge::merge, serve::serve, watch::watch};
+use env_logger::Builder;
+use insize::bgzf::{self, Decompressor};
+use insize::checkpoint::DEFAULT_CHECKPOINT_EVERY;
+use insize::converge::DEFAULT_CONVERGE_MIN_PAIRS;
+use insize::dedup::DEFAULT_DEDUP_CAPACITY;
+use insize::error::create_file;
+use insize::hist::{DEFAULT_EXPECTED_TOLERANCE, DEFAULT_TRIM};
+use insize::http::is_url;
+use insize::metrics::spawn_exporter;
+use insize::multiqc::{write_histograms, write_summaries};
+use insize::picard::write_metrics;
+use insize::plot::{
+    parse_color, plot, Annotation, CurveStyle, Kernel, Lang, PicFormat, PlotOptions, Theme, YMax,
+    YValue,
+};
+use insize::report::DEFAULT_OUTLIER_MADS;
+use insize::samtools::write_stats;
+use insize::serve::{DEFAULT_MAX_UPLOAD, DEFAULT_PORT};
+use insize::stream::DEFAULT_STREAM_EVERY;
+use insize::table::{
+    write_histograms as write_table, write_histograms_json, write_summaries as write_summary_table,
+};
+use insize::watch::DEFAULT_WATCH_INTERVAL;
+use insize::{
+    compute_histograms, parse_flags, Cohort, Config, Error, FailIf, GenomeBuild, Histograms,
+    InsertSizeHistogram, KsTest, LibraryType, Metrics, Options, ReadNames, Reference, Result,
+    SampleSheet, Summary, Targets, ATAC_UPPER, CFDNA_UPPER, DEFAULT_EXCLUDE_FLAGS,
+    DEFAULT_INCLUDE_FLAGS,
+};
+use log::{Level, LevelFilter};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use serde::ser::{Serialize, Serializer};
 
-/// Read is paired, first in pair, properly mapped.
-const P_FLAG: u16 = 0x1 + 0x2 + 0x40;
-/// Read is secondary or supplementary.
-const N_FLAG: u16 = 0x100 + 0x800;
+/// Exit code when an input fails `--fail-if`, errors exit with 1.
+const QC_FAILED: i32 = 2;
 
-fn opterr() -> std::io::Error {
-    Error::new(InvalidData, "Option error.")
+fn opterr() -> Error {
+    Error::Invalid("Option error.".to_string())
 }
 
-struct BamReader<T: BufRead> {
-    reader: T,
+/// Comma separated insert sizes, e.g. `180,260,310`.
+fn parse_sizes(v: &str) -> Result<Vec<usize>> {
+    v.split(',')
+        .map(|v| v.trim().parse().map_err(|_| opterr()))
+        .collect()
 }
 
-impl BamReader<BufReader<MultiGzDecoder<File>>> {
-    /// Read a bam file from path.
-    fn from_path(v: &str) -> Result<Self> {
-        let mut file = BufReader::with_capacity(16 * 1024, MultiGzDecoder::new(File::open(v)?));
-
-        // Magic header.
-        let mut magic = [0u8; 4];
-        file.read_exact(&mut magic)?;
-        if magic != [b'B', b'A', b'M', 1] {
-            return Err(Error::new(InvalidData, "Wrong BAM magic."));
-        };
+/// Components of `--fit-gmm`, at least one.
+fn parse_components(args: &Args) -> Result<Option<usize>> {
+    args.value_of("fit-gmm")
+        .map(|v| {
+            v.parse::<usize>()
+                .ok()
+                .filter(|v| *v > 0)
+                .ok_or_else(opterr)
+        })
+        .transpose()
+}
 
-        // Header text.
-        let l_text = file.read_i32::<LittleEndian>()?;
-        let mut _text = vec![0u8; l_text as usize];
-        file.read_exact(&mut _text)?;
-
-        // Reference and length.
-        let n_ref: u32 = file.read_u32::<LittleEndian>()?;
-        for _ in 0..n_ref {
-            let block_size: usize =
-                file.read_u32::<LittleEndian>()? as usize + std::mem::size_of::<u32>();
-            let mut _ref_entry = vec![0u8; block_size];
-            file.read_exact(&mut _ref_entry)?;
-        }
+/// Whether `--split-by` draws a curve per read group, `rg` is the only group so far.
+fn split_by_rg(args: &Args) -> Result<bool> {
+    match args.value_of("split-by") {
+        None => Ok(false),
+        Some("rg") => Ok(true),
+        Some(v) => Err(Error::Invalid(format!("Wrong split: {}, use rg.", v))),
+    }
+}
 
-        Ok(Self { reader: file })
+/// Comma separated ascending MAPQ lower bounds, e.g. `0,1,30,60`.
+fn parse_mapq_bounds(v: &str) -> Result<Vec<u8>> {
+    let bounds: Vec<u8> = v
+        .split(',')
+        .map(|v| v.trim().parse().map_err(|_| opterr()))
+        .collect::<Result<_>>()?;
+    if bounds.windows(2).any(|v| v[0] >= v[1]) {
+        return Err(Error::Invalid(format!(
+            "MAPQ bounds must be ascending: {}",
+            v
+        )));
     }
+    Ok(bounds)
 }
 
-impl<T: BufRead> BamReader<T> {
-    /// Read a record (one line of bam).
-    fn read_into(&mut self, record: &mut Record) -> Result<bool> {
-        let mut rem_size = match self.reader.read_u32::<LittleEndian>() {
-            Ok(value) => value as usize,
-            Err(e) => {
-                if e.kind() == UnexpectedEof {
-                    return Ok(false);
-                } else {
-                    return Err(e);
-                }
-            }
-        };
-        let mut _sink8 = [0u8; 1];
-        let mut _sink16 = [0u8; 2];
-        let mut _sink32 = [0u8; 4];
-        // Ref id.
-        record.set_ref_id(self.reader.read_i32::<LittleEndian>()?);
-        // Ref position.
-        self.reader.read_exact(&mut _sink32)?;
-        // Query name length.
-        let _l_name = self.reader.read_u8()? as usize;
-        // Mapq.
-        self.reader.read_exact(&mut _sink8)?;
-        // Bin.
-        self.reader.read_exact(&mut _sink16)?;
-        // Number of operations in CIGAR.
-        let _l_cigar = self.reader.read_u16::<LittleEndian>()? as usize;
-        // Flag.
-        record.set_flag(self.reader.read_u16::<LittleEndian>()?);
-        // Sequence length.
-        let _l_seq = self.reader.read_u32::<LittleEndian>()? as usize;
-        // Mate ref id.
-        record.set_mate_ref_id(self.reader.read_i32::<LittleEndian>()?);
-        // Mate pos
-        self.reader.read_exact(&mut _sink32)?;
-        // Template length.
-        record.set_tlen(self.reader.read_i32::<LittleEndian>()?);
-        // Query name
-        self.reader.read_exact(&mut vec![0u8; _l_name])?;
-        // Cigar.
-        self.reader
-            .read_u32_into::<LittleEndian>(&mut vec![0u32; _l_cigar])?;
-        // Sequence.
-        self.reader.read_exact(&mut vec![0u8; (_l_seq + 1) / 2])?;
-        // Quality.
-        self.reader.read_exact(&mut vec![0u8; _l_seq])?;
-        rem_size -= 32 + _l_name + _l_cigar * 4 + (_l_seq + 1) / 2 + _l_seq;
-        // Optinal fields.
-        self.reader.read_exact(&mut vec![0u8; rem_size])?;
-        Ok(true)
-    }
-}
-
-/// Compact read record.
-#[derive(Default)]
-struct Record {
-    ref_id: i32,
-    mate_ref_id: i32,
-    tlen: i32,
-    flag: u16,
-}
-
-impl Record {
-    fn flag(&self) -> &u16 {
-        &self.flag
-    }
-
-    fn set_flag(&mut self, v: u16) {
-        self.flag = v
-    }
-
-    fn tlen(&self) -> &i32 {
-        &self.tlen
-    }
-
-    fn set_tlen(&mut self, v: i32) {
-        self.tlen = v
-    }
-
-    fn ref_id(&self) -> &i32 {
-        &self.ref_id
-    }
-
-    fn set_ref_id(&mut self, v: i32) {
-        self.ref_id = v
-    }
-
-    fn mate_ref_id(&self) -> &i32 {
-        &self.mate_ref_id
-    }
-
-    fn set_mate_ref_id(&mut self, v: i32) {
-        self.mate_ref_id = v
-    }
-}
-
-#[derive(Default)]
-struct Summary {
-    // Pair count all.
-    all_count: u32,
-    // Insert size mean in all.
-    all_mean: f64,
-    // Pair count.
-    count: u32,
-    // Insert size mean.
-    mean: f64,
-    // Insert size standard deviation.
-    std: f64,
-    // First quantile.
-    q1: usize,
-    // Second quantile.
-    q2: usize,
-    // Third quantile.
-    q3: usize,
-}
-
-impl Serialize for Summary {
+/// Summaries keyed by sample name, in input order.
+struct Samples<'a>(&'a [(String, Summary)]);
+
+impl Serialize for Samples<'_> {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Color", 8)?;
-        state.serialize_field("Total count", &self.all_count)?;
-        state.serialize_field(
-            "Total mean insert size",
-            &format!("{:.2}", self.all_mean).parse::<f64>().unwrap(),
-        )?;
-        state.serialize_field("Qualified read count", &self.count)?;
-        state.serialize_field(
-            "Qualified mean insize size",
-            &format!("{:.2}", self.mean).parse::<f64>().unwrap(),
-        )?;
-        state.serialize_field(
-            "Qualified insert size SD",
-            &format!("{:.2}", self.std).parse::<f64>().unwrap(),
-        )?;
-        state.serialize_field("Qualified Q1", &self.q1)?;
-        state.serialize_field("Qualified Q2", &self.q2)?;
-        state.serialize_field("Qualified Q3", &self.q3)?;
-        state.end()
-    }
-}
-
-/// Get a proper upper limit value for figure axis.
-fn round_max(mut v: f64) -> f64 {
-    let mut digits = 0i32;
-    if v >= 10f64 {
-        while v >= 10f64 {
-            v /= 10f64;
-            digits += 1;
-        }
-    } else if v < 1f64 {
-        while v < 1f64 {
-            v *= 10f64;
-            digits -= 1;
-        }
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
     }
-    (v.ceil() + 0.1f64) * 10f64.powi(digits)
 }
 
-fn cli(bam: &str, pic: &str, upper: &usize, format: &PicFormat) -> Result<()> {
-    let mut data = vec![0u32; *upper + 1];
-    let mut record = Record::default();
-    let mut reader = BamReader::from_path(bam)?;
-    let mut sum = Summary::default();
-
-    while reader.read_into(&mut record)? {
-        if record.flag() & P_FLAG != P_FLAG
-            || record.flag() & N_FLAG != 0
-            || record.ref_id() != record.mate_ref_id()
-        {
-            continue;
-        };
-        let tlen = record.tlen().abs() as usize;
-        sum.all_mean += tlen as f64;
-        sum.all_count += 1;
-        if &tlen > upper {
-            continue;
-        };
-        data[tlen] += 1;
-        sum.mean += tlen as f64;
-        sum.count += 1;
-    }
-    sum.all_mean /= sum.all_count as f64;
-    sum.mean /= sum.count as f64;
-
-    // Calculate quantiles and std.
-    let mut quantiles = {
-        let tmp = sum.count as f64;
-        vec![
-            ((tmp * 0.25f64) as u32, 0usize),
-            ((tmp * 0.5f64) as u32, 0usize),
-            ((tmp * 0.75f64) as u32, 0usize),
-        ]
+/// Sample name from input path, file name without extension.
+fn sample_name(bam: &str) -> String {
+    if bam == "-" {
+        return "stdin".to_string();
+    }
+    // Leave out url queries, e.g. of presigned urls.
+    let bam = match bam.split_once('?') {
+        Some((path, _)) if is_url(bam) => path,
+        _ => bam,
     };
+    Path::new(bam)
+        .file_stem()
+        .map(|v| v.to_string_lossy().into_owned())
+        .unwrap_or_else(|| bam.to_string())
+}
 
-    let mut ri = 0usize;
-    let mut flag = true;
-    let mut accum: u32 = 0;
-
-    let mut height_max: u32 = 0;
-    data.iter().enumerate().for_each(|(k, v)| {
-        accum += v;
-        if flag {
-            let (index, value) = &mut quantiles[ri];
-            if accum > *index {
-                *value = k;
-                ri += 1;
+/// Inputs keyed by sample name, the path as given when file names collide.
+fn named<'a>(bams: &[&'a str]) -> Vec<(String, &'a str)> {
+    let mut inputs: Vec<(String, &str)> = Vec::with_capacity(bams.len());
+    for bam in bams {
+        let mut name = sample_name(bam);
+        if inputs.iter().any(|(v, _)| v == &name) {
+            name = bam.to_string();
+        }
+        inputs.push((name, bam));
+    }
+    inputs
+}
+
+/// Curves of one input, one per orientation, GC stratum, MAPQ bucket or read group with pairs
+/// or one of all pairs, group curves prefixed by `name` when given.
+fn curves(name: Option<&str>, hists: &Histograms, out: &Outputs) -> Vec<(String, Vec<u32>)> {
+    let groups: Vec<(&str, &InsertSizeHistogram)> = if out.orientation_curves {
+        hists
+            .orientations
+            .iter()
+            .map(|(o, v)| (o.name(), v))
+            .collect()
+    } else if out.gc_curves {
+        hists
+            .gc_strata
+            .iter()
+            .map(|(k, v)| (k.as_str(), v))
+            .collect()
+    } else if out.mapq_curves {
+        hists
+            .mapq_buckets
+            .iter()
+            .map(|(k, v)| (k.as_str(), v))
+            .collect()
+    } else if out.rg_curves {
+        hists
+            .read_groups
+            .iter()
+            .map(|(k, v)| (k.as_str(), v))
+            .collect()
+    } else {
+        return vec![(
+            name.unwrap_or_default().to_string(),
+            hists.all.counts().to_vec(),
+        )];
+    };
+    groups
+        .into_iter()
+        .filter(|(_, v)| v.count() > 0)
+        .map(|(group, v)| {
+            let label = match name {
+                Some(name) => format!("{} {}", name, group),
+                None => group.to_string(),
             };
-            flag = ri < quantiles.len();
-        };
-        sum.std += (k as f64 - sum.mean).powi(2);
-        height_max = u32::max(*v, height_max);
+            (label, v.counts().to_vec())
+        })
+        .collect()
+}
+
+/// MultiQC content path without a pic or summary json, in the working directory.
+const MQC_BASE: &str = "insert_size";
+
+/// Path next to the pic, with extension replaced by `suffix`.
+fn mqc_path(pic: &str, suffix: &str) -> PathBuf {
+    let path = Path::new(pic);
+    let stem = path
+        .file_stem()
+        .map(|v| v.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}{}", stem, suffix))
+}
+
+/// Output files and how to draw them.
+struct Outputs<'a> {
+    /// Pic paths and their formats, nothing is drawn without.
+    pics: Vec<(&'a str, PicFormat)>,
+    /// Format of per sample pics of batch runs.
+    format: PicFormat,
+    /// Picard metrics path.
+    picard: Option<&'a str>,
+    /// Samtools stats insert size section path.
+    samtools_stats: Option<&'a str>,
+    /// Histogram table path.
+    hist: Option<&'a str>,
+    /// Summary json path.
+    json: Option<&'a str>,
+    /// Print summary to stdout even with a json path.
+    print_summary: bool,
+    /// Summaries go to stdout as ndjson while reading, no summary json is printed at the end.
+    stream_ndjson: bool,
+    /// Write MultiQC custom content next to the pic.
+    multiqc: bool,
+    /// Draw a curve per pair orientation.
+    orientation_curves: bool,
+    /// Draw a curve per GC stratum.
+    gc_curves: bool,
+    /// Draw a curve per MAPQ bucket.
+    mapq_curves: bool,
+    /// Draw a curve per read group.
+    rg_curves: bool,
+    /// Distribution each input is tested against.
+    reference_hist: Option<Reference>,
+    /// Thresholds failing QC of an input.
+    fail_if: Option<FailIf>,
+    /// Inputs processed at once.
+    jobs: usize,
+    /// Directory of per sample pics and summaries.
+    outdir: Option<&'a str>,
+    /// Cohort report path.
+    cohort_report: Option<&'a str>,
+    /// Samples this many MADs from the cohort median are outliers.
+    outlier_mads: f64,
+    plot: PlotOptions,
+}
+
+impl Outputs<'_> {
+    /// Leave out files of a run, e.g. of a server writing per sample, return whether any was
+    /// given.
+    fn clear_files(&mut self) -> bool {
+        let given = !self.pics.is_empty()
+            || self.picard.is_some()
+            || self.samtools_stats.is_some()
+            || self.hist.is_some()
+            || self.json.is_some()
+            || self.multiqc
+            || self.outdir.is_some()
+            || self.cohort_report.is_some();
+        self.pics.clear();
+        self.picard = None;
+        self.samtools_stats = None;
+        self.hist = None;
+        self.json = None;
+        self.multiqc = false;
+        self.outdir = None;
+        self.cohort_report = None;
+        given
+    }
+}
+
+/// Values keyed by sample name, in input order.
+type Named<T> = Vec<(String, T)>;
+
+/// Histograms and summaries of inputs keyed by sample name, after writing the pic, tables and
+/// metrics.
+fn write_outputs(
+    inputs: &[(String, &str)],
+    out: &Outputs,
+    opts: &Options,
+) -> Result<(Named<Histograms>, Named<Summary>)> {
+    if opts.dump_tlens.is_some() && inputs.len() > 1 {
+        return Err(Error::Invalid("--dump-tlens takes one input.".to_string()));
+    }
+    if (opts.checkpoint.is_some() || opts.resume.is_some()) && inputs.len() > 1 {
+        return Err(Error::Invalid(
+            "--checkpoint and --resume take one input.".to_string(),
+        ));
+    }
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(out.jobs)
+        .build()
+        .map_err(|e| Error::Invalid(e.to_string()))?;
+    // Results keep input order, the first failed input is reported.
+    let computed: Vec<Result<(Histograms, Summary)>> = pool.install(|| {
+        inputs
+            .par_iter()
+            .map(|(_, bam)| {
+                let hist = compute_histograms(bam, opts)?;
+                let started = Instant::now();
+                let mut sum = hist.summary();
+                if let Some(reference) = &out.reference_hist {
+                    sum.reference = KsTest::new(&hist.all, reference);
+                    if sum.reference.is_none() {
+                        warn!("{}: no pairs to test against the reference.", bam);
+                    }
+                }
+                info!("{}: stats in {:.2?}", bam, started.elapsed());
+                Ok((hist, sum))
+            })
+            .collect()
     });
-    sum.std = (sum.std / (sum.count as f64)).powf(0.5f64);
-    unsafe {
-        sum.q1 = quantiles.get_unchecked(0).1;
-        sum.q2 = quantiles.get_unchecked(1).1;
-        sum.q3 = quantiles.get_unchecked(2).1;
-    }
-    let height_max: f64 = round_max((height_max as f64) / (sum.count as f64));
-
-    // Plot line.
-    match format {
-        PicFormat::Svg => {
-            let line = Plot::new(
-                data.into_iter()
-                    .enumerate()
-                    .map(|(i, j)| (i as f64, (j as f64) / (sum.count as f64)))
-                    .collect(),
-            )
-            .line_style(
-                LineStyle::new()
-                    .colour("#FF0000")
-                    .linejoin(LineJoin::Round)
-                    .width(1.0),
-            );
-            let view = ContinuousView::new()
-                .add(line)
-                .x_label("插入片段大小(bp)")
-                .y_label("比例");
-            Page::single(&view)
-                .save(pic)
-                .map_err(|_| Error::new(InvalidData, format!("Failed to write {}", pic)))?;
+    let mut hists = Vec::with_capacity(inputs.len());
+    let mut sums: Named<Summary> = Vec::with_capacity(inputs.len());
+    for ((name, _), computed) in inputs.iter().zip(computed) {
+        let (hist, sum) = computed?;
+        hists.push((name.clone(), hist));
+        sums.push((name.clone(), sum));
+    }
+
+    // Group curves are prefixed by sample name for several inputs.
+    let samples: Vec<(String, Vec<u32>)> = hists
+        .iter()
+        .flat_map(|(k, v)| {
+            let name = if inputs.len() == 1
+                && (out.orientation_curves || out.gc_curves || out.mapq_curves || out.rg_curves)
+            {
+                None
+            } else {
+                Some(k.as_str())
+            };
+            curves(name, v, out)
+        })
+        .collect();
+    // Upper may be settled per input by `--auto-upper`, sparse sizes are not drawn.
+    let upper = hists
+        .iter()
+        .map(|(_, v)| v.all.counts().len() - 1)
+        .max()
+        .unwrap_or(opts.upper);
+    let plot_opts = titled(&out.plot, hists.iter().map(|(_, v)| v));
+    // Pics of every format are drawn from the same histograms.
+    for (pic, format) in &out.pics {
+        let started = Instant::now();
+        plot(pic, &upper, format, &samples, &plot_opts)?;
+        info!("{}: rendered in {:.2?}", pic, started.elapsed());
+    }
+    if let Some(path) = out.picard {
+        write_metrics(BufWriter::new(create_file(path)?), &hists)?;
+    }
+    if let Some(path) = out.samtools_stats {
+        write_stats(BufWriter::new(create_file(path)?), &hists)?;
+    }
+    if let Some(path) = out.hist {
+        let bin_size = out.plot.bin_size.unwrap_or(1);
+        let file = BufWriter::new(create_file(path)?);
+        // Json for `.json`, comma separated for `.csv`, tab separated otherwise.
+        if path.ends_with(".json") {
+            write_histograms_json(file, &hists, bin_size)?;
+        } else {
+            let sep = if path.ends_with(".csv") { ',' } else { '\t' };
+            write_table(file, &hists, sep, bin_size)?;
         }
-        PicFormat::Png => {
-            let root = BitMapBackend::new(pic, (700, 610)).into_drawing_area();
-            root.fill(&WHITE)
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-
-            let mut chart = ChartBuilder::on(&root)
-                .x_label_area_size(35)
-                .y_label_area_size(40)
-                .margin(5)
-                .build_cartesian_2d(
-                    (0f64..((upper + 1) as f64))
-                        .step(1.0)
-                        .use_round()
-                        .into_segmented(),
-                    0f64..height_max,
-                )
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-
-            chart
-                .configure_mesh()
-                .disable_mesh()
-                .bold_line_style(&WHITE.mix(0.3))
-                .x_desc("插入片段大小(bp)")
-                .y_desc("比例")
-                .axis_desc_style((FontFamily::Name("WenQuanYi Zen Hei"), 20))
-                .draw()
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-
-            chart
-                .draw_series(LineSeries::new(
-                    data.into_iter().enumerate().map(|(i, j)| {
-                        (
-                            SegmentValue::Exact(i as f64),
-                            (j as f64) / (sum.count as f64),
-                        )
-                    }),
-                    RED.stroke_width(2),
-                ))
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    }
+    if out.multiqc {
+        // Named after the pic, or the summary json without one.
+        let base = out
+            .pics
+            .first()
+            .map(|v| v.0)
+            .or(out.json)
+            .unwrap_or(MQC_BASE);
+        write_histograms(create_file(mqc_path(base, "_mqc.json"))?, &samples)?;
+        write_summaries(create_file(mqc_path(base, "_stats_mqc.json"))?, &sums)?;
+    }
+    for (name, sum) in &sums {
+        let missing: Vec<String> = sum
+            .expected
+            .iter()
+            .filter(|v| v.missing)
+            .map(|v| v.size.to_string())
+            .collect();
+        if !missing.is_empty() {
+            warn!("missing expected sizes of {}: {}", name, missing.join(", "));
         }
     }
+    if let Some(dir) = out.outdir {
+        write_samples(Path::new(dir), &hists, &sums, out)?;
+    }
+    if let Some(path) = out.cohort_report {
+        let cohort = Cohort::new(&sums, out.outlier_mads);
+        cohort.write(path, &path.parse()?)?;
+        let outliers: Vec<&str> = cohort.outliers().collect();
+        if !outliers.is_empty() {
+            warn!("outlier samples by median: {}", outliers.join(", "));
+        }
+    }
+    Ok((hists, sums))
+}
+
+/// Write a pic and summary json of each sample and a table of all summaries into `dir`.
+fn write_samples(
+    dir: &Path,
+    hists: &[(String, Histograms)],
+    sums: &[(String, Summary)],
+    out: &Outputs,
+) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| Error::from(e).in_file(&dir.display().to_string()))?;
+    for ((name, hist), (_, sum)) in hists.iter().zip(sums) {
+        write_sample(dir, name, hist, sum, out)?;
+    }
+    write_summary_table(
+        BufWriter::new(create_file(dir.join("summary.tsv"))?),
+        sums,
+        '\t',
+    )?;
+    Ok(())
+}
 
-    println!(
+/// Write a pic and summary json of sample `name` into `dir`.
+fn write_sample(
+    dir: &Path,
+    name: &str,
+    hist: &Histograms,
+    sum: &Summary,
+    out: &Outputs,
+) -> Result<()> {
+    let pic = dir.join(format!("{}.{}", sample_stem(name), out.format.extension()));
+    let pic = pic.to_str().ok_or_else(opterr)?;
+    let samples = curves(Some(name), hist, out);
+    let upper = hist.all.counts().len() - 1;
+    plot(
+        pic,
+        &upper,
+        &out.format,
+        &samples,
+        &titled(&out.plot, [hist]),
+    )?;
+    let json = serde_json::to_string_pretty(sum)?;
+    writeln!(
+        create_file(dir.join(format!("{}.json", sample_stem(name))))?,
         "{}",
-        serde_json::to_string_pretty(&sum).map_err(|e| Error::new(InvalidData, e))?
-    );
+        json
+    )?;
     Ok(())
 }
 
-enum PicFormat {
-    Svg,
-    Png,
+/// File stem of sample `name`, names falling back to paths must not leave the directory.
+fn sample_stem(name: &str) -> String {
+    name.replace(['/', '\\'], "_")
 }
 
-impl PicFormat {
-    fn from_str(v: &str) -> Result<Self> {
-        if v.ends_with(".svg") || v.ends_with(".SVG") {
-            Ok(Self::Svg)
-        } else if v.ends_with(".png") || v.ends_with(".PNG") {
-            Ok(Self::Png)
-        } else {
-            Err(Error::new(ErrorKind::InvalidData, "No such option."))
+/// Write summary json to the json path and/or stdout.
+fn write_json<T: Serialize>(value: &T, out: &Outputs) -> Result<()> {
+    let json = serde_json::to_string_pretty(value)?;
+    if let Some(path) = out.json {
+        writeln!(create_file(path)?, "{}", json)?;
+    }
+    if (out.json.is_none() || out.print_summary) && !out.stream_ndjson {
+        println!("{}", json);
+    }
+    Ok(())
+}
+
+/// Exit with `QC_FAILED` when any input fails `--fail-if`, after all outputs are written.
+fn check_qc(sums: &[(String, Summary)], out: &Outputs) {
+    let fail_if = match &out.fail_if {
+        Some(v) => v,
+        None => return,
+    };
+    let mut failed = false;
+    for (name, sum) in sums {
+        let failures = fail_if.failures(sum);
+        if !failures.is_empty() {
+            error!("QC failed for {}: {}", name, failures.join(" || "));
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(QC_FAILED);
+    }
+}
+
+fn cli(inputs: &[(String, &str)], out: &Outputs, opts: &Options) -> Result<()> {
+    let (_, sums) = write_outputs(inputs, out, opts)?;
+    if sums.len() == 1 {
+        write_json(&sums[0].1, out)?;
+    } else {
+        write_json(&Samples(&sums), out)?;
+    }
+    check_qc(&sums, out);
+    Ok(())
+}
+
+/// Options of reading and counting inputs, shared by commands reading inputs.
+const COUNT_OPTIONS: &str = "
+        [config] --config=[FILE] 'Defaults of options from a TOML file, keys named like long options, e.g. `upper = 800`, options given here take precedence.'
+        [upper] -m=[NUMBER] 'Maximum insert size to record, default 500 (20000 for mate-pair, 700 with --cfdna, 1000 with --atac), large sizes are stored sparsely.'
+        [library-type] --library-type=[TYPE] 'Library type, paired-end or mate-pair (jumping library of RF pairs with multi-kb inserts, FR pairs are left out and reported as contamination), default paired-end.'
+        [per-chromosome] --per-chromosome 'Also summarize pairs of each reference.'
+        [per-read-group] --per-read-group 'Also summarize pairs of each read group (RG tag).'
+        [per-gc] --per-gc 'Also summarize pairs by GC of the counted read, in 20% strata.'
+        [by-mapq] --by-mapq=[LIST] 'Also summarize pairs by MAPQ of the counted read, in buckets from comma separated ascending lower bounds, e.g. `0,1,30,60` for 0, 1-29, 30-59 and 60+.'
+        [no-dups] --no-dups 'Skip reads marked as duplicate (0x400).'
+        [include-qcfail] --include-qcfail 'Count reads failing platform/vendor QC (0x200), skipped by default.'
+        [count-by] --count-by=[MODE] 'Read counting a pair, read1, read2 or either-dedup (either read with positive TLEN, like samtools stats), default read1.'
+        [auto-upper] --auto-upper 'Pick the maximum insert size covering --auto-upper-cover percent of pairs instead of -m.'
+        [auto-upper-cover] --auto-upper-cover=[PERCENT] 'Percent of pairs below the picked maximum of --auto-upper, default 99.5.'
+        [tail-report] --tail-report 'Also summarize pairs above the maximum insert size, count, mean, 99th percentile and largest, e.g. of SV oriented or linked-read libraries.'
+        [adapter-length] --adapter-length=[NUMBER] 'Also estimate physical fragment sizes, insert size plus NUMBER adapter bases at each end, in the summary.'
+        [read-length] --read-length=[NUMBER] 'Read length for inner distances of fragment sizes, insert size less both reads, SEQ length of each read by default.'
+        [dedup-on-the-fly] --dedup-on-the-fly 'Count pairs of the same reference, start, mate start and orientation once, approximate dedup of bams without duplicates marked.'
+        [dedup-capacity] --dedup-capacity=[NUMBER] 'Pairs remembered by --dedup-on-the-fly for unsorted input, default 1048576.'
+        [clip-adjust] --clip-adjust 'Extend insert size by soft clips at outer ends of the pair, mate clips need the MC tag.'
+        [include-flags] --include-flags=[FLAGS] 'Only count reads with all FLAGS set, number or names like samtools, default PAIRED,PROPER_PAIR (0x3).'
+        [exclude-flags] --exclude-flags=[FLAGS] 'Skip reads with any of FLAGS set, default SECONDARY,QCFAIL,SUPPLEMENTARY (0xb00).'
+        [expected-sizes] --expected-sizes=[LIST] 'Comma separated expected insert sizes, e.g. `180,260,310` of amplicons, summarizes pairs near each, flags missing ones and marks them on the pic.'
+        [expected-tolerance] --expected-tolerance=[BP] 'Bp around an expected size counted as it, default 10.'
+        [fit-gmm] --fit-gmm=[K] 'Fit a mixture of K Gaussians to qualified pairs, e.g. of nucleosome ladders of cfDNA, reporting component weights, means and SDs and drawing them dashed on svg, png and pdf pics.'
+        [cfdna] --cfdna 'Summarize cell-free DNA, mono-, di- and tri-nucleosome peak positions (about 167, 334 and 500 bp), di- and tri- to mono-nucleosome peak height ratios and the fraction below 150 bp, -m defaults to 700.'
+        [atac] --atac 'Summarize ATAC-seq, fractions of nucleosome-free (<100 bp), mono- (180-247 bp) and di-nucleosome (315-473 bp) pairs, their ratios and the ~10.5 bp helical periodicity score, -m defaults to 1000 and pics use --log-y.'
+        [percentiles] --percentiles=[LIST] 'Comma separated percentiles to summarize, e.g. `1,5,25,50,75,95,99`.'
+        [trim] --trim=[FRACTION] 'Fraction of pairs trimmed from each end for the trimmed mean, default 0.05.'
+        [threads] --threads=[N] 'Threads for bam decompression (and cram decoding), default 1.'
+        [decompressor] --decompressor=[NAME] 'Inflater of bam blocks, flate2 or libdeflate (needs the libdeflate feature), default libdeflate when built in, for benchmarking.'
+        [sample-fraction] --sample-fraction=[FRACTION] 'Randomly keep this fraction of pairs for a quick estimate, e.g. 0.05.'
+        [max-pairs] --max-pairs=[NUMBER] 'Stop reading after counting NUMBER pairs.'
+        [converge-tolerance] --converge-tolerance=[TOLERANCE] 'Stop reading once the running median and SD change relatively by less than TOLERANCE, e.g. 0.001, between checks every 10000 pairs, reporting how much of the file was read. A coordinate sorted file is only read from its first references, which may bias the estimate.'
+        [converge-min-pairs] --converge-min-pairs=[NUMBER] 'Pairs counted before --converge-tolerance may stop reading, default 100000.'
+        [checkpoint] --checkpoint=[FILE] 'Save the scan state of one bam file to FILE every --checkpoint-every seconds, so an interrupted run can go on with --resume FILE.'
+        [checkpoint-every] --checkpoint-every=[SECONDS] 'Seconds between checkpoints of --checkpoint, default 60.'
+        [resume] --resume=[FILE] 'Go on from checkpoint FILE of an interrupted run of the same input and options instead of starting over.'
+        [seed] --seed=[NUMBER] 'Seed of --sample-fraction, same seed keeps same pairs, default 0.'
+        [progress] --progress 'Show records, bytes read, throughput and ETA on stderr.'
+        [stream-ndjson] --stream-ndjson 'Print partial summaries as newline delimited json on stdout while reading, and the final summary of each input as last line, instead of the summary json.'
+        [stream-every] --stream-every=[RECORDS] 'Records between partial summaries of --stream-ndjson, default 1000000.'
+        [strict] --strict 'Fail on bam missing the BGZF EOF marker, a likely truncated file, instead of warning.'
+        [lenient] --lenient 'Skip malformed records instead of failing, counted as Records skipped in the summary.'
+        [targets] --targets=[BED] 'Only count pairs whose alignment start falls inside BED regions.'
+        [exclude-contigs] --exclude-contigs=[LIST] 'Skip reads on references matching comma separated glob patterns, e.g. `chrM,*_decoy,HLA-*`.'
+        [primary-only] --primary-only 'Only count reads on autosomes, X and Y, `chr1` or `1` style names.'
+        [autosomes-only] --autosomes-only 'Only count reads on autosomes, `chr1` or `1` style names.'
+        [genome-build] --genome-build=[BUILD] 'Genome build of --primary-only and --autosomes-only, hg19, hg38, GRCh37, GRCh38, mm10 or mm39, default hg38.'
+        [read-name-regex] --read-name-regex=[REGEX] 'Only count reads with names matching REGEX, e.g. `^A00123:8:` for a flowcell lane.'
+        [read-names] --read-names=[FILE] 'Only count reads with names listed in FILE, one per line.'
+        [reference] -T, --reference=[FASTA] 'Reference fasta for decoding cram, cram needs `samtools` in PATH.'
+        ";
+
+/// Options of summary, table and metrics outputs, shared by commands reading inputs.
+const OUTPUT_OPTIONS: &str = "
+        [hist] --hist=[FILE] 'Also write count and fraction of each insert size to FILE, comma separated for `.csv`, json of counts and totals for `.json` (for hist2plot and merge), tab separated otherwise.'
+        [json] --json=[FILE] 'Write summary json to FILE instead of stdout.'
+        [dump-tlens] --dump-tlens=[FILE] 'Also write the insert size of every counted pair to FILE, one per line, gzip compressed for `.gz`, of one input.'
+        [dump-positions] --dump-positions 'Write reference name and 1-based position of the counted read before each insert size of --dump-tlens, tab separated.'
+        [output-prefix] --output-prefix=[PREFIX] 'Name outputs after PREFIX, `PREFIX.png` (by --format), `PREFIX.json` and `PREFIX.hist.tsv`, unless given, summary json is then printed only with --print-summary.'
+        [print-summary] --print-summary 'Print summary json to stdout even with --json.'
+        [multiqc] --multiqc 'Also write MultiQC custom content next to the pic, `<pic stem>_mqc.json` and `<pic stem>_stats_mqc.json`.'
+        [reference-hist] --reference-hist=[FILE] 'Test each input against an expected distribution, a --hist table of one sample, by Kolmogorov-Smirnov.'
+        [fail-if] --fail-if=[EXPR] 'Exit with code 2 when any input matches EXPR like `median<250 || sd>120 && count<1e6`, after writing outputs.'
+        [picard-metrics] --picard-metrics=[FILE] 'Also write Picard CollectInsertSizeMetrics compatible metrics to FILE.'
+        [samtools-stats] --samtools-stats=[FILE] 'Also write the insert size (IS) section of samtools stats to FILE, e.g. for plot-bamstats.'
+        [jobs] -j, --jobs=[N] 'Inputs processed in parallel, each with --threads, default 1.'
+        ";
+
+/// Options of drawing the pic, shared by commands drawing one, `-o` given separately.
+const PLOT_OPTIONS: &str = "
+        [format] --format=[FORMAT] 'Pic format, png, svg, pdf or html, guessed from pic suffix by default.'
+        [log-y] --log-y 'Plot proportions on a log10 axis to show the tail, svg, png and pdf pics.'
+        [cumulative] --cumulative 'Plot cumulative proportions (empirical CDF), svg, png and pdf pics.'
+        [annotate] --annotate=[LIST] 'Mark statistics by dashed vertical lines with labels, comma separated of mean, median and mode.'
+        [title] --title=[TEXT] 'Title above the pic.'
+        [x-label] --x-label=[TEXT] 'X axis label, default by --lang.'
+        [y-label] --y-label=[TEXT] 'Y axis label, default by --lang.'
+        [lang] --lang=[LANG] 'Language of default axis labels, zh or en, default zh.'
+        [width] --width=[PIXELS] 'Pic width, pixels at 96 dpi for png and svg, points for pdf, default 700.'
+        [height] --height=[PIXELS] 'Pic height, like --width, default 610.'
+        [dpi] --dpi=[DPI] 'Resolution of png pics, scales pixels, fonts and lines, default 96.'
+        [font] --font=[FAMILY] 'Font family of svg, png and pdf pics, falls back to an installed CJK font or sans-serif when not found.'
+        [color] --color=[LIST] 'Comma separated curve colors like `#1F77B4` in sample order, default palette for the rest.'
+        [line-width] --line-width=[PIXELS] 'Curve width, default 2.'
+        [style] --style=[STYLE] 'Curve style of svg, png and pdf pics, line, bars or filled-area, default line.'
+        [smooth] --smooth=[WINDOW] 'Smooth plotted proportions over WINDOW bp, svg, png and pdf pics, summary and tables keep raw counts.'
+        [smooth-kernel] --smooth-kernel=[KERNEL] 'Kernel of --smooth, mean (moving average) or gaussian, default mean.'
+        [x-min] --x-min=[BP] 'Smallest insert size drawn on the x axis of svg, png and pdf pics, default 0.'
+        [x-max] --x-max=[BP] 'Largest insert size drawn on the x axis of svg, png and pdf pics, zooming in below -m, default -m.'
+        [bin-size] --bin-size=[N] 'Sum insert sizes into N bp bins for svg, png and pdf pics and --hist, default 1.'
+        [y] --y=[VALUES] 'Y axis values of svg, png and pdf pics, fraction, counts (read pairs) or density (fraction per bp), default fraction.'
+        [y-max] --y-max=[VALUE] 'Top of the y axis of svg, png and pdf pics without --log-y, auto (1.2 times the 99.9th percentile of drawn values, so a spike does not flatten the curve) or a number in --y values, default auto.'
+        [theme] --theme=[THEME] 'Colors of background, text and axes of svg, png and pdf pics, light, dark or minimal, default light.'
+        [orientation-curves] --orientation-curves 'Draw FR, RF and tandem pairs as separate curves.'
+        [gc-curves] --gc-curves 'Draw pairs of each 20% GC stratum of the counted read as separate curves, implies --per-gc.'
+        [mapq-curves] --mapq-curves 'Draw pairs of each MAPQ bucket of --by-mapq as separate curves.'
+        [split-by] --split-by=[GROUP] 'Draw pairs of each group as separate curves with a legend, rg for read groups, implies --per-read-group.'
+        ";
+
+/// Options of batch runs, `--outdir` given separately.
+const BATCH_OPTIONS: &str = "
+        [cohort-report] --cohort-report=[FILE] 'Also write a boxplot of insert sizes by sample with outliers by median in red, `.png`, `.svg` or `.html`.'
+        [outlier-mads] --outlier-mads=[N] 'Samples with median more than N MADs from the cohort median are outliers, default 3.'
+        ";
+
+/// Directory of per sample outputs, optional for the main command.
+const OUTDIR: &str = "[outdir] --outdir=[DIR] 'Also write a pic and summary json of each sample, named by sample, and summary.tsv of all samples to DIR.'";
+
+/// Matches of the shared options, falling back to values of the `--config` file.
+struct Args<'a> {
+    matches: &'a ArgMatches<'a>,
+    config: Option<Config>,
+    /// Output paths named after `--output-prefix`.
+    prefixed: Vec<(&'static str, String)>,
+}
+
+impl<'a> Args<'a> {
+    /// Matches with the config file, options in it must be known and take values in files.
+    fn new(matches: &'a ArgMatches<'a>) -> Result<Self> {
+        let config = matches
+            .value_of("config")
+            .map(Config::from_path)
+            .transpose()?;
+        if let Some(config) = &config {
+            // Names lead lines of usage, e.g. `[upper] -m=[NUMBER] ...`, options of other
+            // commands are known too so one file serves all.
+            let known: Vec<&str> = [COUNT_OPTIONS, OUTPUT_OPTIONS, PLOT_OPTIONS, BATCH_OPTIONS]
+                .iter()
+                .flat_map(|v| v.lines())
+                .filter_map(|v| v.trim().strip_prefix('[')?.split_once(']'))
+                .map(|v| v.0)
+                .chain(["region", "outdir"])
+                .filter(|v| *v != "config")
+                .collect();
+            if let Some(name) = config.names().find(|v| !known.contains(v)) {
+                return Err(Error::Invalid(format!(
+                    "Unknown option in config: {}",
+                    name
+                )));
+            }
+        }
+        let mut args = Self {
+            matches,
+            config,
+            prefixed: Vec::new(),
+        };
+        if let Some(prefix) = args.value_of("output-prefix") {
+            let format = match args.value_of("format") {
+                Some(v) => PicFormat::from_name(v)?,
+                None => PicFormat::Png,
+            };
+            args.prefixed = vec![
+                ("pic", format!("{}.{}", prefix, format.extension())),
+                ("json", format!("{}.json", prefix)),
+                ("hist", format!("{}.hist.tsv", prefix)),
+            ];
         }
+        Ok(args)
+    }
+
+    fn value_of(&self, name: &str) -> Option<&str> {
+        self.matches
+            .value_of(name)
+            .or_else(|| self.config.as_ref()?.value(name))
+            .or_else(|| {
+                let v = self.prefixed.iter().find(|v| v.0 == name)?;
+                Some(v.1.as_str())
+            })
     }
+
+    fn values_of(&self, name: &str) -> Option<Vec<&str>> {
+        if let Some(v) = self.matches.values_of(name) {
+            return Some(v.collect());
+        }
+        if let Some(v) = self.config.as_ref().and_then(|v| v.values(name)) {
+            return Some(v.iter().map(String::as_str).collect());
+        }
+        let v = self.prefixed.iter().find(|v| v.0 == name)?;
+        Some(vec![v.1.as_str()])
+    }
+
+    fn is_present(&self, name: &str) -> bool {
+        self.value_of(name).is_some() || self.matches.is_present(name)
+    }
+}
+
+/// App with options of reading and counting inputs and of outputs.
+fn with_options<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.args_from_usage(COUNT_OPTIONS)
+        .args_from_usage(OUTPUT_OPTIONS)
+        .arg(
+            Arg::from_usage(
+                "[region] -r, --region=[REGION]... 'Only count reads overlapping REGION (chr:start-end), needs bam index, repeatable.'",
+            )
+            .number_of_values(1),
+        )
 }
 
-fn main() -> Result<()> {
-    let opts = App::new(crate_name!())
+/// App with options of drawing the pic, `-o` required unless `optional_pic` or named by
+/// `--output-prefix`.
+fn with_plot_options<'a, 'b>(app: App<'a, 'b>, optional_pic: bool) -> App<'a, 'b> {
+    let pic = Arg::from_usage(
+        "[pic] -o=[FILE]... 'Output pic file path, support `.svg`, `.png`, `.html` and `.pdf` (`pdf` feature) suffix, repeatable to draw several formats in one run.'",
+    )
+    .number_of_values(1);
+    app.arg(if optional_pic {
+        pic
+    } else {
+        pic.required_unless("output-prefix")
+    })
+    .args_from_usage(PLOT_OPTIONS)
+}
+
+/// App with inputs as bam arguments, a sample sheet or htsget urls.
+fn with_inputs<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::from_usage(
+            "[bam]... 'Input bam, sam or cram files, `-` for bam/sam from stdin, one line per file.'",
+        )
+        .required_unless_one(&["sample-sheet", "htsget"]),
+    )
+    .arg(
+        Arg::from_usage(
+            "[sample-sheet] --sample-sheet=[FILE] 'Inputs from a table with sample_name and bam_path columns, instead of bam arguments.'",
+        )
+        .conflicts_with("bam"),
+    )
+    .arg(
+        Arg::from_usage(
+            "[htsget] --htsget=[URL]... 'Inputs from GA4GH htsget reads URL, e.g. `https://server/reads/ID`, only reads of --region when given, repeatable.'",
+        )
+        .number_of_values(1)
+        .conflicts_with_all(&["bam", "sample-sheet"]),
+    )
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<()> {
+    // Without a subcommand the main command draws like `plot`, with batch options.
+    let app = App::new(crate_name!())
         .author(crate_authors!())
         .about(crate_description!())
         .version(crate_version!())
         .setting(AppSettings::ArgRequiredElseHelp)
-        .args_from_usage(
-            "
-            <pic> -o=[FILE] 'Output pic file path, support `.svg` and `.png` suffix.'
-            [upper] -m=[NUMBER] 'Maximum insert size to record, default 500. !Bigger number costs more memory!.'
-            <bam> 'Input bam file.'
-            ",
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(
+            Arg::from_usage(
+                "[verbose] -v... 'Log progress on stderr, timing of header parse, scan, stats and render with -v, details with -vv.'",
+            )
+            .global(true),
+        )
+        .arg(
+            Arg::from_usage("[quiet] -q, --quiet 'Only log errors on stderr, no warnings.'")
+                .global(true),
+        )
+        .arg(Arg::from_usage(OUTDIR))
+        .args_from_usage(BATCH_OPTIONS)
+        .subcommand(
+            with_plot_options(with_options(with_inputs(SubCommand::with_name("plot"))), false)
+                .about("Draw insert sizes of inputs, with summary json, tables and metrics."),
+        )
+        .subcommand(
+            with_options(with_inputs(SubCommand::with_name("stats")))
+                .about("Summarize insert sizes of inputs without drawing, summary json, tables and metrics only."),
+        )
+        .subcommand(
+            with_plot_options(with_options(SubCommand::with_name("compare")), false)
+                .about("Compare insert sizes of two inputs, e.g. tumor and normal, with KS statistic, median difference and overlap coefficient.")
+                .args_from_usage(
+                    "
+                    <first> 'First input bam, sam or cram file.'
+                    <second> 'Second input bam, sam or cram file.'
+                    ",
+                ),
         )
-        .get_matches();
-    let bam: &str = opts.value_of("bam").ok_or_else(opterr)?;
-    let pic: &str = opts.value_of("pic").ok_or_else(opterr)?;
-    let format: PicFormat = PicFormat::from_str(pic)?;
-    let upper: usize = opts
-        .value_of("upper")
-        .unwrap_or("500")
-        .parse()
-        .map_err(|_| opterr())?;
-    cli(bam, pic, &upper, &format)
+        .subcommand(
+            with_plot_options(with_options(with_inputs(SubCommand::with_name("batch"))), true)
+                .about("Write a pic and summary json of each sample and a summary table of all samples to a directory, e.g. of a sample sheet.")
+                .arg(Arg::from_usage(OUTDIR).required(true))
+                .args_from_usage(BATCH_OPTIONS),
+        )
+        .subcommand(
+            with_plot_options(with_options(SubCommand::with_name("watch")), true)
+                .about("Watch a directory, e.g. of a sequencing run, and write a pic and summary json of each finished bam to a directory as it is done.")
+                .args_from_usage(
+                    "
+                    <dir> 'Directory to watch.'
+                    [pattern] --pattern=[GLOB] 'Names of files to process, `*` matches any run of characters and `?` any one, default `*.bam`.'
+                    [interval] --interval=[SECONDS] 'Seconds between polls of the directory, a file unchanged for a poll or as long is finished, default 30.'
+                    [once] --once 'Process finished files once and exit, e.g. from cron.'
+                    [metrics-port] --metrics-port=[PORT] 'Export metrics of processed samples for Prometheus on PORT at /metrics.'
+                    [bind] --bind=[ADDR] 'Address of --metrics-port, default 127.0.0.1, 0.0.0.0 for all interfaces.'
+                    ",
+                )
+                .arg(Arg::from_usage(OUTDIR).required(true)),
+        )
+        .subcommand(
+            with_plot_options(with_options(SubCommand::with_name("serve")), true)
+                .about("Serve analyses over http, e.g. to a LIMS, json summary and pic of a bam given by path or uploaded.")
+                .args_from_usage(
+                    "
+                    [port] --port=[PORT] 'Port to listen on, default 8080.'
+                    [bind] --bind=[ADDR] 'Address to listen on, default 127.0.0.1, 0.0.0.0 for all interfaces.'
+                    [root] --root=[DIR] 'Only inputs under DIR may be given by path, the current directory by default.'
+                    [max-upload] --max-upload=[BYTES] 'Largest request body accepted, larger uploads are answered with 413, default 4294967296 (4 GiB).'
+                    ",
+                ),
+        )
+        .subcommand(
+            with_plot_options(SubCommand::with_name("hist2plot"), false)
+                .about("Draw histograms written by --hist, without reading inputs again.")
+                .arg(Arg::from_usage(
+                    "<table>... 'Histograms written by --hist, json for `.json`, comma separated for `.csv`, tab separated otherwise.'",
+                )),
+        );
+    let app = app.subcommand(
+        SubCommand::with_name("merge")
+            .about("Sum histograms written by --hist, e.g. of lanes of a sample, and summarize them again.")
+            .args_from_usage(
+                "
+                <merged> -o=[FILE] 'Merged histogram path, json for `.json`, comma separated for `.csv`, tab separated otherwise.'
+                [name] --name=[NAME] 'Sample name of the merged histogram, stem of the merged path by default.'
+                [by-sample] --by-sample 'Only merge histograms of the same sample name, keeping samples apart.'
+                [json] --json=[FILE] 'Write summary json to FILE instead of stdout.'
+                [print-summary] --print-summary 'Print summary json to stdout even with --json.'
+                <hist>... 'Histograms written by --hist, json for `.json`, comma separated for `.csv`, tab separated otherwise.'
+                ",
+            ),
+    );
+    let matches = with_plot_options(with_options(with_inputs(app)), false).get_matches();
+    init_logging(&matches);
+    match matches.subcommand() {
+        ("compare", Some(sub)) => {
+            let first = sub.value_of("first").ok_or_else(opterr)?;
+            let second = sub.value_of("second").ok_or_else(opterr)?;
+            let sub = Args::new(sub)?;
+            set_decompressor(&sub)?;
+            compare(&[first, second], &outputs(&sub)?, &options(&sub)?)
+        }
+        ("hist2plot", Some(sub)) => {
+            let tables: Vec<&str> = sub.values_of("table").ok_or_else(opterr)?.collect();
+            hist2plot(&tables, &outputs(&Args::new(sub)?)?)
+        }
+        ("merge", Some(sub)) => {
+            let inputs: Vec<&str> = sub.values_of("hist").ok_or_else(opterr)?.collect();
+            let merged = sub.value_of("merged").ok_or_else(opterr)?;
+            let args = Args::new(sub)?;
+            merge(
+                &inputs,
+                merged,
+                sub.value_of("name"),
+                sub.is_present("by-sample"),
+                &outputs(&args)?,
+            )
+        }
+        ("watch", Some(sub)) => {
+            let dir = sub.value_of("dir").ok_or_else(opterr)?;
+            let args = Args::new(sub)?;
+            set_decompressor(&args)?;
+            let interval = sub
+                .value_of("interval")
+                .map(|v| v.parse::<u64>().ok().filter(|v| *v > 0).ok_or_else(opterr))
+                .transpose()?
+                .unwrap_or(DEFAULT_WATCH_INTERVAL);
+            let metrics = match sub.value_of("metrics-port") {
+                Some(v) => {
+                    let metrics = Arc::new(Mutex::new(Metrics::default()));
+                    spawn_exporter(
+                        sub.value_of("bind").unwrap_or("127.0.0.1"),
+                        v.parse::<u16>().map_err(|_| opterr())?,
+                        Arc::clone(&metrics),
+                    )?;
+                    Some(metrics)
+                }
+                None => None,
+            };
+            watch(
+                dir,
+                sub.value_of("pattern").unwrap_or("*.bam"),
+                interval,
+                sub.is_present("once"),
+                metrics,
+                outputs(&args)?,
+                &options(&args)?,
+            )
+        }
+        ("serve", Some(sub)) => {
+            let args = Args::new(sub)?;
+            set_decompressor(&args)?;
+            let port = sub
+                .value_of("port")
+                .map(|v| v.parse::<u16>().map_err(|_| opterr()))
+                .transpose()?
+                .unwrap_or(DEFAULT_PORT);
+            let max_upload = sub
+                .value_of("max-upload")
+                .map(|v| v.parse::<u64>().map_err(|_| opterr()))
+                .transpose()?
+                .unwrap_or(DEFAULT_MAX_UPLOAD);
+            serve(
+                sub.value_of("bind").unwrap_or("127.0.0.1"),
+                port,
+                sub.value_of("root").unwrap_or("."),
+                max_upload,
+                outputs(&args)?,
+                &options(&args)?,
+            )
+        }
+        ("plot", Some(sub)) | ("batch", Some(sub)) => inputs_cli(sub, true),
+        ("stats", Some(sub)) => inputs_cli(sub, false),
+        _ => inputs_cli(&matches, true),
+    }
+}
+
+/// Log on stderr, warnings by default, more with each `-v` and errors only with `--quiet`,
+/// `RUST_LOG` takes precedence.
+fn init_logging(matches: &ArgMatches) {
+    // Global flags are matched by the subcommand when given after it.
+    let sub = matches.subcommand().1;
+    let verbose =
+        matches.occurrences_of("verbose") + sub.map_or(0, |v| v.occurrences_of("verbose"));
+    let quiet = matches.is_present("quiet") || sub.is_some_and(|v| v.is_present("quiet"));
+    let level = match verbose {
+        _ if quiet => LevelFilter::Error,
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| {
+            let level = match record.level() {
+                Level::Error => "Error",
+                Level::Warn => "Warning",
+                Level::Info => "Info",
+                Level::Debug => "Debug",
+                Level::Trace => "Trace",
+            };
+            writeln!(buf, "{}: {}", level, record.args())
+        })
+        .init();
+}
+
+/// Inflate bam blocks with the decompressor of `--decompressor`, if given.
+fn set_decompressor(args: &Args) -> Result<()> {
+    if let Some(v) = args.value_of("decompressor") {
+        bgzf::set_decompressor(v.parse::<Decompressor>()?);
+    }
+    debug!("inflating bam blocks with {:?}", bgzf::decompressor());
+    Ok(())
+}
+
+/// Run a command reading inputs, drawing a pic when `draws`.
+fn inputs_cli(matches: &ArgMatches, draws: bool) -> Result<()> {
+    let sheet = matches
+        .value_of("sample-sheet")
+        .map(SampleSheet::from_path)
+        .transpose()?;
+    let inputs = match (&sheet, matches.values_of("htsget")) {
+        (Some(sheet), _) => sheet
+            .samples
+            .iter()
+            .map(|(name, path)| (name.clone(), path.as_str()))
+            .collect(),
+        (None, Some(urls)) => named(&urls.collect::<Vec<_>>()),
+        (None, None) => named(
+            &matches
+                .values_of("bam")
+                .ok_or_else(opterr)?
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let args = Args::new(matches)?;
+    set_decompressor(&args)?;
+    let mut out = outputs(&args)?;
+    if !draws {
+        out.pics.clear();
+    }
+    cli(&inputs, &out, &options(&args)?)
+}
+
+/// Plot options titled by the header sample name shared by `hists` unless `--title` is given.
+fn titled<'a, I: IntoIterator<Item = &'a Histograms>>(plot: &PlotOptions, hists: I) -> PlotOptions {
+    let mut plot = plot.clone();
+    if plot.title.is_none() {
+        let mut names = hists.into_iter().map(|v| v.sample_name.as_deref());
+        let first = names.next().flatten();
+        if names.all(|v| v == first) {
+            plot.title = first.map(String::from);
+        }
+    }
+    plot
+}
+
+/// Options from matches of the shared options.
+fn options(args: &Args) -> Result<Options> {
+    let library_type: LibraryType = args
+        .value_of("library-type")
+        .unwrap_or("paired-end")
+        .parse()?;
+    Ok(Options {
+        upper: args
+            .value_of("upper")
+            .map(|v| v.parse().map_err(|_| opterr()))
+            .transpose()?
+            .unwrap_or_else(|| {
+                if args.is_present("atac") {
+                    ATAC_UPPER
+                } else if args.is_present("cfdna") {
+                    CFDNA_UPPER
+                } else {
+                    library_type.default_upper()
+                }
+            }),
+        library_type,
+        reference: args.value_of("reference").map(String::from),
+        no_dups: args.is_present("no-dups"),
+        include_qcfail: args.is_present("include-qcfail"),
+        count_by: args.value_of("count-by").unwrap_or("read1").parse()?,
+        auto_upper: if args.is_present("auto-upper") {
+            Some(
+                args.value_of("auto-upper-cover")
+                    .unwrap_or("99.5")
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| *v > 0.0 && *v <= 100.0)
+                    .ok_or_else(opterr)?
+                    / 100.0,
+            )
+        } else {
+            None
+        },
+        tail_report: args.is_present("tail-report"),
+        clip_adjust: args.is_present("clip-adjust"),
+        include_flags: args
+            .value_of("include-flags")
+            .map(parse_flags)
+            .transpose()?
+            .unwrap_or(DEFAULT_INCLUDE_FLAGS),
+        exclude_flags: args
+            .value_of("exclude-flags")
+            .map(parse_flags)
+            .transpose()?
+            .unwrap_or(DEFAULT_EXCLUDE_FLAGS),
+        per_chromosome: args.is_present("per-chromosome"),
+        per_read_group: args.is_present("per-read-group") || split_by_rg(args)?,
+        per_gc: args.is_present("per-gc") || args.is_present("gc-curves"),
+        mapq_buckets: match args.value_of("by-mapq") {
+            Some(v) => parse_mapq_bounds(v)?,
+            None if args.is_present("mapq-curves") => {
+                return Err(Error::Invalid("--mapq-curves needs --by-mapq.".to_string()))
+            }
+            None => Vec::new(),
+        },
+        percentiles: args
+            .value_of("percentiles")
+            .map(|v| {
+                v.split(',')
+                    .map(|v| {
+                        v.trim()
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|v| (0.0..=100.0).contains(v))
+                            .ok_or_else(opterr)
+                    })
+                    .collect::<Result<Vec<f64>>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        expected_sizes: args
+            .value_of("expected-sizes")
+            .map(parse_sizes)
+            .transpose()?
+            .unwrap_or_default(),
+        expected_tolerance: args
+            .value_of("expected-tolerance")
+            .map(|v| v.parse().map_err(|_| opterr()))
+            .transpose()?
+            .unwrap_or(DEFAULT_EXPECTED_TOLERANCE),
+        fit_gmm: parse_components(args)?,
+        cfdna: args.is_present("cfdna"),
+        atac: args.is_present("atac"),
+        trim: match args.value_of("trim") {
+            Some(v) => v
+                .parse::<f64>()
+                .ok()
+                .filter(|v| (0.0..0.5).contains(v))
+                .ok_or_else(opterr)?,
+            None => DEFAULT_TRIM,
+        },
+        threads: args
+            .value_of("threads")
+            .map(|v| {
+                v.parse::<usize>()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .ok_or_else(opterr)
+            })
+            .transpose()?
+            .unwrap_or(1),
+        sample_fraction: args
+            .value_of("sample-fraction")
+            .map(|v| {
+                v.parse::<f64>()
+                    .ok()
+                    .filter(|v| *v > 0.0 && *v <= 1.0)
+                    .ok_or_else(opterr)
+            })
+            .transpose()?,
+        max_pairs: args
+            .value_of("max-pairs")
+            .map(|v| v.parse().map_err(|_| opterr()))
+            .transpose()?,
+        converge_tolerance: args
+            .value_of("converge-tolerance")
+            .map(|v| {
+                v.parse::<f64>()
+                    .ok()
+                    .filter(|v| *v > 0.0)
+                    .ok_or_else(opterr)
+            })
+            .transpose()?,
+        converge_min_pairs: args
+            .value_of("converge-min-pairs")
+            .map(|v| v.parse().map_err(|_| opterr()))
+            .transpose()?
+            .unwrap_or(DEFAULT_CONVERGE_MIN_PAIRS),
+        seed: args
+            .value_of("seed")
+            .unwrap_or("0")
+            .parse()
+            .map_err(|_| opterr())?,
+        progress: args.is_present("progress"),
+        strict: args.is_present("strict"),
+        lenient: args.is_present("lenient"),
+        adapter_length: args
+            .value_of("adapter-length")
+            .map(|v| v.parse().map_err(|_| opterr()))
+            .transpose()?,
+        dump_tlens: args.value_of("dump-tlens").map(String::from),
+        dump_positions: args.is_present("dump-positions"),
+        checkpoint: args.value_of("checkpoint").map(String::from),
+        checkpoint_every: args
+            .value_of("checkpoint-every")
+            .map(|v| v.parse().map_err(|_| opterr()))
+            .transpose()?
+            .unwrap_or(DEFAULT_CHECKPOINT_EVERY),
+        resume: args.value_of("resume").map(String::from),
+        dedup_capacity: if args.is_present("dedup-on-the-fly") {
+            Some(
+                args.value_of("dedup-capacity")
+                    .map(|v| {
+                        v.parse::<usize>()
+                            .ok()
+                            .filter(|v| *v > 0)
+                            .ok_or_else(opterr)
+                    })
+                    .transpose()?
+                    .unwrap_or(DEFAULT_DEDUP_CAPACITY),
+            )
+        } else {
+            None
+        },
+        read_length: args
+            .value_of("read-length")
+            .map(|v| {
+                v.parse::<usize>()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .ok_or_else(opterr)
+            })
+            .transpose()?,
+        stream_every: if args.is_present("stream-ndjson") {
+            Some(
+                args.value_of("stream-every")
+                    .map(|v| v.parse::<u64>().ok().filter(|v| *v > 0).ok_or_else(opterr))
+                    .transpose()?
+                    .unwrap_or(DEFAULT_STREAM_EVERY),
+            )
+        } else {
+            None
+        },
+        htsget: args.is_present("htsget"),
+        regions: args
+            .values_of("region")
+            .map(|v| v.into_iter().map(str::parse).collect())
+            .unwrap_or_else(|| Ok(Vec::new()))?,
+        targets: args
+            .value_of("targets")
+            .map(Targets::from_path)
+            .transpose()?,
+        exclude_contigs: args
+            .value_of("exclude-contigs")
+            .map(|v| {
+                v.split(',')
+                    .map(str::trim)
+                    .filter(|v| !v.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        contigs: if args.is_present("primary-only") || args.is_present("autosomes-only") {
+            let build: GenomeBuild = args.value_of("genome-build").unwrap_or("hg38").parse()?;
+            Some(build.primary_contigs(args.is_present("autosomes-only")))
+        } else {
+            None
+        },
+        read_names: if args.is_present("read-name-regex") || args.is_present("read-names") {
+            let mut names = ReadNames::default();
+            if let Some(v) = args.value_of("read-name-regex") {
+                names = names.with_regex(v)?;
+            }
+            if let Some(v) = args.value_of("read-names") {
+                names = names.with_names_file(v)?;
+            }
+            Some(names)
+        } else {
+            None
+        },
+    })
+}
+
+/// Outputs from matches of the shared options.
+fn outputs<'a>(args: &'a Args) -> Result<Outputs<'a>> {
+    // Formats by suffix of each pic unless given.
+    let pic_format = |pic: &str| match args.value_of("format") {
+        Some(v) => PicFormat::from_name(v),
+        None => pic.parse(),
+    };
+    let pics = args
+        .values_of("pic")
+        .unwrap_or_default()
+        .into_iter()
+        .map(|v| Ok((v, pic_format(v)?)))
+        .collect::<Result<Vec<_>>>()?;
+    // Per sample pics of batch runs are like the first pic, or png by default.
+    let format = match (args.value_of("format"), pics.first()) {
+        (Some(v), _) => PicFormat::from_name(v)?,
+        (None, Some((pic, _))) => pic.parse()?,
+        (None, None) => PicFormat::Png,
+    };
+    Ok(Outputs {
+        pics,
+        format,
+        picard: args.value_of("picard-metrics"),
+        samtools_stats: args.value_of("samtools-stats"),
+        hist: args.value_of("hist"),
+        json: args.value_of("json"),
+        print_summary: args.is_present("print-summary"),
+        stream_ndjson: args.is_present("stream-ndjson"),
+        multiqc: args.is_present("multiqc"),
+        orientation_curves: args.is_present("orientation-curves"),
+        gc_curves: args.is_present("gc-curves"),
+        mapq_curves: args.is_present("mapq-curves"),
+        rg_curves: split_by_rg(args)?,
+        reference_hist: args
+            .value_of("reference-hist")
+            .map(Reference::from_path)
+            .transpose()?,
+        fail_if: args.value_of("fail-if").map(str::parse).transpose()?,
+        outdir: args.value_of("outdir"),
+        cohort_report: args.value_of("cohort-report"),
+        outlier_mads: match args.value_of("outlier-mads") {
+            Some(v) => v
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v > 0.0)
+                .ok_or_else(opterr)?,
+            None => DEFAULT_OUTLIER_MADS,
+        },
+        jobs: args
+            .value_of("jobs")
+            .map(|v| {
+                v.parse::<usize>()
+                    .ok()
+                    .filter(|v| *v > 0)
+                    .ok_or_else(opterr)
+            })
+            .transpose()?
+            .unwrap_or(1),
+        plot: PlotOptions {
+            log_y: args.is_present("log-y") || args.is_present("atac"),
+            cumulative: args.is_present("cumulative"),
+            annotate: args
+                .value_of("annotate")
+                .map(|v| v.split(',').map(str::parse::<Annotation>).collect())
+                .unwrap_or_else(|| Ok(Vec::new()))?,
+            expected_sizes: args
+                .value_of("expected-sizes")
+                .map(parse_sizes)
+                .transpose()?
+                .unwrap_or_default(),
+            title: args.value_of("title").map(String::from),
+            x_label: args.value_of("x-label").map(String::from),
+            y_label: args.value_of("y-label").map(String::from),
+            lang: args.value_of("lang").unwrap_or("zh").parse::<Lang>()?,
+            width: args
+                .value_of("width")
+                .map(|v| v.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(opterr))
+                .transpose()?,
+            height: args
+                .value_of("height")
+                .map(|v| v.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(opterr))
+                .transpose()?,
+            dpi: args
+                .value_of("dpi")
+                .map(|v| v.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(opterr))
+                .transpose()?,
+            font: args.value_of("font").map(String::from),
+            colors: args
+                .value_of("color")
+                .map(|v| v.split(',').map(parse_color).collect())
+                .unwrap_or_else(|| Ok(Vec::new()))?,
+            line_width: args
+                .value_of("line-width")
+                .map(|v| v.parse::<u32>().ok().filter(|v| *v > 0).ok_or_else(opterr))
+                .transpose()?,
+            style: args
+                .value_of("style")
+                .unwrap_or("line")
+                .parse::<CurveStyle>()?,
+            smooth: args
+                .value_of("smooth")
+                .map(|v| {
+                    v.parse::<usize>()
+                        .ok()
+                        .filter(|v| *v > 0)
+                        .ok_or_else(opterr)
+                })
+                .transpose()?,
+            kernel: args
+                .value_of("smooth-kernel")
+                .unwrap_or("mean")
+                .parse::<Kernel>()?,
+            bin_size: args
+                .value_of("bin-size")
+                .map(|v| {
+                    v.parse::<usize>()
+                        .ok()
+                        .filter(|v| *v > 0)
+                        .ok_or_else(opterr)
+                })
+                .transpose()?,
+            x_min: args
+                .value_of("x-min")
+                .map(|v| v.parse::<usize>().map_err(|_| opterr()))
+                .transpose()?,
+            x_max: args
+                .value_of("x-max")
+                .map(|v| v.parse::<usize>().map_err(|_| opterr()))
+                .transpose()?,
+            y: args.value_of("y").unwrap_or("fraction").parse::<YValue>()?,
+            y_max: args.value_of("y-max").unwrap_or("auto").parse::<YMax>()?,
+            fit_gmm: parse_components(args)?,
+            theme: args.value_of("theme").unwrap_or("light").parse::<Theme>()?,
+        },
+    })
 }
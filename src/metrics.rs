@@ -0,0 +1,157 @@
+use std::fmt::Write as _;
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+use crate::error::Result;
+use crate::serve::{respond, Deadline, Request, HEADER_TIMEOUT};
+use crate::summary::Summary;
+
+/// Samples kept for export, the oldest are dropped past it to bound label cardinality.
+pub const MAX_EXPORTED_SAMPLES: usize = 1000;
+/// Media type of the Prometheus text format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Gauges of one processed sample.
+struct SampleMetrics {
+    name: String,
+    median: f64,
+    sd: f64,
+    qualified_fraction: f64,
+    /// Whether `--fail-if` matched, `None` without.
+    qc_failed: Option<bool>,
+}
+
+/// Value of a gauge of a sample, `None` when not exported.
+type Gauge = fn(&SampleMetrics) -> Option<f64>;
+
+/// Metrics of processed samples in Prometheus text format, for dashboards alerting on drift
+/// of library preps across runs.
+#[derive(Default)]
+pub struct Metrics {
+    samples: Vec<SampleMetrics>,
+    processed: u64,
+    failed: u64,
+}
+
+impl Metrics {
+    /// Record summary `sum` of sample `name`, replacing an earlier one of the name.
+    pub fn add(&mut self, name: &str, sum: &Summary, qc_failed: Option<bool>) {
+        self.samples.retain(|v| v.name != name);
+        if self.samples.len() >= MAX_EXPORTED_SAMPLES {
+            self.samples.remove(0);
+        }
+        // A pair of reads per counted pair, of mapped primary reads.
+        let primary = sum.alignments.primary;
+        let qualified_fraction = if primary > 0 {
+            (2.0 * sum.all_count as f64 / primary as f64).min(1.0)
+        } else {
+            0.0
+        };
+        self.samples.push(SampleMetrics {
+            name: name.to_string(),
+            median: sum.median,
+            sd: sum.std,
+            qualified_fraction,
+            qc_failed,
+        });
+        self.processed += 1;
+    }
+
+    /// Count a sample failing analysis.
+    pub fn add_failure(&mut self) {
+        self.failed += 1;
+    }
+
+    /// Exposition in Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "insize_samples_processed_total",
+                "Samples analyzed since start.",
+                self.processed,
+            ),
+            (
+                "insize_samples_failed_total",
+                "Samples failing analysis since start, e.g. of truncated inputs.",
+                self.failed,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        let gauges: [(&str, &str, Gauge); 4] = [
+            (
+                "insize_median_insert_size",
+                "Median insert size of qualified pairs.",
+                |v| Some(v.median),
+            ),
+            (
+                "insize_insert_size_sd",
+                "Insert size standard deviation of qualified pairs.",
+                |v| Some(v.sd),
+            ),
+            (
+                "insize_qualified_fraction",
+                "Fraction of mapped primary reads in counted pairs.",
+                |v| Some(v.qualified_fraction),
+            ),
+            (
+                "insize_qc_failed",
+                "1 when --fail-if matched the sample, 0 otherwise.",
+                |v| v.qc_failed.map(|v| v as u8 as f64),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+            for sample in &self.samples {
+                if let Some(v) = value(sample) {
+                    let _ = writeln!(out, "{}{{sample=\"{}\"}} {}", name, escape(&sample.name), v);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Escape a label value, backslash, double quote and line feed.
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Answer `GET /metrics` on `bind`:`port` with `metrics` from a background thread.
+pub fn spawn_exporter(bind: &str, port: u16, metrics: Arc<Mutex<Metrics>>) -> Result<()> {
+    let listener = TcpListener::bind((bind, port))?;
+    info!(
+        "exporting metrics on http://{}/metrics",
+        listener.local_addr()?
+    );
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let answered = stream
+                .map_err(Into::into)
+                .and_then(|mut stream| -> Result<()> {
+                    let req = Request::read(&mut BufReader::new(Deadline::new(
+                        stream.try_clone()?,
+                        HEADER_TIMEOUT,
+                    )))?;
+                    match (req.method.as_str(), req.path.as_str()) {
+                        ("GET", "/metrics") => {
+                            let body = metrics.lock().map(|v| v.render()).unwrap_or_default();
+                            respond(&mut stream, 200, CONTENT_TYPE, body.as_bytes())?
+                        }
+                        _ => respond(&mut stream, 404, "text/plain", b"Only /metrics.\n")?,
+                    }
+                    Ok(())
+                });
+            if let Err(e) = answered {
+                warn!("metrics request failed: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::io::Write;
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::error::{Error, Result};
+use crate::summary::{round2, Summary};
+
+/// MultiQC custom content id, sections of this tool are grouped under it.
+const ID: &str = "insize";
+
+/// Histograms of samples as a MultiQC `linegraph`.
+struct LineGraph<'a>(&'a [(String, Vec<u32>)]);
+
+/// Counts keyed by insert size.
+struct Points<'a>(&'a [u32]);
+
+impl Serialize for Points<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().enumerate())
+    }
+}
+
+/// Plot config of the line graph.
+struct LineConfig;
+
+impl Serialize for LineConfig {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("LineConfig", 4)?;
+        state.serialize_field("id", &format!("{}_histogram_plot", ID))?;
+        state.serialize_field("title", "Insert size distribution")?;
+        state.serialize_field("xlab", "Insert size (bp)")?;
+        state.serialize_field("ylab", "Pair count")?;
+        state.end()
+    }
+}
+
+impl Serialize for LineGraph<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("LineGraph", 6)?;
+        state.serialize_field("id", &format!("{}_histogram", ID))?;
+        state.serialize_field("section_name", "Insert size")?;
+        state.serialize_field(
+            "description",
+            "Insert size distribution of qualified pairs.",
+        )?;
+        state.serialize_field("plot_type", "linegraph")?;
+        state.serialize_field("pconfig", &LineConfig)?;
+        let data = self.0.iter().map(|(k, v)| (k, Points(v)));
+        state.serialize_field("data", &Data(data))?;
+        state.end()
+    }
+}
+
+/// Summaries of samples as a MultiQC `table`.
+struct Table<'a>(&'a [(String, Summary)]);
+
+/// Table row of one sample.
+struct Row<'a>(&'a Summary);
+
+impl Serialize for Row<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let sum = self.0;
+        let mut state = serializer.serialize_struct("Row", 6)?;
+        state.serialize_field("count", &sum.count)?;
+        state.serialize_field("mean", &round2(sum.mean))?;
+        state.serialize_field("sd", &round2(sum.std))?;
+        state.serialize_field("q1", &sum.q1)?;
+        state.serialize_field("median", &sum.q2)?;
+        state.serialize_field("q3", &sum.q3)?;
+        state.end()
+    }
+}
+
+impl Serialize for Table<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Table", 5)?;
+        state.serialize_field("id", &format!("{}_stats", ID))?;
+        state.serialize_field("section_name", "Insert size statistics")?;
+        state.serialize_field("description", "Insert size summary of qualified pairs.")?;
+        state.serialize_field("plot_type", "table")?;
+        let data = self.0.iter().map(|(k, v)| (k, Row(v)));
+        state.serialize_field("data", &Data(data))?;
+        state.end()
+    }
+}
+
+/// Map of sample name to value, from a cloneable iterator.
+struct Data<I>(I);
+
+impl<K, V, I> Serialize for Data<I>
+where
+    K: Serialize,
+    V: Serialize,
+    I: Iterator<Item = (K, V)> + Clone,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.clone())
+    }
+}
+
+/// Write histograms of samples as MultiQC custom content, file name should end with `_mqc.json`.
+pub fn write_histograms<W: Write>(out: W, samples: &[(String, Vec<u32>)]) -> Result<()> {
+    serde_json::to_writer_pretty(out, &LineGraph(samples)).map_err(Error::from)
+}
+
+/// Write summaries of samples as MultiQC custom content, file name should end with `_mqc.json`.
+pub fn write_summaries<W: Write>(out: W, sums: &[(String, Summary)]) -> Result<()> {
+    serde_json::to_writer_pretty(out, &Table(sums)).map_err(Error::from)
+}
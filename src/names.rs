@@ -0,0 +1,54 @@
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader};
+
+use regex::bytes::Regex;
+
+use crate::error::{open_file, Error, Result};
+
+/// Read names to count, by pattern and/or a list of names.
+#[derive(Default)]
+pub struct ReadNames {
+    pattern: Option<Regex>,
+    names: Option<HashSet<Vec<u8>>>,
+}
+
+impl ReadNames {
+    /// Only names matching regex `v` anywhere, anchor with `^` and `$` for whole names.
+    pub fn with_regex(mut self, v: &str) -> Result<Self> {
+        let pattern =
+            Regex::new(v).map_err(|e| Error::Invalid(format!("Wrong read name regex: {}", e)))?;
+        self.pattern = Some(pattern);
+        Ok(self)
+    }
+
+    /// Only names listed in file at `v`, one per line, empty and `#` lines are skipped.
+    pub fn with_names_file(mut self, v: &str) -> Result<Self> {
+        let mut names = HashSet::new();
+        for line in BufReader::new(open_file(v)?).lines() {
+            let line = line?;
+            let name = line.trim();
+            if !name.is_empty() && !name.starts_with('#') {
+                names.insert(name.as_bytes().to_vec());
+            }
+        }
+        self.names = Some(names);
+        Ok(self)
+    }
+
+    /// Feed the regex and the names in order to `state`.
+    pub fn hash_into<H: Hasher>(&self, state: &mut H) {
+        self.pattern.as_ref().map(Regex::as_str).hash(state);
+        let mut names: Option<Vec<&Vec<u8>>> = self.names.as_ref().map(|v| v.iter().collect());
+        if let Some(v) = &mut names {
+            v.sort();
+        }
+        names.hash(state);
+    }
+
+    /// Whether a read name passes both the regex and the list.
+    pub fn matches(&self, name: &[u8]) -> bool {
+        self.pattern.as_ref().is_none_or(|v| v.is_match(name))
+            && self.names.as_ref().is_none_or(|v| v.contains(name))
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::{Result, Write};
+
+use crate::hist::{
+    bins_mad, bins_median, widths_of_percent, Histograms, InsertSizeHistogram, WIDTH_PERCENTS,
+};
+
+/// Fraction of pairs an orientation needs to be reported, as Picard `MINIMUM_PCT`.
+const MINIMUM_PCT: f64 = 0.05;
+/// Histogram is trimmed to median plus this many MADs before mean and sd, as Picard `DEVIATIONS`.
+const DEVIATIONS: f64 = 10.0;
+/// One row of `InsertSizeMetrics`.
+struct Metrics {
+    median: f64,
+    mode: usize,
+    mad: f64,
+    min: usize,
+    max: usize,
+    mean: f64,
+    sd: f64,
+    pairs: u64,
+    widths: Vec<usize>,
+    // Last insert size kept in histogram section.
+    trim: usize,
+}
+
+impl Metrics {
+    /// Metrics of insert size histogram, insert size 0 is ignored like Picard.
+    fn new(hist: &InsertSizeHistogram) -> Option<Self> {
+        let bins = || {
+            hist.bins()
+                .skip_while(|(k, _)| *k == 0)
+                .map(|(k, v)| (k, v as u64))
+        };
+        let pairs: u64 = bins().map(|(_, v)| v).sum();
+        if pairs == 0 {
+            return None;
+        }
+        let min = bins().next()?.0;
+        let max = bins().last()?.0;
+        let mode = bins().fold((0, 0), |a, b| if b.1 > a.1 { b } else { a }).0;
+        let median = bins_median(bins().map(|(k, v)| (k as f64, v)), pairs);
+        let mad = bins_mad(bins().map(|(k, v)| (k as f64, v)), median, pairs);
+
+        let widths = widths_of_percent(bins(), median);
+
+        // Mean and sample sd of the trimmed histogram.
+        let trim = (median + DEVIATIONS * mad) as usize;
+        let (mut count, mut sum) = (0u64, 0f64);
+        for (k, v) in bins().take_while(|(k, _)| *k <= trim) {
+            count += v;
+            sum += (k as u64 * v) as f64;
+        }
+        let mean = sum / count as f64;
+        let sd = (bins()
+            .take_while(|(k, _)| *k <= trim)
+            .map(|(k, v)| v as f64 * (k as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (count as f64 - 1.0))
+            .sqrt();
+
+        Some(Self {
+            median,
+            mode,
+            mad,
+            min,
+            max,
+            mean,
+            sd,
+            pairs,
+            widths,
+            trim,
+        })
+    }
+}
+
+/// Format a double like Picard, at most 6 decimals without trailing zeros.
+fn fmt_f64(v: f64) -> String {
+    if !v.is_finite() {
+        return "?".to_string();
+    }
+    let s = format!("{:.6}", v);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Write Picard `CollectInsertSizeMetrics` compatible metrics of samples.
+///
+/// A single sample is reported as `All_Reads` with empty `SAMPLE`, several samples
+/// as rows with `SAMPLE` set, like `METRIC_ACCUMULATION_LEVEL=SAMPLE`. Pairs beyond
+/// the upper insert size are not included.
+pub fn write_metrics<W: Write>(mut out: W, samples: &[(String, Histograms)]) -> Result<()> {
+    writeln!(out, "## htsjdk.samtools.metrics.StringHeader")?;
+    writeln!(
+        out,
+        "# {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(out)?;
+    writeln!(out, "## METRICS CLASS\tpicard.analysis.InsertSizeMetrics")?;
+    let widths: Vec<String> = WIDTH_PERCENTS
+        .iter()
+        .map(|v| format!("WIDTH_OF_{}_PERCENT", v))
+        .collect();
+    writeln!(
+        out,
+        "MEDIAN_INSERT_SIZE\tMODE_INSERT_SIZE\tMEDIAN_ABSOLUTE_DEVIATION\tMIN_INSERT_SIZE\t\
+         MAX_INSERT_SIZE\tMEAN_INSERT_SIZE\tSTANDARD_DEVIATION\tREAD_PAIRS\tPAIR_ORIENTATION\t\
+         {}\tSAMPLE\tLIBRARY\tREAD_GROUP",
+        widths.join("\t")
+    )?;
+
+    // Histogram columns of reported orientations.
+    let mut columns = Vec::new();
+    let single = samples.len() == 1;
+    for (name, hists) in samples {
+        let total: u64 = hists
+            .orientations
+            .iter()
+            .map(|(_, v)| {
+                v.bins()
+                    .filter(|(k, _)| *k > 0)
+                    .map(|(_, v)| v as u64)
+                    .sum::<u64>()
+            })
+            .sum();
+        for (orientation, hist) in &hists.orientations {
+            let metrics = match Metrics::new(hist) {
+                Some(v) if v.pairs as f64 >= MINIMUM_PCT * total as f64 => v,
+                _ => continue,
+            };
+            let widths: Vec<String> = metrics.widths.iter().map(|v| v.to_string()).collect();
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t\t",
+                fmt_f64(metrics.median),
+                metrics.mode,
+                fmt_f64(metrics.mad),
+                metrics.min,
+                metrics.max,
+                fmt_f64(metrics.mean),
+                fmt_f64(metrics.sd),
+                metrics.pairs,
+                orientation.name(),
+                widths.join("\t"),
+                if single { "" } else { name },
+            )?;
+            let prefix = if single { "All_Reads" } else { name };
+            columns.push((
+                format!("{}.{}_count", prefix, orientation.name().to_lowercase()),
+                (0..=usize::min(metrics.trim, hist.upper()))
+                    .map(|k| hist.get(k))
+                    .collect::<Vec<u32>>(),
+            ));
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "## HISTOGRAM\tjava.lang.Integer")?;
+    write!(out, "insert_size")?;
+    for (name, _) in &columns {
+        write!(out, "\t{}", name)?;
+    }
+    writeln!(out)?;
+    let len = columns.iter().map(|(_, v)| v.len()).max().unwrap_or(0);
+    for k in 1..len {
+        let row: Vec<u32> = columns
+            .iter()
+            .map(|(_, v)| v.get(k).copied().unwrap_or(0))
+            .collect();
+        if row.iter().all(|v| *v == 0) {
+            continue;
+        }
+        write!(out, "{}", k)?;
+        for v in row {
+            write!(out, "\t{}", v)?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
@@ -0,0 +1,972 @@
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+#[cfg(not(target_arch = "wasm32"))]
+use font_kit::source::SystemSource;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::error::{create_file, Error, Result};
+use crate::gmm::Mixture;
+use crate::hist::bins_median;
+use crate::html::write_html;
+
+/// Output picture format.
+#[derive(Clone, Copy)]
+pub enum PicFormat {
+    Svg,
+    Png,
+    /// Vector pdf, needs the `pdf` feature.
+    Pdf,
+    /// Self-contained interactive html.
+    Html,
+}
+
+impl PicFormat {
+    /// File name suffix of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::Png => "png",
+            Self::Pdf => "pdf",
+            Self::Html => "html",
+        }
+    }
+
+    /// Format from name, `png`, `svg`, `pdf` or `html`, case insensitive.
+    pub fn from_name(v: &str) -> Result<Self> {
+        match v.to_ascii_lowercase().as_str() {
+            "svg" => Ok(Self::Svg),
+            "png" => Ok(Self::Png),
+            "html" => Ok(Self::Html),
+            "pdf" if cfg!(feature = "pdf") => Ok(Self::Pdf),
+            "pdf" => Err(Error::Invalid(PDF_DISABLED.to_string())),
+            _ => Err(Error::Invalid("No such option.".to_string())),
+        }
+    }
+}
+
+impl FromStr for PicFormat {
+    type Err = Error;
+
+    /// Format from file name suffix.
+    fn from_str(v: &str) -> Result<Self> {
+        match v.rsplit_once('.') {
+            Some((_, ext)) => Self::from_name(ext),
+            None => Err(Error::Invalid("No such option.".to_string())),
+        }
+    }
+}
+
+/// Error of pdf output without the `pdf` feature.
+const PDF_DISABLED: &str = "Pdf output needs insize built with the `pdf` feature.";
+
+/// Percentile of drawn values the automatic y axis is fit to, a spike above it is cut.
+const Y_MAX_PERCENTILE: f64 = 99.9;
+/// Room above `Y_MAX_PERCENTILE` of the automatic y axis.
+const Y_MAX_HEADROOM: f64 = 1.2;
+
+/// Line colors of samples, first one is the single sample color.
+pub(crate) const COLORS: [(u8, u8, u8); 8] = [
+    (255, 0, 0),
+    (31, 119, 180),
+    (44, 160, 44),
+    (255, 127, 14),
+    (148, 103, 189),
+    (140, 86, 75),
+    (227, 119, 194),
+    (127, 127, 127),
+];
+
+/// Statistic marked on the pic by a vertical line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Annotation {
+    Mean,
+    Median,
+    Mode,
+}
+
+impl Annotation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Mean => "mean",
+            Self::Median => "median",
+            Self::Mode => "mode",
+        }
+    }
+
+    /// Value of counts of each insert size, `None` without pairs.
+    fn value(&self, counts: &[u32]) -> Option<f64> {
+        let bins = || {
+            counts
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| **v > 0)
+                .map(|(k, v)| (k as f64, *v as u64))
+        };
+        let total: u64 = bins().map(|v| v.1).sum();
+        if total == 0 {
+            return None;
+        }
+        Some(match self {
+            Self::Mean => bins().map(|(k, v)| k * v as f64).sum::<f64>() / total as f64,
+            Self::Median => bins_median(bins(), total),
+            Self::Mode => {
+                bins()
+                    .fold((0.0, 0), |a, b| if b.1 > a.1 { b } else { a })
+                    .0
+            }
+        })
+    }
+}
+
+impl FromStr for Annotation {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v.trim() {
+            "mean" => Ok(Self::Mean),
+            "median" => Ok(Self::Median),
+            "mode" => Ok(Self::Mode),
+            _ => Err(Error::Invalid(format!(
+                "Wrong annotation: {}, use mean, median or mode.",
+                v
+            ))),
+        }
+    }
+}
+
+/// How curves are drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum CurveStyle {
+    #[default]
+    Line,
+    /// One bar of each insert size.
+    Bars,
+    /// Line with the area below filled.
+    FilledArea,
+}
+
+impl FromStr for CurveStyle {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "line" => Ok(Self::Line),
+            "bars" => Ok(Self::Bars),
+            "filled-area" => Ok(Self::FilledArea),
+            _ => Err(Error::Invalid(format!(
+                "Wrong style: {}, use line, bars or filled-area.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Kernel of curve smoothing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Kernel {
+    /// Moving average.
+    #[default]
+    Mean,
+    /// Gaussian weights, the window spans 4 standard deviations.
+    Gaussian,
+}
+
+impl Kernel {
+    /// Weights of offsets `-window / 2..=window / 2`.
+    fn weights(&self, window: usize) -> Vec<f64> {
+        let half = (window / 2) as f64;
+        let sigma = f64::max(window as f64 / 4.0, 0.5);
+        (0..=window / 2 * 2)
+            .map(|i| match self {
+                Self::Mean => 1.0,
+                Self::Gaussian => (-0.5 * ((i as f64 - half) / sigma).powi(2)).exp(),
+            })
+            .collect()
+    }
+
+    /// Smoothed values, weights are renormalized at both ends.
+    pub fn smooth(&self, values: &[f64], window: usize) -> Vec<f64> {
+        let weights = self.weights(window);
+        let half = weights.len() / 2;
+        (0..values.len())
+            .map(|i| {
+                let (mut sum, mut norm) = (0.0, 0.0);
+                for (j, w) in weights.iter().enumerate() {
+                    if let Some(v) = (i + j).checked_sub(half).and_then(|k| values.get(k)) {
+                        sum += v * w;
+                        norm += w;
+                    }
+                }
+                sum / norm
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Kernel {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "mean" => Ok(Self::Mean),
+            "gaussian" => Ok(Self::Gaussian),
+            _ => Err(Error::Invalid(format!(
+                "Wrong kernel: {}, use mean or gaussian.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Color from hex `#RRGGBB`, `#` is optional.
+pub fn parse_color(v: &str) -> Result<(u8, u8, u8)> {
+    let hex = v.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|c| u8::from_str_radix(c, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok((r, g, b)),
+        _ => Err(Error::Invalid(format!("Wrong color: {}, use #RRGGBB.", v))),
+    }
+}
+
+/// Values on the y axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum YValue {
+    /// Proportion of pairs in each bin.
+    #[default]
+    Fraction,
+    /// Read pairs in each bin.
+    Counts,
+    /// Proportion per bp, comparable across bin sizes.
+    Density,
+}
+
+impl FromStr for YValue {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "fraction" => Ok(Self::Fraction),
+            "counts" => Ok(Self::Counts),
+            "density" => Ok(Self::Density),
+            _ => Err(Error::Invalid(format!(
+                "Wrong y values: {}, use fraction, counts or density.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Top of the y axis of pics without log y.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum YMax {
+    /// `Y_MAX_HEADROOM` times `Y_MAX_PERCENTILE` of drawn values, so one spike bin does
+    /// not flatten the rest.
+    #[default]
+    Auto,
+    Value(f64),
+}
+
+impl YMax {
+    /// Top of the axis of drawn `ys`.
+    fn top(&self, mut ys: Vec<f64>) -> f64 {
+        match self {
+            Self::Value(v) => *v,
+            Self::Auto => {
+                ys.retain(|v| v.is_finite());
+                ys.sort_by(f64::total_cmp);
+                // Nearest rank below, a single top bin of few points is left out too.
+                let k = ((ys.len().max(1) - 1) as f64 * Y_MAX_PERCENTILE / 100.0) as usize;
+                match ys.get(k) {
+                    Some(v) if *v > 0.0 => v * Y_MAX_HEADROOM,
+                    _ => 1.0,
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for YMax {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "auto" => Ok(Self::Auto),
+            _ => v
+                .parse::<f64>()
+                .ok()
+                .filter(|v| *v > 0.0 && v.is_finite())
+                .map(Self::Value)
+                .ok_or_else(|| {
+                    Error::Invalid(format!(
+                        "Wrong y max: {}, use auto or a positive number.",
+                        v
+                    ))
+                }),
+        }
+    }
+}
+
+/// Colors of everything but curves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Theme {
+    #[default]
+    Light,
+    /// Light text and axes on a dark background, for dark dashboards.
+    Dark,
+    /// Gray text and axes, legend without border.
+    Minimal,
+}
+
+/// Colors of a theme.
+struct Palette {
+    background: (u8, u8, u8),
+    text: (u8, u8, u8),
+    axis: (u8, u8, u8),
+    legend_border: bool,
+}
+
+impl Theme {
+    fn palette(&self) -> Palette {
+        match self {
+            Self::Light => Palette {
+                background: (255, 255, 255),
+                text: (0, 0, 0),
+                axis: (0, 0, 0),
+                legend_border: true,
+            },
+            Self::Dark => Palette {
+                background: (30, 30, 30),
+                text: (220, 220, 220),
+                axis: (160, 160, 160),
+                legend_border: true,
+            },
+            Self::Minimal => Palette {
+                background: (255, 255, 255),
+                text: (90, 90, 90),
+                axis: (190, 190, 190),
+                legend_border: false,
+            },
+        }
+    }
+}
+
+impl FromStr for Theme {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v {
+            "light" => Ok(Self::Light),
+            "dark" => Ok(Self::Dark),
+            "minimal" => Ok(Self::Minimal),
+            _ => Err(Error::Invalid(format!(
+                "Wrong theme: {}, use light, dark or minimal.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Language of default axis labels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Lang {
+    #[default]
+    Zh,
+    En,
+}
+
+impl Lang {
+    fn x_label(&self) -> &'static str {
+        match self {
+            Self::Zh => "插入片段大小(bp)",
+            Self::En => "Insert size (bp)",
+        }
+    }
+
+    fn y_label(&self, y: YValue, cumulative: bool) -> &'static str {
+        match (self, y, cumulative) {
+            (Self::Zh, YValue::Fraction, false) => "比例",
+            (Self::Zh, YValue::Fraction, true) => "累积比例",
+            (Self::Zh, YValue::Counts, false) => "片段数",
+            (Self::Zh, YValue::Counts, true) => "累积片段数",
+            (Self::Zh, YValue::Density, _) => "密度(每bp)",
+            (Self::En, YValue::Fraction, false) => "Proportion",
+            (Self::En, YValue::Fraction, true) => "Cumulative proportion",
+            (Self::En, YValue::Counts, false) => "Read pairs",
+            (Self::En, YValue::Counts, true) => "Cumulative read pairs",
+            (Self::En, YValue::Density, _) => "Density (per bp)",
+        }
+    }
+}
+
+impl FromStr for Lang {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v.to_ascii_lowercase().as_str() {
+            "zh" => Ok(Self::Zh),
+            "en" => Ok(Self::En),
+            _ => Err(Error::Invalid(format!(
+                "Wrong language: {}, use zh or en.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Title and axis labels of a pic.
+pub struct Labels {
+    pub title: Option<String>,
+    pub x: String,
+    pub y: String,
+}
+
+/// Resolution the pic size is given at, other dpi scales pixels, fonts and lines of png pics.
+pub const BASE_DPI: u32 = 96;
+
+/// How to draw the pic.
+#[derive(Clone, Default)]
+pub struct PlotOptions {
+    /// Plot proportions on a log10 axis, empty insert sizes are left out.
+    pub log_y: bool,
+    /// Plot cumulative proportions, the empirical CDF.
+    pub cumulative: bool,
+    /// Statistics marked by dashed vertical lines with labels.
+    pub annotate: Vec<Annotation>,
+    /// Expected insert sizes, e.g. of amplicons, marked like statistics.
+    pub expected_sizes: Vec<usize>,
+    /// Title above the pic, none by default.
+    pub title: Option<String>,
+    /// X axis label, default by `lang`.
+    pub x_label: Option<String>,
+    /// Y axis label, default by `lang`.
+    pub y_label: Option<String>,
+    /// Language of default labels.
+    pub lang: Lang,
+    /// Pic width, pixels at `BASE_DPI` for png and svg, points for pdf, default 700.
+    pub width: Option<u32>,
+    /// Pic height, like `width`, default 610.
+    pub height: Option<u32>,
+    /// Resolution of png pics, default `BASE_DPI`.
+    pub dpi: Option<u32>,
+    /// Font family of svg, png and pdf pics, falls back to `FALLBACK_FONTS` when not found.
+    pub font: Option<String>,
+    /// Curve colors in sample order, `COLORS` for the rest.
+    pub colors: Vec<(u8, u8, u8)>,
+    /// Curve width in pixels, default 2.
+    pub line_width: Option<u32>,
+    /// How curves are drawn in svg, png and pdf pics.
+    pub style: CurveStyle,
+    /// Window in bp smoothing proportions of svg, png and pdf pics, counts are kept raw.
+    pub smooth: Option<usize>,
+    /// Kernel of `smooth`.
+    pub kernel: Kernel,
+    /// Insert sizes summed into each point of svg, png and pdf pics, default 1.
+    pub bin_size: Option<usize>,
+    /// Insert sizes drawn on the x axis of svg, png and pdf pics, from 0 to the upper bound
+    /// by default.
+    pub x_min: Option<usize>,
+    pub x_max: Option<usize>,
+    /// Values on the y axis of svg, png and pdf pics.
+    pub y: YValue,
+    /// Top of the y axis of svg, png and pdf pics without log y.
+    pub y_max: YMax,
+    /// Draw components of a Gaussian mixture of this many fitted to each curve, dashed, in
+    /// svg, png and pdf pics without cumulative.
+    pub fit_gmm: Option<usize>,
+    /// Colors of background, text and axes of svg, png and pdf pics.
+    pub theme: Theme,
+}
+
+impl PlotOptions {
+    /// Color of the `i`th curve.
+    pub fn color(&self, i: usize) -> (u8, u8, u8) {
+        self.colors
+            .get(i)
+            .copied()
+            .unwrap_or(COLORS[i % COLORS.len()])
+    }
+
+    /// Labels of the pic, `y` is the default of plain proportions when not set.
+    pub fn labels(&self) -> Labels {
+        Labels {
+            title: self.title.clone(),
+            x: self
+                .x_label
+                .clone()
+                .unwrap_or_else(|| self.lang.x_label().to_string()),
+            y: self
+                .y_label
+                .clone()
+                .unwrap_or_else(|| self.lang.y_label(self.y, self.cumulative).to_string()),
+        }
+    }
+}
+
+/// Fonts tried in order without `--font` or when it is not found, CJK ones for zh labels.
+pub const FALLBACK_FONTS: [&str; 4] = [
+    "WenQuanYi Zen Hei",
+    "Noto Sans CJK SC",
+    "Source Han Sans SC",
+    "Microsoft YaHei",
+];
+
+/// Whether the system has a font family, generic families always, only those in wasm.
+fn has_font(family: &str) -> bool {
+    match family {
+        "serif" | "sans-serif" | "monospace" => true,
+        #[cfg(not(target_arch = "wasm32"))]
+        _ => SystemSource::new().select_family_by_name(family).is_ok(),
+        #[cfg(target_arch = "wasm32")]
+        _ => false,
+    }
+}
+
+/// Font families installed on the system, sorted, none are listed in wasm.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn font_families() -> Vec<String> {
+    let mut families = SystemSource::new().all_families().unwrap_or_default();
+    families.sort();
+    families.dedup();
+    families
+}
+
+/// Font families installed on the system, sorted, none are listed in wasm.
+#[cfg(target_arch = "wasm32")]
+pub fn font_families() -> Vec<String> {
+    Vec::new()
+}
+
+/// Wanted font if found, else the first found fallback, `sans-serif` at last.
+pub(crate) fn pick_font(wanted: Option<&str>) -> String {
+    if let Some(v) = wanted.filter(|v| has_font(v)) {
+        return v.to_string();
+    }
+    let picked = FALLBACK_FONTS
+        .iter()
+        .copied()
+        .find(|v| has_font(v))
+        .unwrap_or("sans-serif");
+    if let Some(v) = wanted {
+        warn!("font {} is not found, using {}.", v, picked);
+    }
+    picked.to_string()
+}
+
+/// Drawing error with installed families, most of failures are missing fonts.
+pub(crate) fn font_err(font: &str, e: Error) -> Error {
+    Error::Plot(format!(
+        "{} (font {}), available font families: {}",
+        e,
+        font,
+        font_families().join(", ")
+    ))
+}
+
+/// Vertical line at a statistic of one curve.
+struct Mark {
+    x: f64,
+    label: String,
+    color: (u8, u8, u8),
+}
+
+/// Dashed curve of a mixture component fitted to a curve, in its color.
+struct Fit {
+    points: Vec<(f64, f64)>,
+    color: (u8, u8, u8),
+}
+
+/// Everything drawn, shared by backends.
+struct Figure<'a> {
+    samples: &'a [(String, Vec<u32>)],
+    x_range: (f64, f64),
+    // Points of each curve.
+    lines: Vec<Vec<(f64, f64)>>,
+    fits: Vec<Fit>,
+    y_range: (f64, f64),
+    labels: Labels,
+    marks: Vec<Mark>,
+    // Factor of fonts, lines and margins.
+    scale: f64,
+    // Family of all text.
+    font: String,
+    // Color of each curve.
+    colors: Vec<(u8, u8, u8)>,
+    line_width: u32,
+    style: CurveStyle,
+    // Width of a point in bp.
+    bin: f64,
+    theme: Theme,
+}
+
+/// Drawing error of any plotters backend.
+pub(crate) fn draw_err<E: std::fmt::Display>(e: E) -> Error {
+    Error::Plot(e.to_string())
+}
+
+/// Draw proportion lines of samples on a plotters drawing area.
+fn draw<DB: DrawingBackend>(root: &DrawingArea<DB, Shift>, fig: &Figure) -> Result<()> {
+    let palette = fig.theme.palette();
+    let rgb = |(r, g, b): (u8, u8, u8)| RGBColor(r, g, b);
+    let (background, text, axis) = (
+        rgb(palette.background),
+        rgb(palette.text),
+        rgb(palette.axis),
+    );
+    root.fill(&background).map_err(draw_err)?;
+
+    let (y_low, y_high) = fig.y_range;
+    let px = |v: u32| (v as f64 * fig.scale).round() as u32;
+    let pt = |v: f64| v * fig.scale;
+    let font = FontFamily::from(fig.font.as_str());
+    let mut builder = ChartBuilder::on(root);
+    builder
+        .x_label_area_size(px(35))
+        .y_label_area_size(px(40))
+        .margin(px(5));
+    if let Some(title) = &fig.labels.title {
+        builder.caption(title, (font, pt(24.0)).into_font().color(&text));
+    }
+    let mut chart = builder
+        .build_cartesian_2d(
+            (fig.x_range.0..(fig.x_range.1 + 1.0))
+                .step(1.0)
+                .use_round()
+                .into_segmented(),
+            y_low..y_high,
+        )
+        .map_err(draw_err)?;
+
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .bold_line_style(background.mix(0.3))
+        .axis_style(axis)
+        .x_desc(fig.labels.x.as_str())
+        .y_desc(fig.labels.y.as_str())
+        .label_style((font, pt(12.0)).into_font().color(&text))
+        .axis_desc_style((font, pt(20.0)).into_font().color(&text))
+        .draw()
+        .map_err(draw_err)?;
+
+    let (legend_len, stroke) = (px(20) as i32, px(fig.line_width));
+    for (((name, _), line), (r, g, b)) in fig.samples.iter().zip(&fig.lines).zip(&fig.colors) {
+        let color = RGBColor(*r, *g, *b);
+        let points = line.iter().map(|(x, y)| (SegmentValue::Exact(*x), *y));
+        match fig.style {
+            CurveStyle::Line => {
+                chart.draw_series(LineSeries::new(points, color.stroke_width(stroke)))
+            }
+            // Translucent so overlaid curves stay visible.
+            CurveStyle::FilledArea => chart.draw_series(
+                AreaSeries::new(points, y_low, color.mix(0.3))
+                    .border_style(color.stroke_width(stroke)),
+            ),
+            CurveStyle::Bars => chart.draw_series(line.iter().map(|(x, y)| {
+                Rectangle::new(
+                    [
+                        (SegmentValue::Exact(*x), y_low),
+                        (SegmentValue::Exact(*x + fig.bin), *y),
+                    ],
+                    color.mix(0.5).filled(),
+                )
+            })),
+        }
+        .map_err(draw_err)?
+        .label(name.as_str())
+        .legend(move |(x, y)| {
+            PathElement::new(
+                vec![(x, y), (x + legend_len, y)],
+                color.stroke_width(stroke),
+            )
+        });
+    }
+    // Fitted components dashed in the color of their curve.
+    for fit in &fig.fits {
+        let points = fit
+            .points
+            .iter()
+            .map(|(x, y)| (SegmentValue::Exact(*x), *y));
+        chart
+            .draw_series(DashedLineSeries::new(
+                points,
+                px(6),
+                px(4),
+                rgb(fit.color).stroke_width(px(1)),
+            ))
+            .map_err(draw_err)?;
+    }
+    // Labels are stacked down from the top so they do not overlap.
+    for (i, mark) in fig.marks.iter().enumerate() {
+        let color = RGBColor(mark.color.0, mark.color.1, mark.color.2);
+        let x = SegmentValue::Exact(mark.x);
+        chart
+            .draw_series(DashedLineSeries::new(
+                vec![(x, y_low), (SegmentValue::Exact(mark.x), y_high)],
+                px(6),
+                px(4),
+                color.stroke_width(px(1)),
+            ))
+            .map_err(draw_err)?;
+        let y = y_high - (y_high - y_low) * 0.05 * (i + 1) as f64;
+        chart
+            .draw_series(std::iter::once(Text::new(
+                mark.label.clone(),
+                (SegmentValue::Exact(mark.x + 2.0), y),
+                (font, pt(14.0)).into_font().color(&color),
+            )))
+            .map_err(draw_err)?;
+    }
+    if fig.samples.len() > 1 {
+        let mut labels = chart.configure_series_labels();
+        labels
+            .label_font((font, pt(12.0)).into_font().color(&text))
+            .background_style(background.mix(0.8));
+        if palette.legend_border {
+            labels.border_style(axis);
+        }
+        labels.draw().map_err(draw_err)?;
+    }
+    root.present().map_err(draw_err)
+}
+
+pub fn plot(
+    pic: &str,
+    upper: &usize,
+    format: &PicFormat,
+    samples: &[(String, Vec<u32>)],
+    opts: &PlotOptions,
+) -> Result<()> {
+    if opts.cumulative && opts.y == YValue::Density {
+        return Err(Error::Invalid(
+            "Density can not be cumulative, use fraction or counts.".to_string(),
+        ));
+    }
+    let (x_low, x_high) = (opts.x_min.unwrap_or(0), opts.x_max.unwrap_or(*upper));
+    if x_low >= x_high {
+        return Err(Error::Invalid(format!(
+            "X range {}-{} is empty.",
+            x_low, x_high
+        )));
+    }
+    let (x_low, x_high) = (x_low as f64, x_high as f64);
+    // Value of each bin, at its smallest insert size.
+    let bin = opts.bin_size.unwrap_or(1);
+    let mut lines: Vec<Vec<(f64, f64)>> = samples
+        .iter()
+        .map(|(_, data)| {
+            let total = data.iter().sum::<u32>() as f64;
+            data.chunks(bin)
+                .enumerate()
+                .map(|(i, v)| {
+                    let count = v.iter().map(|v| *v as u64).sum::<u64>() as f64;
+                    let y = match opts.y {
+                        YValue::Fraction => count / total,
+                        YValue::Counts => count,
+                        YValue::Density => count / total / bin as f64,
+                    };
+                    ((i * bin) as f64, y)
+                })
+                .collect()
+        })
+        .collect();
+    if let Some(window) = opts.smooth {
+        for line in lines.iter_mut() {
+            let ys: Vec<f64> = line.iter().map(|v| v.1).collect();
+            let smoothed = opts.kernel.smooth(&ys, usize::max(window / bin, 1));
+            line.iter_mut().zip(smoothed).for_each(|(v, y)| v.1 = y);
+        }
+    }
+    if opts.cumulative {
+        for line in lines.iter_mut() {
+            let mut accum = 0.0;
+            line.iter_mut().for_each(|v| {
+                accum += v.1;
+                v.1 = accum;
+            });
+        }
+    }
+    // Components fitted to counts of each curve, in values of the y axis at bin centers.
+    let mut fits = Vec::new();
+    for (i, (_, counts)) in samples.iter().enumerate() {
+        let k = match opts.fit_gmm.filter(|_| !opts.cumulative) {
+            Some(v) => v,
+            None => break,
+        };
+        let total = counts.iter().map(|v| *v as f64).sum::<f64>();
+        let scale = match opts.y {
+            YValue::Fraction => bin as f64,
+            YValue::Counts => total * bin as f64,
+            YValue::Density => 1.0,
+        };
+        for c in Mixture::fit(counts, k).map_or_else(Vec::new, |v| v.components) {
+            let center = |x: usize| x as f64 + (bin - 1) as f64 / 2.0;
+            let points = (0..counts.len())
+                .step_by(bin)
+                .map(|x| (x as f64, scale * c.density(center(x))))
+                .collect();
+            fits.push(Fit {
+                points,
+                color: opts.color(i),
+            });
+        }
+    }
+    // Cut to the x range after smoothing and accumulating, curves keep their values.
+    for line in lines
+        .iter_mut()
+        .chain(fits.iter_mut().map(|v| &mut v.points))
+    {
+        line.retain(|v| v.0 >= x_low && v.0 <= x_high);
+    }
+    let mut labels = opts.labels();
+    let ys = |lines: &[Vec<(f64, f64)>]| lines.iter().flatten().map(|v| v.1).collect::<Vec<f64>>();
+    // Marks from counts of each curve, not from drawn values.
+    let mut marks = Vec::new();
+    for (i, (name, counts)) in samples.iter().enumerate() {
+        for annotation in &opts.annotate {
+            if let Some(x) = annotation.value(counts) {
+                let prefix = if samples.len() > 1 {
+                    format!("{} ", name)
+                } else {
+                    String::new()
+                };
+                marks.push(Mark {
+                    x,
+                    label: format!("{}{} {:.1}", prefix, annotation.name(), x),
+                    color: opts.color(i),
+                });
+            }
+        }
+    }
+    for size in &opts.expected_sizes {
+        marks.push(Mark {
+            x: *size as f64,
+            label: format!("expected {}", size),
+            color: opts.theme.palette().axis,
+        });
+    }
+    marks.retain(|v| v.x >= x_low && v.x <= x_high);
+    let y_range = if opts.log_y {
+        // Log10 of proportions, whole decades around the data.
+        for line in lines.iter_mut() {
+            line.retain(|v| v.1 > 0.0);
+            line.iter_mut().for_each(|v| v.1 = v.1.log10());
+        }
+        let ys = ys(&lines);
+        let low = ys.iter().copied().fold(f64::NAN, f64::min);
+        let high = ys.iter().copied().fold(f64::NAN, f64::max);
+        // Fitted tails below the data are left out.
+        for fit in fits.iter_mut() {
+            fit.points
+                .retain(|v| v.1 > 0.0 && v.1.log10() >= low.floor());
+            fit.points.iter_mut().for_each(|v| v.1 = v.1.log10());
+        }
+        if opts.y_label.is_none() {
+            labels.y = format!("log10({})", labels.y);
+        }
+        if low.is_nan() {
+            (-1.0, 0.0)
+        } else {
+            (low.floor(), f64::max(high.ceil(), low.floor() + 1.0))
+        }
+    } else {
+        (0.0, opts.y_max.top(ys(&lines)))
+    };
+    let fig = Figure {
+        samples,
+        x_range: (x_low, x_high),
+        lines,
+        fits,
+        y_range,
+        labels,
+        marks,
+        scale: 1.0,
+        font: String::new(),
+        colors: (0..samples.len()).map(|i| opts.color(i)).collect(),
+        line_width: opts.line_width.unwrap_or(2),
+        style: opts.style,
+        bin: bin as f64,
+        theme: opts.theme,
+    };
+    let (width, height) = (opts.width.unwrap_or(700), opts.height.unwrap_or(610));
+
+    // Plot line.
+    match format {
+        PicFormat::Svg => {
+            // Drawn to a string, written like other outputs so `/dev/stdout` works too.
+            let fig = Figure {
+                font: pick_font(opts.font.as_deref()),
+                ..fig
+            };
+            let mut svg = String::new();
+            {
+                let root = SVGBackend::with_string(&mut svg, (width, height)).into_drawing_area();
+                draw(&root, &fig).map_err(|e| font_err(&fig.font, e))?;
+            }
+            create_file(pic)?.write_all(svg.as_bytes())?;
+        }
+        PicFormat::Png => {
+            // Encoded by hand, plotters picks the image format from the path suffix.
+            let scale = opts.dpi.unwrap_or(BASE_DPI) as f64 / BASE_DPI as f64;
+            let fig = Figure {
+                scale,
+                font: pick_font(opts.font.as_deref()),
+                ..fig
+            };
+            let w = (width as f64 * scale).round() as u32;
+            let h = (height as f64 * scale).round() as u32;
+            let mut buf = vec![0u8; w as usize * h as usize * 3];
+            {
+                let root = BitMapBackend::with_buffer(&mut buf, (w, h)).into_drawing_area();
+                draw(&root, &fig).map_err(|e| font_err(&fig.font, e))?;
+            }
+            image::save_buffer_with_format(
+                pic,
+                &buf,
+                w,
+                h,
+                image::ColorType::Rgb8,
+                image::ImageFormat::Png,
+            )
+            .map_err(|e| Error::Plot(e.to_string()).in_file(pic))?;
+        }
+        #[cfg(feature = "pdf")]
+        PicFormat::Pdf => {
+            let surface = cairo::PdfSurface::new(width as f64, height as f64, pic)
+                .map_err(|e| Error::Plot(e.to_string()).in_file(pic))?;
+            {
+                let context =
+                    cairo::Context::new(&surface).map_err(|e| Error::Plot(e.to_string()))?;
+                let root = plotters_cairo::CairoBackend::new(&context, (width, height))
+                    .map_err(|e| Error::Plot(e.to_string()))?
+                    .into_drawing_area();
+                let fig = Figure {
+                    font: pick_font(opts.font.as_deref()),
+                    ..fig
+                };
+                draw(&root, &fig).map_err(|e| font_err(&fig.font, e))?;
+            }
+            surface.finish();
+        }
+        #[cfg(not(feature = "pdf"))]
+        PicFormat::Pdf => return Err(Error::Invalid(PDF_DISABLED.to_string())),
+        PicFormat::Html => {
+            let mut labels = opts.labels();
+            // Html pic is plain proportions, whatever the options.
+            if opts.y_label.is_none() {
+                labels.y = opts.lang.y_label(YValue::Fraction, false).to_string();
+            }
+            write_html(
+                BufWriter::new(create_file(pic)?),
+                samples,
+                &labels,
+                &fig.colors,
+            )?
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::error::{Error, Result};
+
+/// Records between progress bar updates.
+const UPDATE_EVERY: u64 = 1 << 16;
+
+/// Progress of reading one input on stderr, records processed and input bytes consumed.
+///
+/// Input of known size shows a bar with throughput and ETA, stdin and cram only a spinner.
+pub struct Progress {
+    bar: ProgressBar,
+    bytes: Arc<AtomicU64>,
+    records: u64,
+    // Input path shown before counts.
+    name: String,
+}
+
+impl Progress {
+    /// Progress of input at `path`, `-` for stdin.
+    pub fn new(path: &str) -> Result<Self> {
+        let len = match path {
+            "-" => None,
+            _ => fs::metadata(path)
+                .ok()
+                .filter(|v| v.is_file())
+                .map(|v| v.len()),
+        };
+        let bar = match len {
+            Some(len) => ProgressBar::new(len).with_style(
+                ProgressStyle::with_template(
+                    "{msg} [{elapsed_precise}] [{wide_bar}] {bytes}/{total_bytes} ({bytes_per_sec}, ETA {eta})",
+                )
+                .map_err(|e| Error::Invalid(e.to_string()))?
+                .progress_chars("=> "),
+            ),
+            None => ProgressBar::new_spinner().with_style(
+                ProgressStyle::with_template("{spinner} {msg} [{elapsed_precise}] {bytes} ({bytes_per_sec})")
+                    .map_err(|e| Error::Invalid(e.to_string()))?,
+            ),
+        };
+        let progress = Self {
+            bar,
+            bytes: Arc::new(AtomicU64::new(0)),
+            records: 0,
+            name: path.to_string(),
+        };
+        progress.update();
+        Ok(progress)
+    }
+
+    /// Counter of input bytes, for a `CountingReader`.
+    pub fn counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.bytes)
+    }
+
+    /// Count one record read, the bar is redrawn every few records.
+    pub fn record(&mut self) {
+        self.records += 1;
+        if self.records.is_multiple_of(UPDATE_EVERY) {
+            self.update();
+        }
+    }
+
+    fn update(&self) {
+        self.bar.set_position(self.bytes.load(Ordering::Relaxed));
+        self.bar
+            .set_message(format!("{}: {} records", self.name, self.records));
+    }
+
+    /// Draw final counts and leave the bar on screen.
+    pub fn finish(&self) {
+        self.update();
+        self.bar
+            .finish_with_message(format!("{}: {} records", self.name, self.records));
+    }
+}
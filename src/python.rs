@@ -0,0 +1,110 @@
+use numpy::{IntoPyArray, PyArray1};
+use pyo3::exceptions::{PyFileNotFoundError, PyOSError, PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use crate::error::Error;
+use crate::record::parse_flags;
+use crate::{
+    compute_histogram, compute_insert_sizes, Options, Result, Targets, ATAC_UPPER, CFDNA_UPPER,
+};
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::NotFound(_) => PyFileNotFoundError::new_err(e.to_string()),
+            Error::Invalid(_) => PyValueError::new_err(e.to_string()),
+            _ => PyOSError::new_err(e.to_string()),
+        }
+    }
+}
+
+/// Flags of an int or a string like `--include-flags`.
+fn flags(v: &PyAny) -> PyResult<u16> {
+    match v.extract::<u16>() {
+        Ok(v) => Ok(v),
+        Err(_) => Ok(parse_flags(v.extract()?)?),
+    }
+}
+
+/// Options from keyword filters named like the command line options, with underscores.
+fn options(filters: Option<&PyDict>) -> PyResult<Options> {
+    let mut opts = Options::default();
+    let mut upper = None;
+    for (k, v) in filters.into_iter().flatten() {
+        let key: &str = k.extract()?;
+        match key {
+            "upper" => upper = Some(v.extract()?),
+            "reference" => opts.reference = Some(v.extract()?),
+            "no_dups" => opts.no_dups = v.extract()?,
+            "include_qcfail" => opts.include_qcfail = v.extract()?,
+            "include_flags" => opts.include_flags = flags(v)?,
+            "exclude_flags" => opts.exclude_flags = flags(v)?,
+            "count_by" => opts.count_by = v.extract::<&str>()?.parse()?,
+            "library_type" => opts.library_type = v.extract::<&str>()?.parse()?,
+            "clip_adjust" => opts.clip_adjust = v.extract()?,
+            "regions" => {
+                opts.regions = v
+                    .extract::<Vec<&str>>()?
+                    .iter()
+                    .map(|v| v.parse())
+                    .collect::<Result<_>>()?
+            }
+            "targets" => opts.targets = Some(Targets::from_path(v.extract()?)?),
+            "contigs" => opts.contigs = Some(v.extract()?),
+            "exclude_contigs" => opts.exclude_contigs = v.extract()?,
+            "per_chromosome" => opts.per_chromosome = v.extract()?,
+            "per_read_group" => opts.per_read_group = v.extract()?,
+            "per_gc" => opts.per_gc = v.extract()?,
+            "cfdna" => opts.cfdna = v.extract()?,
+            "atac" => opts.atac = v.extract()?,
+            "threads" => opts.threads = v.extract()?,
+            "sample_fraction" => opts.sample_fraction = Some(v.extract()?),
+            "seed" => opts.seed = v.extract()?,
+            "max_pairs" => opts.max_pairs = Some(v.extract()?),
+            "strict" => opts.strict = v.extract()?,
+            "lenient" => opts.lenient = v.extract()?,
+            _ => return Err(PyTypeError::new_err(format!("Unexpected filter: {}", key))),
+        }
+    }
+    // Upper follows the mode and library type unless given, like `-m`.
+    opts.upper = upper.unwrap_or(if opts.atac {
+        ATAC_UPPER
+    } else if opts.cfdna {
+        CFDNA_UPPER
+    } else {
+        opts.library_type.default_upper()
+    });
+    Ok(opts)
+}
+
+/// Summary of bam, sam or cram at `path` as a dict like the summary json, with keyword filters.
+#[pyfunction]
+#[pyo3(signature = (path, **filters))]
+fn compute_insert_size(py: Python<'_>, path: &str, filters: Option<&PyDict>) -> PyResult<PyObject> {
+    let opts = options(filters)?;
+    let sum = py.allow_threads(|| compute_insert_sizes(path, &opts))?;
+    let json = serde_json::to_string(&sum).map_err(Error::from)?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
+
+/// Counts by insert size from 0 to upper of bam, sam or cram at `path`, with keyword filters.
+#[pyfunction]
+#[pyo3(signature = (path, **filters))]
+fn histogram<'py>(
+    py: Python<'py>,
+    path: &str,
+    filters: Option<&PyDict>,
+) -> PyResult<&'py PyArray1<u32>> {
+    let opts = options(filters)?;
+    let hist = py.allow_threads(|| compute_histogram(path, &opts))?;
+    Ok(hist.counts().to_vec().into_pyarray(py))
+}
+
+/// Python module `insize`, built with the `python` feature, e.g. by maturin.
+#[pymodule]
+fn insize(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compute_insert_size, m)?)?;
+    m.add_function(wrap_pyfunction!(histogram, m)?)?;
+    Ok(())
+}
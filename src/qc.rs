@@ -0,0 +1,247 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::summary::Summary;
+
+/// Summary value a threshold is put on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Count,
+    TotalCount,
+    Mean,
+    Median,
+    Mode,
+    Sd,
+    TotalSd,
+    Mad,
+    Iqr,
+    Skewness,
+    Kurtosis,
+    Q1,
+    Q3,
+    TrimmedMean,
+    Min,
+    Max,
+    MissingAmplicons,
+    ShortInserts,
+}
+
+/// Metrics by name, as written in expressions.
+const METRICS: [(&str, Metric); 18] = [
+    ("count", Metric::Count),
+    ("total_count", Metric::TotalCount),
+    ("mean", Metric::Mean),
+    ("median", Metric::Median),
+    ("mode", Metric::Mode),
+    ("sd", Metric::Sd),
+    ("total_sd", Metric::TotalSd),
+    ("mad", Metric::Mad),
+    ("iqr", Metric::Iqr),
+    ("skewness", Metric::Skewness),
+    ("kurtosis", Metric::Kurtosis),
+    ("q1", Metric::Q1),
+    ("q3", Metric::Q3),
+    ("trimmed_mean", Metric::TrimmedMean),
+    ("min", Metric::Min),
+    ("max", Metric::Max),
+    ("missing_amplicons", Metric::MissingAmplicons),
+    ("short_insert_fraction", Metric::ShortInserts),
+];
+
+impl Metric {
+    pub fn value(&self, sum: &Summary) -> f64 {
+        match self {
+            Self::Count => sum.count as f64,
+            Self::TotalCount => sum.all_count as f64,
+            Self::Mean => sum.mean,
+            Self::Median => sum.median,
+            Self::Mode => sum.mode as f64,
+            Self::Sd => sum.std,
+            Self::TotalSd => sum.all_std,
+            Self::Mad => sum.mad,
+            Self::Iqr => sum.iqr as f64,
+            Self::Skewness => sum.skewness,
+            Self::Kurtosis => sum.kurtosis,
+            Self::Q1 => sum.q1 as f64,
+            Self::Q3 => sum.q3 as f64,
+            Self::TrimmedMean => sum.trimmed_mean,
+            Self::Min => sum.min as f64,
+            Self::Max => sum.max as f64,
+            Self::MissingAmplicons => sum.expected.iter().filter(|v| v.missing).count() as f64,
+            Self::ShortInserts => sum.short_inserts.unwrap_or(0.0),
+        }
+    }
+}
+
+/// Comparison operators, two character ones first so they match before `<` and `>`.
+const OPS: [&str; 6] = ["<=", ">=", "==", "!=", "<", ">"];
+
+/// One comparison like `median<250`.
+#[derive(Clone, Debug)]
+struct Condition {
+    name: String,
+    metric: Metric,
+    op: &'static str,
+    value: f64,
+}
+
+impl Condition {
+    fn holds(&self, sum: &Summary) -> bool {
+        let v = self.metric.value(sum);
+        match self.op {
+            "<=" => v <= self.value,
+            ">=" => v >= self.value,
+            "==" => v == self.value,
+            "!=" => v != self.value,
+            "<" => v < self.value,
+            _ => v > self.value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.name, self.op, self.value)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        let err = || Error::Invalid(format!("Wrong QC condition: {}", v));
+        let (i, op) = OPS
+            .iter()
+            .filter_map(|op| v.find(op).map(|i| (i, *op)))
+            .min_by_key(|(i, op)| (*i, std::cmp::Reverse(op.len())))
+            .ok_or_else(err)?;
+        let name = v[..i].trim();
+        let metric = METRICS
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, m)| *m)
+            .ok_or_else(|| {
+                let names: Vec<&str> = METRICS.iter().map(|(k, _)| *k).collect();
+                Error::Invalid(format!(
+                    "Unknown QC metric: {}, use {}.",
+                    name,
+                    names.join(", ")
+                ))
+            })?;
+        let value = v[i + op.len()..].trim().parse().map_err(|_| err())?;
+        Ok(Self {
+            name: name.to_string(),
+            metric,
+            op,
+            value,
+        })
+    }
+}
+
+/// Thresholds failing a sample, like `median<250 || sd>120 && count<1e6`, `&&` binds tighter.
+#[derive(Clone, Debug)]
+pub struct FailIf {
+    /// Any group failing fails, all conditions of a group must hold.
+    groups: Vec<Vec<Condition>>,
+}
+
+impl FailIf {
+    /// Failed groups of a summary, empty when passed.
+    pub fn failures(&self, sum: &Summary) -> Vec<String> {
+        self.groups
+            .iter()
+            .filter(|group| group.iter().all(|c| c.holds(sum)))
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|c| format!("{} ({})", c, c.metric.value(sum)))
+                    .collect::<Vec<String>>()
+                    .join(" && ")
+            })
+            .collect()
+    }
+}
+
+impl FromStr for FailIf {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        let groups = v
+            .split("||")
+            .map(|group| group.split("&&").map(str::parse).collect())
+            .collect::<Result<Vec<Vec<Condition>>>>()?;
+        Ok(Self { groups })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(median: f64, std: f64, count: u32) -> Summary {
+        Summary {
+            median,
+            std,
+            count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let fail: FailIf = "median<250 || sd>120 && count<1000".parse().unwrap();
+        assert_eq!(fail.groups.len(), 2);
+        assert_eq!(fail.groups[1].len(), 2);
+        // Low median alone fails, high SD only with few pairs.
+        assert_eq!(fail.failures(&summary(200.0, 50.0, 5000)).len(), 1);
+        assert!(fail.failures(&summary(300.0, 150.0, 5000)).is_empty());
+        assert_eq!(
+            fail.failures(&summary(300.0, 150.0, 500)),
+            ["sd>120 (150) && count<1000 (500)"]
+        );
+    }
+
+    #[test]
+    fn two_character_operators() {
+        for (v, op) in [
+            ("median<=250", "<="),
+            ("median>=250", ">="),
+            ("median==250", "=="),
+            ("median!=250", "!="),
+            ("median<250", "<"),
+            ("median>250", ">"),
+        ] {
+            let c: Condition = v.parse().unwrap();
+            assert_eq!((c.metric, c.op, c.value), (Metric::Median, op, 250.0));
+        }
+        let holds = |v: &str, median| {
+            v.parse::<Condition>()
+                .unwrap()
+                .holds(&summary(median, 0.0, 0))
+        };
+        assert!(holds("median <= 250", 250.0));
+        assert!(!holds("median < 250", 250.0));
+        assert!(holds("median >= 250", 250.0));
+        assert!(!holds("median > 250", 250.0));
+        assert!(holds("median == 250", 250.0));
+        assert!(holds("median != 250", 251.0));
+    }
+
+    #[test]
+    fn bad_input() {
+        for v in [
+            "",
+            "median",
+            "median=250",
+            "median<",
+            "median<abc",
+            "<250",
+            "medain<250",
+            "median<250 ||",
+            "median<250 && && sd>1",
+        ] {
+            assert!(v.parse::<FailIf>().is_err(), "{}", v);
+        }
+    }
+}
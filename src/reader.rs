@@ -0,0 +1,158 @@
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::bam::BamReader;
+use crate::cram::CramReader;
+use crate::error::{open_file, Error, Result};
+use crate::header::Header;
+use crate::http::{is_url, HttpReader};
+use crate::record::{Fields, Record};
+use crate::sam::SamReader;
+
+/// Source of alignment records.
+pub trait RecordReader {
+    /// Read next record into `record`, return `false` at end of input.
+    fn read_into(&mut self, record: &mut Record) -> Result<bool>;
+
+    /// Name of reference id.
+    fn reference_name(&self, id: i32) -> Option<&str>;
+    /// Read group ids from `@RG` header lines, sam header lines are only seen after reading.
+    fn read_groups(&self) -> &[String];
+    /// Header text and reference dictionary, sam header lines are only seen after reading.
+    fn header(&self) -> &Header;
+
+    /// Optional fields to fill in, a reader may skip parsing others. All by default.
+    fn set_fields(&mut self, _fields: Fields) {}
+
+    /// Whether bam input ended without the bgzf EOF block, likely truncated.
+    fn missing_eof(&self) -> bool {
+        false
+    }
+
+    /// Bgzf virtual offset of the next record of bam inflated on the reading thread.
+    fn virtual_offset(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Reader adding bytes read to a shared counter, e.g. for progress of compressed input.
+pub struct CountingReader<R: Read> {
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R, count: Arc<AtomicU64>) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for CountingReader<R> {
+    /// Seek, the count becomes the new position.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let v = self.inner.seek(pos)?;
+        self.count.store(v, Ordering::Relaxed);
+        Ok(v)
+    }
+}
+
+/// Open bam or sam held in memory, e.g. a slice dropped on a browser page, format is detected
+/// from leading bytes.
+pub fn open_bytes(bytes: Vec<u8>) -> Result<Box<dyn RecordReader>> {
+    let mut input = BufReader::new(Cursor::new(bytes));
+    let magic = input.fill_buf()?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        if magic.get(3).is_some_and(|v| v & 4 == 0) {
+            return Err(Error::NotBam);
+        }
+        Ok(Box::new(BamReader::new(input)?))
+    } else if magic.starts_with(b"CRAM") {
+        Err(Error::Invalid(
+            "Cram in memory is not supported.".to_string(),
+        ))
+    } else {
+        Ok(Box::new(SamReader::new(input)))
+    }
+}
+
+/// Open bam file at `path` for reading on from bgzf virtual offset `voffset`, 0 for the first
+/// record, bytes read are added to `count`.
+pub fn open_resumable_reader(
+    path: &str,
+    voffset: u64,
+    count: Arc<AtomicU64>,
+) -> Result<Box<dyn RecordReader>> {
+    let mut input = BufReader::new(CountingReader::new(open_file(path)?, count));
+    if !input.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        return Err(Error::Invalid("Checkpoints need bam input.".to_string()));
+    }
+    let mut bam = BamReader::new(input)?;
+    if voffset > 0 {
+        bam.seek(voffset)?;
+    }
+    Ok(Box::new(bam))
+}
+
+/// Open bam, sam or cram from path or url, `-` for stdin, format is detected from leading bytes.
+///
+/// With more than one `threads`, bam blocks are inflated ahead by a worker pool and cram
+/// is decoded with extra samtools threads.
+pub fn open_reader(
+    v: &str,
+    reference: Option<&str>,
+    threads: usize,
+) -> Result<Box<dyn RecordReader>> {
+    open_counting_reader(v, reference, threads, None)
+}
+
+/// Like `open_reader`, bytes read from bam or sam input are added to `count`.
+pub fn open_counting_reader(
+    v: &str,
+    reference: Option<&str>,
+    threads: usize,
+    count: Option<Arc<AtomicU64>>,
+) -> Result<Box<dyn RecordReader>> {
+    let input: Box<dyn Read> = if v == "-" {
+        Box::new(std::io::stdin())
+    } else if is_url(v) {
+        Box::new(HttpReader::new(v)?)
+    } else {
+        Box::new(open_file(v)?)
+    };
+    let input: Box<dyn Read> = match count {
+        Some(count) => Box::new(CountingReader::new(input, count)),
+        None => input,
+    };
+    let mut input = BufReader::new(input);
+    let magic = input.fill_buf()?;
+    // Bam is always bgzf (gzip) compressed, anything else is treated as sam text.
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        // Bgzf blocks have extra fields, plain gzip like a `.sam.gz` does not.
+        if magic.get(3).is_some_and(|v| v & 4 == 0) {
+            return Err(Error::NotBam);
+        }
+        if threads > 1 {
+            Ok(Box::new(BamReader::with_threads(input, threads)?))
+        } else {
+            Ok(Box::new(BamReader::new(input)?))
+        }
+    } else if magic.starts_with(b"CRAM") {
+        if v == "-" {
+            return Err(Error::Invalid(
+                "Cram from stdin is not supported.".to_string(),
+            ));
+        }
+        Ok(Box::new(CramReader::new(v, reference, threads)?))
+    } else {
+        Ok(Box::new(SamReader::new(input)))
+    }
+}
@@ -0,0 +1,332 @@
+use crate::error::{Error, Result};
+
+/// Orientation of a pair, as classified by Picard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PairOrientation {
+    /// Forward read before reverse read, the usual library.
+    FR,
+    /// Reverse read before forward read.
+    RF,
+    /// Both reads on the same strand.
+    Tandem,
+}
+
+impl PairOrientation {
+    /// All orientations, in index order.
+    pub const ALL: [PairOrientation; 3] = [Self::FR, Self::RF, Self::Tandem];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::FR => "FR",
+            Self::RF => "RF",
+            Self::Tandem => "TANDEM",
+        }
+    }
+}
+
+/// Samtools flag names and bits.
+const FLAG_NAMES: [(&str, u16); 12] = [
+    ("PAIRED", 0x1),
+    ("PROPER_PAIR", 0x2),
+    ("UNMAP", 0x4),
+    ("MUNMAP", 0x8),
+    ("REVERSE", 0x10),
+    ("MREVERSE", 0x20),
+    ("READ1", 0x40),
+    ("READ2", 0x80),
+    ("SECONDARY", 0x100),
+    ("QCFAIL", 0x200),
+    ("DUP", 0x400),
+    ("SUPPLEMENTARY", 0x800),
+];
+
+/// Parse flags like samtools, a number (decimal, `0x` hex or `0` octal) or comma separated
+/// names, e.g. `0x900` or `SECONDARY,SUPPLEMENTARY`.
+pub fn parse_flags(v: &str) -> Result<u16> {
+    let err = || Error::Invalid(format!("Wrong flags: {}", v));
+    let v = v.trim();
+    if v.starts_with(|c: char| c.is_ascii_digit()) {
+        return if let Some(hex) = v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+            u16::from_str_radix(hex, 16)
+        } else if v.len() > 1 && v.starts_with('0') {
+            u16::from_str_radix(&v[1..], 8)
+        } else {
+            v.parse()
+        }
+        .map_err(|_| err());
+    }
+    v.split(',').try_fold(0, |flags, name| {
+        FLAG_NAMES
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name.trim()))
+            .map(|(_, bit)| flags | bit)
+            .ok_or_else(err)
+    })
+}
+
+/// Cigar operations as length and op char from sam text, e.g. `5S90M`.
+pub(crate) fn text_cigar_ops(cigar: &[u8]) -> impl Iterator<Item = (i32, u8)> + '_ {
+    let mut len = 0i32;
+    cigar.iter().filter_map(move |c| match c {
+        b'0'..=b'9' => {
+            len = len.saturating_mul(10).saturating_add((c - b'0') as i32);
+            None
+        }
+        _ => Some((std::mem::take(&mut len), *c)),
+    })
+}
+
+/// Reference span `n` so far extended by cigar op `v`, saturating so a corrupt cigar does not
+/// overflow.
+pub(crate) fn span_of(n: i32, v: &(i32, u8)) -> i32 {
+    match v.1 {
+        b'M' | b'D' | b'N' | b'=' | b'X' => n.saturating_add(v.0),
+        _ => n,
+    }
+}
+
+/// Soft clipped bases at left and right end of alignment, hard clips outside are skipped.
+pub(crate) fn soft_clips<I: Iterator<Item = (i32, u8)>>(ops: I) -> (i32, i32) {
+    let mut left = None;
+    let mut right = 0;
+    for (len, op) in ops.filter(|v| v.1 != b'H') {
+        let clip = if op == b'S' { len } else { 0 };
+        left.get_or_insert(clip);
+        right = clip;
+    }
+    (left.unwrap_or(0), right)
+}
+
+/// Optional record fields a reader fills in, the rest it may skip parsing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fields(u8);
+
+impl Fields {
+    /// Only the fixed fields, positions, flag, mapq and lengths.
+    pub const NONE: Fields = Fields(0);
+    /// G and C count of sequence.
+    pub const GC: Fields = Fields(1);
+    /// Query name.
+    pub const NAME: Fields = Fields(2);
+    /// Soft clips of read and, from `MC:Z:` tag, of mate.
+    pub const CLIPS: Fields = Fields(4);
+    /// Value of `RG:Z:` tag.
+    pub const READ_GROUP: Fields = Fields(8);
+    pub const ALL: Fields = Fields(0xf);
+
+    pub fn contains(self, other: Fields) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl std::ops::BitOr for Fields {
+    type Output = Fields;
+
+    fn bitor(self, other: Fields) -> Fields {
+        Fields(self.0 | other.0)
+    }
+}
+
+/// Read is reverse complemented.
+const REVERSE: u16 = 0x10;
+/// Mate is reverse complemented.
+const MATE_REVERSE: u16 = 0x20;
+
+/// Compact read record.
+#[derive(Default)]
+pub struct Record {
+    ref_id: i32,
+    // 0-based leftmost position.
+    pos: i32,
+    // 0-based exclusive end position on reference.
+    end: i32,
+    mate_ref_id: i32,
+    // 0-based leftmost position of mate.
+    mate_pos: i32,
+    tlen: i32,
+    flag: u16,
+    mapq: u8,
+    // Number of CIGAR operations.
+    cigar_len: u16,
+    // Length of SEQ, 0 when not stored, and its G, C and S bases.
+    seq_len: u32,
+    gc_count: u32,
+    // Query name.
+    name: Vec<u8>,
+    // Value of `RG:Z:` tag, empty when absent.
+    read_group: Vec<u8>,
+    // Soft clips at left and right end, of this read and of mate from `MC:Z:` tag.
+    clips: (i32, i32),
+    mate_clips: (i32, i32),
+}
+
+impl Record {
+    pub fn flag(&self) -> &u16 {
+        &self.flag
+    }
+
+    pub fn set_flag(&mut self, v: u16) {
+        self.flag = v
+    }
+
+    pub fn mapq(&self) -> &u8 {
+        &self.mapq
+    }
+
+    pub fn set_mapq(&mut self, v: u8) {
+        self.mapq = v
+    }
+
+    pub fn tlen(&self) -> &i32 {
+        &self.tlen
+    }
+
+    pub fn set_tlen(&mut self, v: i32) {
+        self.tlen = v
+    }
+
+    pub fn ref_id(&self) -> &i32 {
+        &self.ref_id
+    }
+
+    pub fn set_ref_id(&mut self, v: i32) {
+        self.ref_id = v
+    }
+
+    pub fn pos(&self) -> &i32 {
+        &self.pos
+    }
+
+    pub fn set_pos(&mut self, v: i32) {
+        self.pos = v
+    }
+
+    pub fn end(&self) -> &i32 {
+        &self.end
+    }
+
+    pub fn set_end(&mut self, v: i32) {
+        self.end = v
+    }
+
+    pub fn mate_ref_id(&self) -> &i32 {
+        &self.mate_ref_id
+    }
+
+    pub fn set_mate_ref_id(&mut self, v: i32) {
+        self.mate_ref_id = v
+    }
+
+    pub fn cigar_len(&self) -> &u16 {
+        &self.cigar_len
+    }
+
+    pub fn set_cigar_len(&mut self, v: u16) {
+        self.cigar_len = v
+    }
+
+    pub fn seq_len(&self) -> &u32 {
+        &self.seq_len
+    }
+
+    pub fn set_seq_len(&mut self, v: u32) {
+        self.seq_len = v
+    }
+
+    pub fn gc_count(&self) -> &u32 {
+        &self.gc_count
+    }
+
+    pub fn set_gc_count(&mut self, v: u32) {
+        self.gc_count = v
+    }
+
+    /// GC fraction of SEQ, `None` when not stored.
+    pub fn gc(&self) -> Option<f64> {
+        (self.seq_len > 0).then(|| self.gc_count as f64 / self.seq_len as f64)
+    }
+
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    pub fn set_name(&mut self, v: &[u8]) {
+        self.name.clear();
+        self.name.extend_from_slice(v);
+    }
+
+    pub fn read_group(&self) -> &[u8] {
+        &self.read_group
+    }
+
+    pub fn set_read_group(&mut self, v: &[u8]) {
+        self.read_group.clear();
+        self.read_group.extend_from_slice(v);
+    }
+
+    pub fn mate_pos(&self) -> &i32 {
+        &self.mate_pos
+    }
+
+    pub fn set_mate_pos(&mut self, v: i32) {
+        self.mate_pos = v
+    }
+
+    /// Soft clipped bases at left and right end.
+    pub fn clips(&self) -> &(i32, i32) {
+        &self.clips
+    }
+
+    pub fn set_clips(&mut self, v: (i32, i32)) {
+        self.clips = v
+    }
+
+    /// Soft clipped bases at left and right end of mate, zero without `MC` tag.
+    pub fn mate_clips(&self) -> &(i32, i32) {
+        &self.mate_clips
+    }
+
+    pub fn set_mate_clips(&mut self, v: (i32, i32)) {
+        self.mate_clips = v
+    }
+
+    /// Template length between unclipped outer ends of the pair, sign kept.
+    ///
+    /// The leftmost read extends by its left clip and the rightmost by its right clip, mate
+    /// clips are only known with the `MC` tag.
+    pub fn clip_adjusted_tlen(&self) -> i32 {
+        match self.tlen {
+            v if v > 0 => v
+                .saturating_add(self.clips.0)
+                .saturating_add(self.mate_clips.1),
+            v if v < 0 => v
+                .saturating_sub(self.clips.1)
+                .saturating_sub(self.mate_clips.0),
+            _ => 0,
+        }
+    }
+
+    /// Pair orientation from strands and 5' ends, meaningful for pairs on one reference.
+    pub fn orientation(&self) -> PairOrientation {
+        let reverse = self.flag & REVERSE != 0;
+        if reverse == (self.flag & MATE_REVERSE != 0) {
+            return PairOrientation::Tandem;
+        }
+        let (positive, negative) = if reverse {
+            (self.mate_pos, self.end - 1)
+        } else {
+            (self.pos, self.pos.saturating_add(self.tlen))
+        };
+        if positive < negative {
+            PairOrientation::FR
+        } else {
+            PairOrientation::RF
+        }
+    }
+}
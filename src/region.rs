@@ -0,0 +1,300 @@
+use std::io::{BufReader, Read, Seek};
+use std::str::FromStr;
+
+use crate::bai::{merge_chunks, BaiIndex};
+use crate::bam::BamReader;
+use crate::bgzf::BgzfReader;
+use crate::error::{open_file, Error, Result};
+use crate::header::Header;
+use crate::http::{is_url, HttpReader};
+use crate::reader::RecordReader;
+use crate::record::{Fields, Record};
+
+/// Genomic region, 0-based and half open.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub name: String,
+    pub start: i32,
+    pub end: i32,
+    // Text as given, a whole reference name when the input has one with colons.
+    text: String,
+}
+
+impl FromStr for Region {
+    type Err = Error;
+
+    /// Parse `chr`, `chr:start` or `chr:start-end`, 1-based and inclusive. Text after the last
+    /// colon which is no range of digits is part of the name.
+    fn from_str(v: &str) -> Result<Self> {
+        let whole = Self {
+            name: v.to_string(),
+            start: 0,
+            end: i32::MAX,
+            text: v.to_string(),
+        };
+        let (name, range) = match v.rfind(':') {
+            Some(i) => (&v[..i], v[i + 1..].replace(',', "")),
+            None => return Ok(whole),
+        };
+        if !range.is_empty() && !range.bytes().all(|c| c.is_ascii_digit() || c == b'-') {
+            return Ok(whole);
+        }
+        let err = || Error::Invalid(format!("Wrong region: {}", v));
+        let mut iter = range.splitn(2, '-');
+        let start: i32 = iter.next().and_then(|v| v.parse().ok()).ok_or_else(err)?;
+        let end: i32 = match iter.next() {
+            Some(v) => v.parse().map_err(|_| err())?,
+            None => i32::MAX,
+        };
+        if start < 1 || end < start {
+            return Err(err());
+        }
+        Ok(Self {
+            name: name.to_string(),
+            start: start - 1,
+            end,
+            text: v.to_string(),
+        })
+    }
+}
+
+impl Region {
+    /// Region on `references` of the input, the text is a whole reference name, e.g.
+    /// `HLA-A*01:01:01:01`, when it is one and the part before its last colon is not.
+    pub fn resolve(&self, references: &[(String, u32)]) -> Region {
+        let known = |v: &str| references.iter().any(|(name, _)| name == v);
+        if self.name != self.text && !known(&self.name) && known(&self.text) {
+            Region {
+                name: self.text.clone(),
+                start: 0,
+                end: i32::MAX,
+                text: self.text.clone(),
+            }
+        } else {
+            self.clone()
+        }
+    }
+}
+
+/// Whether reference `name` matches glob `pattern`, `*` matches any run of characters and `?`
+/// any one character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (p, v) = (pattern.as_bytes(), name.as_bytes());
+    let (mut pi, mut vi) = (0, 0);
+    // Position after the last `*` and the name position it is matched up to.
+    let mut star = None;
+    while vi < v.len() {
+        match p.get(pi) {
+            Some(b'*') => {
+                pi += 1;
+                star = Some((pi, vi));
+            }
+            Some(c) if *c == b'?' || *c == v[vi] => {
+                pi += 1;
+                vi += 1;
+            }
+            _ => match star {
+                // Let the last `*` take one more character.
+                Some((sp, sv)) => {
+                    pi = sp;
+                    vi = sv + 1;
+                    star = Some((sp, sv + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    p[pi..].iter().all(|c| *c == b'*')
+}
+
+/// Genome build of primary contig presets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenomeBuild {
+    /// Human, hg19, hg38, GRCh37 or GRCh38.
+    Human,
+    /// Mouse, mm10, mm39, GRCm38 or GRCm39.
+    Mouse,
+}
+
+impl GenomeBuild {
+    /// Autosome names, and sex chromosomes unless `autosomes_only`, both `chr1` and `1` styles.
+    pub fn primary_contigs(&self, autosomes_only: bool) -> Vec<String> {
+        let autosomes = match self {
+            Self::Human => 22,
+            Self::Mouse => 19,
+        };
+        let mut names: Vec<String> = (1..=autosomes).map(|v| v.to_string()).collect();
+        if !autosomes_only {
+            names.extend(["X".to_string(), "Y".to_string()]);
+        }
+        let prefixed: Vec<String> = names.iter().map(|v| format!("chr{}", v)).collect();
+        names.extend(prefixed);
+        names
+    }
+}
+
+impl FromStr for GenomeBuild {
+    type Err = Error;
+
+    fn from_str(v: &str) -> Result<Self> {
+        match v.to_ascii_lowercase().as_str() {
+            "hg19" | "hg38" | "grch37" | "grch38" | "b37" => Ok(Self::Human),
+            "mm10" | "mm39" | "grcm38" | "grcm39" => Ok(Self::Mouse),
+            _ => Err(Error::Invalid(format!(
+                "Wrong genome build: {}, use hg19, hg38, GRCh37, GRCh38, mm10 or mm39.",
+                v
+            ))),
+        }
+    }
+}
+
+/// Seekable input, a local file or a url.
+trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Bam reader restricted to regions, seeking with the bai index.
+pub struct RegionReader {
+    bam: BamReader<BgzfReader<BufReader<Box<dyn ReadSeek>>>>,
+    // Regions as reference id, start and end.
+    intervals: Vec<(i32, i32, i32)>,
+    // Merged chunks to read.
+    chunks: Vec<(u64, u64)>,
+    // Current chunk.
+    ci: usize,
+    // Current chunk is seeked to.
+    seeked: bool,
+}
+
+impl RegionReader {
+    pub fn new(path: &str, regions: &[Region]) -> Result<Self> {
+        let input: Box<dyn ReadSeek> = if is_url(path) {
+            Box::new(HttpReader::new(path)?)
+        } else {
+            Box::new(open_file(path)?)
+        };
+        let bam = BamReader::new(BufReader::new(input))?;
+        let index = BaiIndex::for_bam(path)?;
+        let mut intervals = Vec::with_capacity(regions.len());
+        let mut chunks = Vec::new();
+        for region in regions {
+            let region = region.resolve(bam.references());
+            let rid = bam
+                .references()
+                .iter()
+                .position(|(name, _)| name == &region.name)
+                .ok_or_else(|| Error::Invalid(format!("No reference {} in bam.", region.name)))?;
+            chunks.extend(index.query(rid, region.start, region.end));
+            intervals.push((rid as i32, region.start, region.end));
+        }
+        Ok(Self {
+            bam,
+            intervals,
+            chunks: merge_chunks(chunks),
+            ci: 0,
+            seeked: false,
+        })
+    }
+}
+
+impl RecordReader for RegionReader {
+    /// Read a record overlapping any region.
+    fn read_into(&mut self, record: &mut Record) -> Result<bool> {
+        loop {
+            let (beg, end) = match self.chunks.get(self.ci) {
+                Some(v) => *v,
+                None => return Ok(false),
+            };
+            if !self.seeked {
+                self.bam.get_mut().seek(beg)?;
+                self.seeked = true;
+            }
+            if self.bam.get_ref().virtual_offset() >= end || !self.bam.read_into(record)? {
+                self.ci += 1;
+                self.seeked = false;
+                continue;
+            }
+            if self.intervals.iter().any(|(rid, start, end)| {
+                record.ref_id() == rid && record.pos() < end && record.end() > start
+            }) {
+                return Ok(true);
+            }
+        }
+    }
+
+    fn reference_name(&self, id: i32) -> Option<&str> {
+        self.bam.reference_name(id)
+    }
+
+    fn read_groups(&self) -> &[String] {
+        self.bam.read_groups()
+    }
+
+    fn header(&self) -> &Header {
+        self.bam.header()
+    }
+
+    fn set_fields(&mut self, fields: Fields) {
+        self.bam.set_fields(fields)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(v: &str) -> (String, i32, i32) {
+        let v: Region = v.parse().unwrap();
+        (v.name, v.start, v.end)
+    }
+
+    #[test]
+    fn parse_region() {
+        assert_eq!(region("chr1"), ("chr1".to_string(), 0, i32::MAX));
+        assert_eq!(region("chr1:100"), ("chr1".to_string(), 99, i32::MAX));
+        assert_eq!(region("chr1:100-200"), ("chr1".to_string(), 99, 200));
+        assert_eq!(region("chr1:1,000-2,000"), ("chr1".to_string(), 999, 2000));
+        assert_eq!(region("chr1:5-5"), ("chr1".to_string(), 4, 5));
+        // Only the last colon separates the range, e.g. of HLA contigs.
+        assert_eq!(
+            region("HLA-A*01:01:01:01:1-10"),
+            ("HLA-A*01:01:01:01".to_string(), 0, 10)
+        );
+        // No range after the last colon, the name has colons.
+        assert_eq!(region("chrUn:abc"), ("chrUn:abc".to_string(), 0, i32::MAX));
+        assert_eq!(region("chr1:a-10"), ("chr1:a-10".to_string(), 0, i32::MAX));
+    }
+
+    #[test]
+    fn resolve_names_with_colons() {
+        let references = [
+            ("chr1".to_string(), 1000),
+            ("HLA-A*01:01:01:01".to_string(), 3503),
+        ];
+        let resolve = |v: &str| {
+            let v = v.parse::<Region>().unwrap().resolve(&references);
+            (v.name, v.start, v.end)
+        };
+        assert_eq!(
+            resolve("HLA-A*01:01:01:01"),
+            ("HLA-A*01:01:01:01".to_string(), 0, i32::MAX)
+        );
+        assert_eq!(
+            resolve("HLA-A*01:01:01:01:100-200"),
+            ("HLA-A*01:01:01:01".to_string(), 99, 200)
+        );
+        assert_eq!(resolve("chr1:100-200"), ("chr1".to_string(), 99, 200));
+        // Neither is a reference, the range stands and the name is not found later.
+        assert_eq!(
+            resolve("HLA-B*07:02:01:01"),
+            ("HLA-B*07:02:01".to_string(), 0, i32::MAX)
+        );
+    }
+
+    #[test]
+    fn bad_region() {
+        for v in ["chr1:", "chr1:0-10", "chr1:20-10", "chr1:1-", "chr1:-5"] {
+            assert!(v.parse::<Region>().is_err(), "{}", v);
+        }
+    }
+}
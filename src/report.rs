@@ -0,0 +1,373 @@
+use std::io::{BufWriter, Write};
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::error::{create_file, Error, Result};
+use crate::hist::bins_median;
+use crate::plot::{draw_err, font_err, pick_font, PicFormat, COLORS};
+use crate::summary::{round2, Summary};
+
+/// Samples with median further than this many MADs from the cohort median are outliers by default.
+pub const DEFAULT_OUTLIER_MADS: f64 = 3.0;
+
+/// Boxes of outlier samples are red, others blue, of `COLORS`.
+const OUTLIER_COLOR: (u8, u8, u8) = COLORS[0];
+const BOX_COLOR: (u8, u8, u8) = COLORS[1];
+
+/// Insert size box of one sample.
+struct SampleBox {
+    name: String,
+    median: f64,
+    q1: usize,
+    q3: usize,
+    // Whiskers at 1.5 IQR beyond the box, within the smallest and largest insert size.
+    low: usize,
+    high: usize,
+    outlier: bool,
+}
+
+/// Insert size boxes of a cohort, with samples whose median is unusual in the cohort flagged.
+pub struct Cohort {
+    samples: Vec<SampleBox>,
+    /// Median of sample medians.
+    pub median: f64,
+    /// Median absolute deviation of sample medians.
+    pub mad: f64,
+    /// Outliers are more than this many MADs from the cohort median.
+    pub mads: f64,
+}
+
+impl Cohort {
+    pub fn new(sums: &[(String, Summary)], mads: f64) -> Self {
+        let mut medians: Vec<f64> = sums.iter().map(|(_, v)| v.median).collect();
+        medians.sort_by(f64::total_cmp);
+        let median = bins_median(medians.iter().map(|v| (*v, 1)), medians.len() as u64);
+        let mut deviations: Vec<f64> = medians.iter().map(|v| (v - median).abs()).collect();
+        deviations.sort_by(f64::total_cmp);
+        let mad = bins_median(deviations.iter().map(|v| (*v, 1)), deviations.len() as u64);
+        let samples = sums
+            .iter()
+            .map(|(name, sum)| {
+                let whisker = sum.iqr * 3 / 2;
+                SampleBox {
+                    name: name.clone(),
+                    median: sum.median,
+                    q1: sum.q1,
+                    q3: sum.q3,
+                    low: usize::max(sum.q1.saturating_sub(whisker), sum.min),
+                    high: usize::min(sum.q3 + whisker, sum.max),
+                    outlier: (sum.median - median).abs() > mads * mad,
+                }
+            })
+            .collect();
+        Self {
+            samples,
+            median,
+            mad,
+            mads,
+        }
+    }
+
+    /// Names of outlier samples, in input order.
+    pub fn outliers(&self) -> impl Iterator<Item = &str> {
+        self.samples
+            .iter()
+            .filter(|v| v.outlier)
+            .map(|v| v.name.as_str())
+    }
+
+    /// Range of sample medians not flagged.
+    fn limits(&self) -> (f64, f64) {
+        (
+            self.median - self.mads * self.mad,
+            self.median + self.mads * self.mad,
+        )
+    }
+
+    /// Write the report at `path`, a png or svg boxplot or an interactive html page.
+    pub fn write(&self, path: &str, format: &PicFormat) -> Result<()> {
+        let size = (u32::max(800, self.samples.len() as u32 * 24 + 100), 500);
+        match format {
+            PicFormat::Png => {
+                let font = pick_font(None);
+                let (w, h) = size;
+                let mut buf = vec![0u8; w as usize * h as usize * 3];
+                {
+                    let root = BitMapBackend::with_buffer(&mut buf, size).into_drawing_area();
+                    self.draw(&root, &font).map_err(|e| font_err(&font, e))?;
+                }
+                image::save_buffer_with_format(
+                    path,
+                    &buf,
+                    w,
+                    h,
+                    image::ColorType::Rgb8,
+                    image::ImageFormat::Png,
+                )
+                .map_err(|e| Error::Plot(e.to_string()).in_file(path))
+            }
+            PicFormat::Svg => {
+                let font = "sans-serif";
+                let root = SVGBackend::new(path, size).into_drawing_area();
+                self.draw(&root, font).map_err(|e| font_err(font, e))
+            }
+            PicFormat::Html => self.write_html(BufWriter::new(create_file(path)?)),
+            PicFormat::Pdf => Err(Error::Invalid(
+                "Cohort report supports png, svg and html.".to_string(),
+            )),
+        }
+    }
+
+    fn draw<DB: DrawingBackend>(&self, root: &DrawingArea<DB, Shift>, font: &str) -> Result<()> {
+        root.fill(&WHITE).map_err(draw_err)?;
+        let (lower, upper) = self.limits();
+        let y_low = self
+            .samples
+            .iter()
+            .map(|v| v.low as f64)
+            .fold(lower, f64::min);
+        let y_high = self
+            .samples
+            .iter()
+            .map(|v| v.high as f64)
+            .fold(upper, f64::max);
+        let pad = f64::max(y_high - y_low, 1.0) * 0.05;
+        let (y_low, y_high) = (f64::max(y_low - pad, 0.0), y_high + pad);
+        let n = self.samples.len();
+        let x_max = n as f64 - 0.5;
+        let name_of = |v: &f64| {
+            let i = v.round();
+            match self.samples.get(i as usize) {
+                Some(s) if (v - i).abs() < 1e-6 && i >= 0.0 => s.name.clone(),
+                _ => String::new(),
+            }
+        };
+        let mut chart = ChartBuilder::on(root)
+            .caption("Insert size by sample", (font, 20.0))
+            .x_label_area_size(40)
+            .y_label_area_size(50)
+            .margin(10)
+            .build_cartesian_2d(-0.5..x_max, y_low..y_high)
+            .map_err(draw_err)?;
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .x_labels(n)
+            .x_label_formatter(&name_of)
+            .y_desc("Insert size")
+            .label_style((font, 12.0))
+            .axis_desc_style((font, 16.0))
+            .draw()
+            .map_err(draw_err)?;
+
+        // Cohort median and outlier limits across all boxes.
+        let grey = RGBColor(127, 127, 127);
+        for (y, style) in [
+            (self.median, grey.stroke_width(2)),
+            (lower, grey.stroke_width(1)),
+            (upper, grey.stroke_width(1)),
+        ] {
+            chart
+                .draw_series(DashedLineSeries::new(
+                    vec![(-0.5, y), (x_max, y)],
+                    6,
+                    4,
+                    style,
+                ))
+                .map_err(draw_err)?;
+        }
+        for (i, s) in self.samples.iter().enumerate() {
+            let (r, g, b) = if s.outlier { OUTLIER_COLOR } else { BOX_COLOR };
+            let color = RGBColor(r, g, b);
+            let x = i as f64;
+            let (q1, q3) = (s.q1 as f64, s.q3 as f64);
+            chart
+                .draw_series([
+                    PathElement::new(vec![(x, s.low as f64), (x, q1)], color),
+                    PathElement::new(vec![(x, q3), (x, s.high as f64)], color),
+                    PathElement::new(
+                        vec![(x - 0.3, s.median), (x + 0.3, s.median)],
+                        color.stroke_width(2),
+                    ),
+                ])
+                .map_err(draw_err)?;
+            chart
+                .draw_series([
+                    Rectangle::new([(x - 0.3, q1), (x + 0.3, q3)], color.mix(0.3).filled()),
+                    Rectangle::new([(x - 0.3, q1), (x + 0.3, q3)], color),
+                ])
+                .map_err(draw_err)?;
+            if s.outlier {
+                chart
+                    .draw_series(std::iter::once(Text::new(
+                        s.name.clone(),
+                        (x + 0.35, s.median),
+                        (font, 12.0).into_font().color(&color),
+                    )))
+                    .map_err(draw_err)?;
+            }
+        }
+        root.present().map_err(draw_err)
+    }
+
+    /// Write a self-contained html page of the boxplot and a table of samples.
+    fn write_html<W: Write>(&self, mut out: W) -> Result<()> {
+        let data = serde_json::to_string(self)?
+            // Keep names like `</script>` from closing the script block.
+            .replace("</", "<\\/");
+        out.write_all(TEMPLATE.replace("__COHORT__", &data).as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Serialize for SampleBox {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("SampleBox", 7)?;
+        state.serialize_field("name", &self.name)?;
+        state.serialize_field("median", &self.median)?;
+        state.serialize_field("q1", &self.q1)?;
+        state.serialize_field("q3", &self.q3)?;
+        state.serialize_field("low", &self.low)?;
+        state.serialize_field("high", &self.high)?;
+        state.serialize_field("outlier", &self.outlier)?;
+        state.end()
+    }
+}
+
+impl Serialize for Cohort {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Cohort", 4)?;
+        state.serialize_field("median", &self.median)?;
+        state.serialize_field("mad", &round2(self.mad))?;
+        state.serialize_field("mads", &self.mads)?;
+        state.serialize_field("samples", &self.samples)?;
+        state.end()
+    }
+}
+
+/// Page template, `__COHORT__` is replaced by the cohort json.
+const TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Insert size cohort report</title>
+<style>
+body { font-family: sans-serif; margin: 20px; }
+#chart { position: relative; display: inline-block; }
+canvas { border: 1px solid #ddd; }
+#tip { position: absolute; display: none; pointer-events: none; white-space: nowrap;
+  background: rgba(255, 255, 255, 0.9); border: 1px solid #999; padding: 4px 6px; font-size: 12px; }
+table { border-collapse: collapse; margin-top: 16px; font-size: 13px; }
+th, td { border: 1px solid #ddd; padding: 3px 8px; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+tr.outlier td { color: #FF0000; font-weight: bold; }
+p { color: #666; font-size: 12px; }
+</style>
+</head>
+<body>
+<h3>Insert size by sample</h3>
+<div id="chart"><canvas id="canvas" width="900" height="500"></canvas><div id="tip"></div></div>
+<p id="note"></p>
+<table id="table"><tr><th>Sample</th><th>Median</th><th>Q1</th><th>Q3</th><th>MADs from cohort median</th></tr></table>
+<script>
+const COHORT = __COHORT__;
+const L = 70, R = 20, T = 20, B = 30;
+const canvas = document.getElementById("canvas");
+const samples = COHORT.samples;
+canvas.width = Math.max(900, samples.length * 24 + L + R);
+const ctx = canvas.getContext("2d");
+const tip = document.getElementById("tip");
+const W = canvas.width, H = canvas.height;
+const lower = COHORT.median - COHORT.mads * COHORT.mad, upper = COHORT.median + COHORT.mads * COHORT.mad;
+let ymin = Math.min(lower, ...samples.map(s => s.low)), ymax = Math.max(upper, ...samples.map(s => s.high));
+const pad = Math.max(ymax - ymin, 1) * 0.05;
+ymin = Math.max(0, ymin - pad);
+ymax += pad;
+const step = (W - L - R) / samples.length;
+const px = i => L + (i + 0.5) * step;
+const py = y => H - B - (y - ymin) / (ymax - ymin) * (H - T - B);
+const esc = t => String(t).replace(/[&<>"]/g, c => ({ "&": "&amp;", "<": "&lt;", ">": "&gt;", '"': "&quot;" })[c]);
+const deviation = s => COHORT.mad > 0 ? (s.median - COHORT.median) / COHORT.mad : 0;
+
+function line(x0, y0, x1, y1) {
+  ctx.beginPath();
+  ctx.moveTo(x0, y0);
+  ctx.lineTo(x1, y1);
+  ctx.stroke();
+}
+
+ctx.strokeStyle = "#000";
+ctx.fillStyle = "#000";
+ctx.font = "12px sans-serif";
+line(L, T, L, H - B);
+line(L, H - B, W - R, H - B);
+ctx.textAlign = "right";
+for (let i = 0; i <= 5; i++) {
+  const y = ymin + (ymax - ymin) * i / 5;
+  ctx.fillText(Math.round(y), L - 6, py(y) + 4);
+}
+ctx.save();
+ctx.translate(16, (T + H - B) / 2);
+ctx.rotate(-Math.PI / 2);
+ctx.textAlign = "center";
+ctx.fillText("Insert size", 0, 0);
+ctx.restore();
+ctx.strokeStyle = "#7F7F7F";
+ctx.setLineDash([6, 4]);
+ctx.lineWidth = 2;
+line(L, py(COHORT.median), W - R, py(COHORT.median));
+ctx.lineWidth = 1;
+line(L, py(lower), W - R, py(lower));
+line(L, py(upper), W - R, py(upper));
+ctx.setLineDash([]);
+const w = Math.min(20, step * 0.6);
+samples.forEach((s, i) => {
+  const color = s.outlier ? "#FF0000" : "#1F77B4";
+  const x = px(i);
+  ctx.strokeStyle = color;
+  line(x, py(s.low), x, py(s.q1));
+  line(x, py(s.q3), x, py(s.high));
+  ctx.fillStyle = color + "4D";
+  ctx.fillRect(x - w / 2, py(s.q3), w, py(s.q1) - py(s.q3));
+  ctx.strokeRect(x - w / 2, py(s.q3), w, py(s.q1) - py(s.q3));
+  ctx.lineWidth = 2;
+  line(x - w / 2, py(s.median), x + w / 2, py(s.median));
+  ctx.lineWidth = 1;
+});
+
+document.getElementById("note").textContent =
+  `Cohort median ${COHORT.median}, MAD ${COHORT.mad}, samples with median more than ${COHORT.mads} MADs away are red.`;
+const table = document.getElementById("table");
+samples.forEach(s => {
+  const row = table.insertRow();
+  if (s.outlier) row.className = "outlier";
+  [s.name, s.median, s.q1, s.q3, deviation(s).toFixed(2)].forEach(v => row.insertCell().textContent = v);
+});
+
+canvas.onmousemove = e => {
+  const i = Math.floor((e.offsetX - L) / step);
+  const s = samples[i];
+  if (!s) {
+    tip.style.display = "none";
+    return;
+  }
+  tip.innerHTML = `<b>${esc(s.name)}</b><br>median ${s.median}<br>Q1 ${s.q1}, Q3 ${s.q3}<br>whiskers ${s.low} to ${s.high}`;
+  tip.style.display = "block";
+  tip.style.left = (e.offsetX + 12) + "px";
+  tip.style.top = (e.offsetY + 12) + "px";
+};
+canvas.onmouseleave = () => {
+  tip.style.display = "none";
+};
+</script>
+</body>
+</html>
+"##;
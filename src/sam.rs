@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::io::BufRead;
+
+use crate::error::{Error, Result};
+use crate::header::Header;
+use crate::reader::RecordReader;
+use crate::record::{soft_clips, span_of, text_cigar_ops, Record};
+
+/// Sam reader, header lines are consumed along with records.
+pub struct SamReader<T: BufRead> {
+    reader: T,
+    line: String,
+    // Reference name to id, from `@SQ` lines or in order of appearance for headerless sam.
+    refs: HashMap<String, i32>,
+    // Reference names in order of id.
+    names: Vec<String>,
+    // Read group ids from `@RG` lines.
+    read_groups: Vec<String>,
+    // Header lines seen so far.
+    header: Header,
+}
+
+impl<T: BufRead> SamReader<T> {
+    pub fn new(reader: T) -> Self {
+        Self {
+            reader,
+            line: String::new(),
+            refs: HashMap::new(),
+            names: Vec::new(),
+            read_groups: Vec::new(),
+            header: Header::default(),
+        }
+    }
+
+    /// Id of reference name, `*` is unmapped.
+    fn ref_id(&mut self, name: &str) -> i32 {
+        if name == "*" {
+            return -1;
+        }
+        let next = self.refs.len() as i32;
+        let names = &mut self.names;
+        *self.refs.entry(name.to_string()).or_insert_with(|| {
+            names.push(name.to_string());
+            next
+        })
+    }
+}
+
+impl<T: BufRead> RecordReader for SamReader<T> {
+    /// Read a record (one line of sam), header lines are consumed on the way.
+    fn read_into(&mut self, record: &mut Record) -> Result<bool> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            let line = self.line.trim_end_matches(&['\n', '\r'][..]);
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('@') {
+                self.header.push_line(line);
+            }
+            if line.starts_with("@SQ") {
+                if let Some(name) = line.split('\t').find_map(|v| v.strip_prefix("SN:")) {
+                    let name = name.to_string();
+                    self.ref_id(&name);
+                }
+                continue;
+            }
+            if line.starts_with("@RG") {
+                if let Some(id) = line.split('\t').find_map(|v| v.strip_prefix("ID:")) {
+                    self.read_groups.push(id.to_string());
+                }
+                continue;
+            }
+            if line.starts_with('@') {
+                continue;
+            }
+            break;
+        }
+        let line = std::mem::take(&mut self.line);
+        let fields: Vec<&str> = line
+            .trim_end_matches(&['\n', '\r'][..])
+            .split('\t')
+            .collect();
+        if fields.len() < 11 {
+            return Err(Error::Malformed(format!(
+                "Malformed sam line: {}",
+                line.trim_end()
+            )));
+        }
+        let malformed = |v: &str| Error::Malformed(format!("Malformed sam field: {}", v));
+        // Query name.
+        record.set_name(fields[0].as_bytes());
+        // Flag.
+        record.set_flag(fields[1].parse().map_err(|_| malformed(fields[1]))?);
+        // Ref id.
+        let ref_id = self.ref_id(fields[2]);
+        record.set_ref_id(ref_id);
+        // Position, 1-based in sam.
+        let pos = fields[3]
+            .parse::<i32>()
+            .map_err(|_| malformed(fields[3]))?
+            .saturating_sub(1);
+        record.set_pos(pos);
+        // Mapping quality.
+        record.set_mapq(fields[4].parse().map_err(|_| malformed(fields[4]))?);
+        // Reference span and soft clips from cigar.
+        let cigar = fields[5].as_bytes();
+        let span = text_cigar_ops(cigar).fold(0i32, |n, v| span_of(n, &v));
+        record.set_end(pos.saturating_add(i32::max(span, 1)));
+        record.set_clips(soft_clips(text_cigar_ops(cigar)));
+        record.set_cigar_len(match cigar {
+            b"*" => 0,
+            _ => text_cigar_ops(cigar).count() as u16,
+        });
+        // Mate ref id, `=` means the same reference.
+        record.set_mate_ref_id(match fields[6] {
+            "=" => ref_id,
+            v => self.ref_id(v),
+        });
+        // Mate position, 1-based in sam.
+        record.set_mate_pos(
+            fields[7]
+                .parse::<i32>()
+                .map_err(|_| malformed(fields[7]))?
+                .saturating_sub(1),
+        );
+        // Template length.
+        record.set_tlen(fields[8].parse().map_err(|_| malformed(fields[8]))?);
+        // Read length and GC, `*` when sequence is not stored.
+        let seq = match fields[9] {
+            "*" => "",
+            v => v,
+        };
+        record.set_seq_len(seq.len() as u32);
+        record.set_gc_count(
+            seq.bytes()
+                .filter(|v| matches!(v, b'G' | b'C' | b'S' | b'g' | b'c' | b's'))
+                .count() as u32,
+        );
+        // Read group from optional fields.
+        let rg = fields[11..].iter().find_map(|v| v.strip_prefix("RG:Z:"));
+        record.set_read_group(rg.unwrap_or("").as_bytes());
+        // Mate cigar, for mate soft clips.
+        let mc = fields[11..].iter().find_map(|v| v.strip_prefix("MC:Z:"));
+        record.set_mate_clips(soft_clips(text_cigar_ops(mc.unwrap_or("").as_bytes())));
+        self.line = line;
+        Ok(true)
+    }
+
+    fn reference_name(&self, id: i32) -> Option<&str> {
+        self.names.get(id as usize).map(String::as_str)
+    }
+
+    fn read_groups(&self) -> &[String] {
+        &self.read_groups
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+}
@@ -0,0 +1,31 @@
+/// Deterministic Bernoulli sampler, keeps each pair with a fixed probability.
+///
+/// Uses SplitMix64, so the same seed keeps the same pairs of the same input.
+pub struct Sampler {
+    // Keep threshold on the 53 bit uniform value.
+    threshold: u64,
+    state: u64,
+}
+
+impl Sampler {
+    /// Sampler keeping `fraction` (0 to 1) of pairs.
+    pub fn new(fraction: f64, seed: u64) -> Self {
+        Self {
+            threshold: (fraction.clamp(0.0, 1.0) * (1u64 << 53) as f64) as u64,
+            state: seed,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Whether to keep the next pair.
+    pub fn keep(&mut self) -> bool {
+        (self.next_u64() >> 11) < self.threshold
+    }
+}
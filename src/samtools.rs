@@ -0,0 +1,60 @@
+use std::io::{Result, Write};
+
+use crate::hist::Histograms;
+use crate::record::PairOrientation;
+
+/// Write the insert size section of `samtools stats` of samples, `IS` lines of insert size,
+/// pairs total, inward (FR), outward (RF) and other (TANDEM) pairs.
+///
+/// Lines run from insert size 0 to the largest counted within the upper bound. Several samples
+/// are written one after another, each after a `# Sample` comment line.
+pub fn write_stats<W: Write>(mut out: W, samples: &[(String, Histograms)]) -> Result<()> {
+    writeln!(
+        out,
+        "# This file was produced by {} {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    for (name, hists) in samples {
+        if samples.len() > 1 {
+            writeln!(out, "# Sample: {}", name)?;
+        }
+        writeln!(
+            out,
+            "# Insert sizes. Use `grep ^IS | cut -f 2-` to extract this part. The columns are: \
+             insert size, pairs total, inward oriented pairs, outward oriented pairs, other pairs"
+        )?;
+        let orientation = |o: PairOrientation| {
+            hists
+                .orientations
+                .iter()
+                .find(|(k, _)| *k == o)
+                .map(|(_, v)| v)
+        };
+        let (inward, outward) = (
+            orientation(PairOrientation::FR),
+            orientation(PairOrientation::RF),
+        );
+        let last = hists
+            .all
+            .bins()
+            .filter(|(_, v)| *v > 0)
+            .last()
+            .map_or(0, |v| v.0);
+        for k in 0..=last {
+            let total = hists.all.get(k);
+            let inward = inward.map_or(0, |v| v.get(k));
+            let outward = outward.map_or(0, |v| v.get(k));
+            writeln!(
+                out,
+                "IS\t{}\t{}\t{}\t{}\t{}",
+                k,
+                total,
+                inward,
+                outward,
+                total.saturating_sub(inward + outward)
+            )?;
+        }
+    }
+    Ok(())
+}
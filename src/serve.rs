@@ -0,0 +1,372 @@
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{self, BufRead, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result};
+
+/// Port of the http server by default.
+pub const DEFAULT_PORT: u16 = 8080;
+/// Time a client has for request line and headers, requests are served one at a time.
+pub const HEADER_TIMEOUT: Duration = Duration::from_secs(30);
+/// Slowest upload accepted in bytes per second, a body gets time by its length at this rate.
+pub const MIN_UPLOAD_RATE: u64 = 64 * 1024;
+/// Largest request body accepted by default, 4 GiB.
+pub const DEFAULT_MAX_UPLOAD: u64 = 4 << 30;
+/// Header lines of a request at most, more is refused.
+const MAX_HEADERS: usize = 100;
+/// Bytes of a request line or header line at most, longer is refused.
+const MAX_LINE: usize = 8192;
+
+/// Tcp stream failing reads past a deadline of the whole request, so a client trickling bytes
+/// can not hold the server.
+pub struct Deadline {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Deadline {
+    /// Reads of `stream` for `timeout` from now.
+    pub fn new(stream: TcpStream, timeout: Duration) -> Self {
+        Self {
+            stream,
+            deadline: Instant::now() + timeout,
+        }
+    }
+
+    /// Allow reads for `v` longer, e.g. of a body by its length.
+    pub fn extend(&mut self, v: Duration) {
+        self.deadline += v;
+    }
+}
+
+impl Read for Deadline {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Request took too long.",
+            ));
+        }
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+/// Time to read a body of `len` bytes at `MIN_UPLOAD_RATE`, with headroom of `HEADER_TIMEOUT`.
+pub fn body_timeout(len: u64) -> Duration {
+    Duration::from_secs(len / MIN_UPLOAD_RATE) + HEADER_TIMEOUT
+}
+
+/// Directory of temporary files only the user may enter, removed with its files on drop.
+pub struct SpoolDir {
+    path: PathBuf,
+}
+
+impl SpoolDir {
+    /// Create a new directory in the temporary directory of the system, an existing one,
+    /// e.g. planted by another user, is never reused.
+    pub fn create() -> Result<Self> {
+        let base = std::env::temp_dir();
+        let mut builder = DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |v| v.subsec_nanos());
+        for i in 0..100u32 {
+            let path = base.join(format!(
+                "insize-serve-{}-{:08x}",
+                std::process::id(),
+                seed.wrapping_add(i)
+            ));
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::from(e).in_file(&path.display().to_string())),
+            }
+        }
+        Err(Error::Invalid(
+            "No temporary directory could be created.".to_string(),
+        ))
+    }
+
+    /// Path of file `name` in the directory.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Create file `name` in the directory, readable by the user only, failing when it exists.
+    pub fn create_file(&self, name: &str) -> Result<File> {
+        let path = self.path(name);
+        let mut opts = OpenOptions::new();
+        opts.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+        opts.open(&path)
+            .map_err(|e| Error::from(e).in_file(&path.display().to_string()))
+    }
+
+    /// Remove file `name` of the directory, if any.
+    pub fn remove_file(&self, name: &str) {
+        let _ = std::fs::remove_file(self.path(name));
+    }
+}
+
+impl Drop for SpoolDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Read a line of at most `MAX_LINE` bytes into `line`, return bytes read.
+fn read_line<R: BufRead>(r: &mut R, line: &mut String) -> Result<usize> {
+    let n = r.by_ref().take(MAX_LINE as u64).read_line(line)?;
+    if n == MAX_LINE && !line.ends_with('\n') {
+        return Err(malformed("Request line too long."));
+    }
+    Ok(n)
+}
+
+/// Http request line and headers, the body is left in the stream.
+pub struct Request {
+    pub method: String,
+    /// Path without query.
+    pub path: String,
+    /// Percent decoded query parameters in order.
+    pub query: Vec<(String, String)>,
+    /// Length of the body following headers, 0 without.
+    pub content_length: u64,
+}
+
+impl Request {
+    /// Read request line and headers of an http/1.x request.
+    pub fn read<R: BufRead>(r: &mut R) -> Result<Self> {
+        let mut line = String::new();
+        read_line(r, &mut line)?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method.to_string(), target.to_string())
+            }
+            _ => return Err(malformed("Malformed request line.")),
+        };
+        let mut content_length = 0;
+        for _ in 0..MAX_HEADERS {
+            line.clear();
+            if read_line(r, &mut line)? == 0 {
+                return Err(malformed("Request ended in headers."));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+                return Ok(Self {
+                    method,
+                    path: percent_decode(path),
+                    query: query
+                        .split('&')
+                        .filter(|v| !v.is_empty())
+                        .map(|v| {
+                            let (k, v) = v.split_once('=').unwrap_or((v, ""));
+                            (percent_decode(k), percent_decode(v))
+                        })
+                        .collect(),
+                    content_length,
+                });
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| malformed("Wrong Content-Length."))?;
+                } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                    return Err(malformed("Chunked bodies are not supported."));
+                }
+            }
+        }
+        Err(malformed("Too many request headers."))
+    }
+
+    /// Value of query parameter `name`, the first one when repeated.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|v| v.0 == name)
+            .map(|v| v.1.as_str())
+    }
+}
+
+fn malformed(v: &str) -> Error {
+    Error::Malformed(v.to_string())
+}
+
+/// Decode `%XX` escapes and `+` as space, bad escapes are kept as is.
+fn percent_decode(v: &str) -> String {
+    let bytes = v.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => match v
+                .get(i + 1..i + 3)
+                .filter(|h| h.bytes().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                Some(b) => {
+                    out.push(b);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Write a whole response of `status` with `body`, the connection is closed after it.
+pub fn respond<W: Write>(
+    w: &mut W,
+    status: u16,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    };
+    write!(
+        w,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    )?;
+    w.write_all(body)?;
+    w.flush()
+}
+
+/// Standard base64 of `data` with padding, for binary pics in json.
+pub fn base64(data: &[u8]) -> String {
+    const CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |a, (i, v)| a | (*v as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    fn request(v: &str) -> Result<Request> {
+        Request::read(&mut Cursor::new(v.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn read_request() {
+        let mut r = Cursor::new(
+            b"POST /plot?name=a%20b&format=svg&x HTTP/1.1\r\nHost: h\r\nContent-Length: 3\r\n\r\nabc"
+                .to_vec(),
+        );
+        let req = Request::read(&mut r).unwrap();
+        assert_eq!((req.method.as_str(), req.path.as_str()), ("POST", "/plot"));
+        assert_eq!(req.param("name"), Some("a b"));
+        assert_eq!(req.param("format"), Some("svg"));
+        assert_eq!(req.param("x"), Some(""));
+        assert_eq!(req.content_length, 3);
+        // The body is left in the stream.
+        let mut body = String::new();
+        r.read_to_string(&mut body).unwrap();
+        assert_eq!(body, "abc");
+    }
+
+    #[test]
+    fn bad_requests() {
+        for v in [
+            "",
+            "GET /\r\n\r\n",
+            "GET / SPDY/3\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: h\r\n",
+            "POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n",
+            "POST / HTTP/1.1\r\nContent-Length: 1e3\r\n\r\n",
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+        ] {
+            assert!(request(v).is_err(), "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn oversized_lines() {
+        let long = "a".repeat(MAX_LINE);
+        assert!(request(&format!("GET /{} HTTP/1.1\r\n\r\n", long)).is_err());
+        assert!(request(&format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", long)).is_err());
+        // Just below the limit, newline included.
+        let path = "a".repeat(MAX_LINE - "GET / HTTP/1.1\r\n".len());
+        assert!(request(&format!("GET /{} HTTP/1.1\r\n\r\n", path)).is_ok());
+    }
+
+    #[test]
+    fn header_limits() {
+        let headers = |n: usize| "X: 1\r\n".repeat(n);
+        assert!(request(&format!(
+            "GET / HTTP/1.1\r\n{}\r\n",
+            headers(MAX_HEADERS - 1)
+        ))
+        .is_ok());
+        assert!(request(&format!("GET / HTTP/1.1\r\n{}\r\n", headers(MAX_HEADERS))).is_err());
+    }
+
+    #[test]
+    fn percent_escapes() {
+        assert_eq!(percent_decode("a%2Fb+c%7e"), "a/b c~");
+        assert_eq!(percent_decode("%e2%82%ac"), "\u{20ac}");
+        // Bad escapes are kept.
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%4"), "%4");
+        assert_eq!(percent_decode("%zz%"), "%zz%");
+        // Signs are no hex digits.
+        assert_eq!(percent_decode("%+1%-1"), "% 1%-1");
+        assert_eq!(percent_decode("%%41"), "%A");
+        assert_eq!(percent_decode("%\u{e9}1"), "%\u{e9}1");
+        // Invalid utf-8 is replaced.
+        assert_eq!(percent_decode("%ff"), "\u{fffd}");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    }
+}
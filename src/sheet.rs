@@ -0,0 +1,60 @@
+use std::io::{BufRead, BufReader};
+
+use crate::error::{open_file, Error, Result};
+
+/// Inputs of a batch run, sample names and bam paths in sheet order.
+pub struct SampleSheet {
+    pub samples: Vec<(String, String)>,
+}
+
+impl SampleSheet {
+    /// Read a table with `sample_name` and `bam_path` columns, comma separated for `.csv`, tab
+    /// separated otherwise. Other columns and `#` lines are skipped, sample names must be unique.
+    pub fn from_path(v: &str) -> Result<Self> {
+        let sep = if v.ends_with(".csv") { ',' } else { '\t' };
+        let mut lines = BufReader::new(open_file(v)?)
+            .lines()
+            .filter(|v| !v.as_ref().is_ok_and(|v| v.is_empty() || v.starts_with('#')));
+        let header = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| Error::Malformed(format!("Empty sample sheet: {}", v)))?;
+        let column = |name: &str| {
+            header
+                .split(sep)
+                .position(|v| v.trim() == name)
+                .ok_or_else(|| {
+                    Error::Malformed(format!("No {} column in sample sheet: {}", name, v))
+                })
+        };
+        let (name_col, path_col) = (column("sample_name")?, column("bam_path")?);
+        let mut samples: Vec<(String, String)> = Vec::new();
+        for line in lines {
+            let line = line?;
+            let fields: Vec<&str> = line.split(sep).map(str::trim).collect();
+            let (name, path) = match (fields.get(name_col), fields.get(path_col)) {
+                (Some(name), Some(path)) if !name.is_empty() && !path.is_empty() => (name, path),
+                _ => {
+                    return Err(Error::Malformed(format!(
+                        "Malformed sample sheet line: {}",
+                        line
+                    )))
+                }
+            };
+            if samples.iter().any(|(v, _)| v == name) {
+                return Err(Error::Malformed(format!(
+                    "Duplicate sample in sample sheet: {}",
+                    name
+                )));
+            }
+            samples.push((name.to_string(), path.to_string()));
+        }
+        if samples.is_empty() {
+            return Err(Error::Malformed(format!(
+                "No samples in sample sheet: {}",
+                v
+            )));
+        }
+        Ok(Self { samples })
+    }
+}
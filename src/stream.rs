@@ -0,0 +1,68 @@
+use std::io::{self, Write};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::error::Result;
+use crate::summary::Summary;
+
+/// Records between partial summaries by default.
+pub const DEFAULT_STREAM_EVERY: u64 = 1_000_000;
+
+/// Summaries of one input as newline delimited json on stdout, a partial one every few
+/// records and the final one at the end, for monitoring long runs.
+pub struct NdjsonStream {
+    every: u64,
+    name: String,
+}
+
+/// One line, input path, records read so far and summary.
+struct Line<'a> {
+    input: &'a str,
+    records: u64,
+    done: bool,
+    summary: &'a Summary,
+}
+
+impl Serialize for Line<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Line", 4)?;
+        state.serialize_field("input", self.input)?;
+        state.serialize_field("records", &self.records)?;
+        state.serialize_field("done", &self.done)?;
+        state.serialize_field("summary", self.summary)?;
+        state.end()
+    }
+}
+
+impl NdjsonStream {
+    /// Stream of input at `path`, a partial summary every `every` records.
+    pub fn new(path: &str, every: u64) -> Self {
+        Self {
+            every: every.max(1),
+            name: path.to_string(),
+        }
+    }
+
+    /// Whether a partial summary is due after `records` records.
+    pub fn due(&self, records: u64) -> bool {
+        records.is_multiple_of(self.every)
+    }
+
+    /// Print summary after `records` records, `done` at end of input.
+    pub fn emit(&self, records: u64, done: bool, summary: &Summary) -> Result<()> {
+        let line = serde_json::to_string(&Line {
+            input: &self.name,
+            records,
+            done,
+            summary,
+        })?;
+        // One write per line, lines of inputs read in parallel do not mix.
+        let mut stdout = io::stdout().lock();
+        writeln!(stdout, "{}", line)?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,539 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use crate::compare::KsTest;
+use crate::converge::EarlyStop;
+use crate::gmm::Mixture;
+use crate::record::Record;
+
+/// Version of the summary json layout, bumped when keys change meaning or go away.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Insert size statistics of one input.
+#[derive(Default)]
+pub struct Summary {
+    /// Pair count all.
+    pub all_count: u32,
+    /// Insert size mean in all.
+    pub all_mean: f64,
+    /// Insert size standard deviation in all.
+    pub all_std: f64,
+    /// Pair count.
+    pub count: u32,
+    /// Insert size mean.
+    pub mean: f64,
+    /// Smallest insert size in all.
+    pub min: usize,
+    /// Largest insert size in all.
+    pub max: usize,
+    /// Insert size median.
+    pub median: f64,
+    /// Insert size with highest count.
+    pub mode: usize,
+    /// Median absolute deviation of insert size.
+    pub mad: f64,
+    /// Interquartile range, Q3 - Q1.
+    pub iqr: usize,
+    /// Insert size mean with both ends trimmed.
+    pub trimmed_mean: f64,
+    /// Width of the window around median holding each percent of pairs, keyed by percent.
+    pub widths: Vec<(u32, usize)>,
+    /// Insert size at each requested percentile, keyed by percent.
+    pub percentiles: Vec<(f64, usize)>,
+    /// Insert size standard deviation.
+    pub std: f64,
+    /// Insert size skewness, positive for a long right tail.
+    pub skewness: f64,
+    /// Insert size excess kurtosis, 0 for a normal distribution.
+    pub kurtosis: f64,
+    /// First quantile.
+    pub q1: usize,
+    /// Second quantile.
+    pub q2: usize,
+    /// Third quantile.
+    pub q3: usize,
+    /// Summary of each reference, when counted per chromosome.
+    pub chromosomes: Vec<(String, Summary)>,
+    /// Summary of each read group, when counted per read group.
+    pub read_groups: Vec<(String, Summary)>,
+    /// Summary of each GC stratum of reads, when counted per GC.
+    pub gc_strata: Vec<(String, Summary)>,
+    /// Summary of each MAPQ bucket of reads, when counted by MAPQ.
+    pub mapq_buckets: Vec<(String, Summary)>,
+    /// Summary of each pair orientation, FR, RF and TANDEM.
+    pub orientations: Vec<(String, Summary)>,
+    /// Sample name from `@RG SM` of the header, overall summary only.
+    pub sample_name: Option<String>,
+    /// Reference genome from `@SQ` or `@PG` of the header, overall summary only.
+    pub reference_genome: Option<String>,
+    /// Pairs left out of the distribution, overall summary only.
+    pub excluded: Excluded,
+    /// Malformed records skipped in lenient mode, overall summary only.
+    pub records_skipped: u64,
+    /// Where reading stopped on convergence, overall summary only.
+    pub early_stop: Option<EarlyStop>,
+    /// Records read by kind of alignment, overall summary only.
+    pub alignments: Alignments,
+    /// Reads of counted mates failing include flags, overall summary only.
+    pub unqualified: Unqualified,
+    /// Fraction of pairs in FR orientation, short paired-end contamination of mate-pair
+    /// libraries, overall summary of mate-pair libraries only.
+    pub fr_contamination: Option<f64>,
+    /// Fraction of pairs of known read length with insert size below it, overlapping mates
+    /// reading through into adapter, overall summary only.
+    pub short_inserts: Option<f64>,
+    /// Pairs near each expected size, overall summary only.
+    pub expected: Vec<ExpectedSize>,
+    /// Pairs above the upper bound, overall summary with tail report only.
+    pub tail: Option<TailSummary>,
+    /// Gaussian mixture fitted to qualified pairs, overall summary with `fit_gmm` only.
+    pub mixture: Option<Mixture>,
+    /// Nucleosome peaks and short fragments of cfDNA, overall summary with `cfdna` only.
+    pub cfdna: Option<CfdnaSummary>,
+    /// Nucleosome-free and nucleosomal fragments of ATAC-seq, overall summary with `atac` only.
+    pub atac: Option<AtacSummary>,
+    /// Estimated fragment sizes and inner distances, overall summary in fragment mode only.
+    pub fragment: Option<FragmentSummary>,
+    /// Test against a reference distribution, overall summary only.
+    pub reference: Option<KsTest>,
+    /// Insert sizes of modes, overall summary only.
+    pub peaks: Vec<usize>,
+    /// Secondary peak below `SHORT_PEAK_LIMIT`, likely adapter dimers or short fragments.
+    pub short_peak: bool,
+}
+
+/// Estimated physical fragment sizes and inner distances between mates.
+#[derive(Default)]
+pub struct FragmentSummary {
+    /// Adapter bases at each end of a fragment.
+    pub adapter_length: usize,
+    /// Count, mean, median and sd of fragment sizes, insert size plus adapters.
+    pub count: u32,
+    pub mean: f64,
+    pub median: usize,
+    pub sd: f64,
+    /// Mean and sd of inner distance, insert size less both reads, `None` without read length.
+    pub inner_mean: Option<f64>,
+    pub inner_sd: Option<f64>,
+}
+
+impl Serialize for FragmentSummary {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("FragmentSummary", 7)?;
+        state.serialize_field("adapter_length", &self.adapter_length)?;
+        state.serialize_field("count", &self.count)?;
+        state.serialize_field("mean", &round2(self.mean))?;
+        state.serialize_field("median", &self.median)?;
+        state.serialize_field("sd", &round2(self.sd))?;
+        state.serialize_field("mean_inner_distance", &self.inner_mean.map(round2))?;
+        state.serialize_field("inner_distance_sd", &self.inner_sd.map(round2))?;
+        state.end()
+    }
+}
+
+/// Pairs near an expected insert size, e.g. of an amplicon.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ExpectedSize {
+    pub size: usize,
+    /// Pairs within tolerance of the size and their fraction of pairs.
+    pub count: u32,
+    pub fraction: f64,
+    /// Far fewer pairs than an even split among expected sizes, e.g. a failed amplicon.
+    pub missing: bool,
+}
+
+/// Expected sizes keyed by size.
+struct Expected<'a>(&'a [ExpectedSize]);
+
+impl Serialize for Expected<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|v| (v.size.to_string(), v)))
+    }
+}
+
+impl Serialize for ExpectedSize {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("ExpectedSize", 3)?;
+        state.serialize_field("count", &self.count)?;
+        state.serialize_field("fraction", &round4(self.fraction))?;
+        state.serialize_field("missing", &self.missing)?;
+        state.end()
+    }
+}
+
+/// Insert sizes above the upper bound.
+#[derive(Default)]
+pub struct TailSummary {
+    /// Upper bound of the distribution.
+    pub upper: usize,
+    /// Count, mean, 99th percentile and largest insert size of pairs above upper.
+    pub count: u64,
+    pub mean: f64,
+    pub p99: usize,
+    pub max: usize,
+}
+
+impl Serialize for TailSummary {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("TailSummary", 5)?;
+        state.serialize_field("upper", &self.upper)?;
+        state.serialize_field("count", &self.count)?;
+        state.serialize_field("mean", &round2(self.mean))?;
+        state.serialize_field("p99", &self.p99)?;
+        state.serialize_field("max", &self.max)?;
+        state.end()
+    }
+}
+
+/// Nucleosome periodicity of cell-free DNA, standard liquid biopsy QC.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CfdnaSummary {
+    /// Insert sizes of mono-, di- and tri-nucleosome peaks, `None` without pairs in the window.
+    pub mono_peak: Option<usize>,
+    pub di_peak: Option<usize>,
+    pub tri_peak: Option<usize>,
+    /// Heights of di- and tri-nucleosome peaks relative to the mono-nucleosome one.
+    pub di_mono_ratio: Option<f64>,
+    pub tri_mono_ratio: Option<f64>,
+    /// Fraction of pairs below `CFDNA_SHORT_LIMIT`.
+    pub short_fraction: f64,
+}
+
+impl Serialize for CfdnaSummary {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("CfdnaSummary", 6)?;
+        state.serialize_field("mono_nucleosome_peak", &self.mono_peak)?;
+        state.serialize_field("di_nucleosome_peak", &self.di_peak)?;
+        state.serialize_field("tri_nucleosome_peak", &self.tri_peak)?;
+        state.serialize_field("di_mono_ratio", &self.di_mono_ratio.map(round4))?;
+        state.serialize_field("tri_mono_ratio", &self.tri_mono_ratio.map(round4))?;
+        state.serialize_field("short_fraction", &round4(self.short_fraction))?;
+        state.end()
+    }
+}
+
+/// Fragment size QC of ATAC-seq, nucleosome-free fragments of open chromatin against
+/// nucleosomal ones.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AtacSummary {
+    /// Fractions of pairs below `NUCLEOSOME_FREE_LIMIT` and in `ATAC_NUCLEOSOME_WINDOWS`.
+    pub nucleosome_free: f64,
+    pub mono: f64,
+    pub di: f64,
+    /// Nucleosome-free to mono-nucleosome and mono- to di-nucleosome pair ratios.
+    pub free_mono_ratio: Option<f64>,
+    pub mono_di_ratio: Option<f64>,
+    /// Share of the spectrum at the ~10.5 bp helical period, `None` when upper cuts it short.
+    pub periodicity: Option<f64>,
+}
+
+impl Serialize for AtacSummary {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("AtacSummary", 6)?;
+        state.serialize_field("nucleosome_free_fraction", &round4(self.nucleosome_free))?;
+        state.serialize_field("mono_nucleosome_fraction", &round4(self.mono))?;
+        state.serialize_field("di_nucleosome_fraction", &round4(self.di))?;
+        state.serialize_field("free_mono_ratio", &self.free_mono_ratio.map(round4))?;
+        state.serialize_field("mono_di_ratio", &self.mono_di_ratio.map(round4))?;
+        state.serialize_field("periodicity_score", &self.periodicity.map(round4))?;
+        state.end()
+    }
+}
+
+/// Counts of pairs passing read filters but left out of the distribution, by reason.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Excluded {
+    /// Mates on different references.
+    pub discordant_interchrom: u64,
+    /// Mates on one reference with TLEN 0.
+    pub tlen_zero: u64,
+    /// Duplicates not marked in input, collapsed on the fly.
+    pub duplicates: u64,
+    /// Pairs of other orientations than the library's, e.g. FR pairs of mate-pair libraries.
+    pub wrong_orientation: u64,
+    /// Insert size above the upper bound, only in total count and mean.
+    pub above_upper: u64,
+}
+
+impl Serialize for Excluded {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Excluded", 5)?;
+        state.serialize_field("discordant_interchrom", &self.discordant_interchrom)?;
+        state.serialize_field("tlen_zero", &self.tlen_zero)?;
+        state.serialize_field("duplicates", &self.duplicates)?;
+        state.serialize_field("wrong_orientation", &self.wrong_orientation)?;
+        state.serialize_field("above_upper", &self.above_upper)?;
+        state.end()
+    }
+}
+
+/// Counts of reads of counted mates failing include flags, by reason, e.g. the proper pair
+/// flag of the default, explaining qualified read counts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Unqualified {
+    /// Read unmapped.
+    pub unmapped: u64,
+    /// Read mapped, mate unmapped.
+    pub mate_unmapped: u64,
+    /// Mates mapped to different references.
+    pub interchromosomal: u64,
+    /// Mates mapped to one reference, other flags failed, mostly proper pair.
+    pub not_proper_pair: u64,
+}
+
+impl Unqualified {
+    /// Count `record` by the first reason it fails.
+    pub fn add(&mut self, record: &Record) {
+        let flag = *record.flag();
+        if flag & crate::UNMAPPED_FLAG != 0 {
+            self.unmapped += 1;
+        } else if flag & crate::MATE_UNMAPPED_FLAG != 0 {
+            self.mate_unmapped += 1;
+        } else if record.ref_id() != record.mate_ref_id() {
+            self.interchromosomal += 1;
+        } else {
+            self.not_proper_pair += 1;
+        }
+    }
+}
+
+impl Serialize for Unqualified {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Unqualified", 4)?;
+        state.serialize_field("unmapped", &self.unmapped)?;
+        state.serialize_field("mate_unmapped", &self.mate_unmapped)?;
+        state.serialize_field("interchromosomal", &self.interchromosomal)?;
+        state.serialize_field("not_proper_pair", &self.not_proper_pair)?;
+        state.end()
+    }
+}
+
+/// Records read by kind of alignment, secondary and supplementary ones are skipped by default
+/// but counted here, e.g. for the burden of chimeric reads.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Alignments {
+    /// Mapped reads neither secondary nor supplementary.
+    pub primary: u64,
+    pub secondary: u64,
+    pub supplementary: u64,
+}
+
+impl Alignments {
+    /// Count a record of `flag`.
+    pub fn add(&mut self, flag: u16) {
+        if flag & crate::SECONDARY_FLAG != 0 {
+            self.secondary += 1;
+        } else if flag & crate::SUPPLEMENTARY_FLAG != 0 {
+            self.supplementary += 1;
+        } else if flag & crate::UNMAPPED_FLAG == 0 {
+            self.primary += 1;
+        }
+    }
+
+    /// Ratio of `count` to primary alignments, 0 without them.
+    fn per_primary(&self, count: u64) -> f64 {
+        if self.primary > 0 {
+            round4(count as f64 / self.primary as f64)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Serialize for Alignments {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Alignments", 5)?;
+        state.serialize_field("primary_count", &self.primary)?;
+        state.serialize_field("secondary_count", &self.secondary)?;
+        state.serialize_field("supplementary_count", &self.supplementary)?;
+        state.serialize_field("secondary_per_primary", &self.per_primary(self.secondary))?;
+        state.serialize_field(
+            "supplementary_per_primary",
+            &self.per_primary(self.supplementary),
+        )?;
+        state.end()
+    }
+}
+
+/// Round to 2 decimals for output.
+pub(crate) fn round2(v: f64) -> f64 {
+    format!("{:.2}", v).parse::<f64>().unwrap()
+}
+
+/// Round to 4 decimals for output, of fractions and statistics.
+pub(crate) fn round4(v: f64) -> f64 {
+    format!("{:.4}", v).parse::<f64>().unwrap()
+}
+
+impl Serialize for Summary {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Summary", 45)?;
+        state.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        state.serialize_field("tool_version", env!("CARGO_PKG_VERSION"))?;
+        state.serialize_field("sample_name", &self.sample_name)?;
+        state.serialize_field("reference_genome", &self.reference_genome)?;
+        state.serialize_field("Total count", &self.all_count)?;
+        state.serialize_field("Total mean insert size", &round2(self.all_mean))?;
+        state.serialize_field("Total insert size SD", &round2(self.all_std))?;
+        state.serialize_field("Total min insert size", &self.min)?;
+        state.serialize_field("Total max insert size", &self.max)?;
+        state.serialize_field("Qualified read count", &self.count)?;
+        state.serialize_field("Qualified mean insize size", &round2(self.mean))?;
+        state.serialize_field("Qualified insert size SD", &round2(self.std))?;
+        state.serialize_field("Qualified median insert size", &self.median)?;
+        state.serialize_field("Qualified mode insert size", &self.mode)?;
+        state.serialize_field(
+            "Qualified trimmed mean insert size",
+            &round2(self.trimmed_mean),
+        )?;
+        state.serialize_field("Qualified insert size MAD", &self.mad)?;
+        state.serialize_field("Qualified insert size IQR", &self.iqr)?;
+        state.serialize_field("Qualified insert size skewness", &round2(self.skewness))?;
+        state.serialize_field(
+            "Qualified insert size excess kurtosis",
+            &round2(self.kurtosis),
+        )?;
+        state.serialize_field("Qualified width of percent", &Widths(&self.widths))?;
+        if !self.percentiles.is_empty() {
+            state.serialize_field("Qualified percentiles", &Percentiles(&self.percentiles))?;
+        }
+        state.serialize_field("Qualified Q1", &self.q1)?;
+        state.serialize_field("Qualified Q2", &self.q2)?;
+        state.serialize_field("Qualified Q3", &self.q3)?;
+        state.serialize_field("Excluded pairs", &self.excluded)?;
+        state.serialize_field("Records skipped", &self.records_skipped)?;
+        if let Some(v) = &self.early_stop {
+            state.serialize_field("Early stop", v)?;
+        }
+        state.serialize_field("Alignments", &self.alignments)?;
+        state.serialize_field("Unqualified reads", &self.unqualified)?;
+        if let Some(v) = self.fr_contamination {
+            state.serialize_field("FR contamination", &round4(v))?;
+        }
+        if let Some(v) = self.short_inserts {
+            state.serialize_field("Short insert fraction", &round4(v))?;
+        }
+        if !self.expected.is_empty() {
+            state.serialize_field("Expected sizes", &Expected(&self.expected))?;
+        }
+        if let Some(v) = &self.tail {
+            state.serialize_field("Tail above upper", v)?;
+        }
+        if let Some(v) = &self.mixture {
+            state.serialize_field("Mixture", v)?;
+        }
+        if let Some(v) = &self.cfdna {
+            state.serialize_field("cfDNA", v)?;
+        }
+        if let Some(v) = &self.atac {
+            state.serialize_field("ATAC", v)?;
+        }
+        if let Some(v) = &self.fragment {
+            state.serialize_field("Fragment size", v)?;
+        }
+        state.serialize_field("Mode count", &self.peaks.len())?;
+        state.serialize_field("Peak insert sizes", &self.peaks)?;
+        state.serialize_field("Short fragment peak", &self.short_peak)?;
+        if let Some(v) = &self.reference {
+            state.serialize_field("Reference test", v)?;
+        }
+        if !self.chromosomes.is_empty() {
+            state.serialize_field("Per chromosome", &Briefs(&self.chromosomes))?;
+        }
+        if !self.read_groups.is_empty() {
+            state.serialize_field("Per read group", &Briefs(&self.read_groups))?;
+        }
+        if !self.gc_strata.is_empty() {
+            state.serialize_field("Per GC stratum", &Briefs(&self.gc_strata))?;
+        }
+        if !self.mapq_buckets.is_empty() {
+            state.serialize_field("Per MAPQ", &Briefs(&self.mapq_buckets))?;
+        }
+        if !self.orientations.is_empty() {
+            state.serialize_field("Per orientation", &Briefs(&self.orientations))?;
+        }
+        state.end()
+    }
+}
+
+/// Widths keyed by percent.
+struct Widths<'a>(&'a [(u32, usize)]);
+
+impl Serialize for Widths<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+}
+
+/// Insert sizes keyed by percent.
+struct Percentiles<'a>(&'a [(f64, usize)]);
+
+impl Serialize for Percentiles<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+}
+
+/// Brief summaries keyed by name.
+struct Briefs<'a>(&'a [(String, Summary)]);
+
+impl Serialize for Briefs<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, Brief(v))))
+    }
+}
+
+/// Count, mean, median and sd only.
+struct Brief<'a>(&'a Summary);
+
+impl Serialize for Brief<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Brief", 4)?;
+        state.serialize_field("count", &self.0.count)?;
+        state.serialize_field("mean", &round2(self.0.mean))?;
+        state.serialize_field("median", &self.0.q2)?;
+        state.serialize_field("sd", &round2(self.0.std))?;
+        state.end()
+    }
+}
@@ -0,0 +1,280 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json::Value;
+
+use crate::error::{open_file, Error, Result};
+use crate::hist::{Histograms, InsertSizeHistogram};
+use crate::summary::{round2, Summary, SCHEMA_VERSION};
+
+/// Pair count of each `bin_size` bp bin within upper, keyed by its smallest insert size.
+fn binned(hist: &InsertSizeHistogram, bin_size: usize) -> Vec<(usize, u64)> {
+    // Rows are sorted, so a bin is a run of rows.
+    let mut bins: Vec<(usize, u64)> = Vec::new();
+    for (k, v) in hist.rows() {
+        let start = k / bin_size * bin_size;
+        match bins.last_mut() {
+            Some(bin) if bin.0 == start => bin.1 += v as u64,
+            _ => bins.push((start, v as u64)),
+        }
+    }
+    bins
+}
+
+/// Write count and fraction of each `bin_size` bp bin, keyed by its smallest insert size, with a
+/// leading sample column for several samples, fields separated by `sep`.
+pub fn write_histograms<W: Write>(
+    mut out: W,
+    samples: &[(String, Histograms)],
+    sep: char,
+    bin_size: usize,
+) -> io::Result<()> {
+    let single = samples.len() == 1;
+    if !single {
+        write!(out, "sample{}", sep)?;
+    }
+    writeln!(out, "insert_size{}count{}fraction", sep, sep)?;
+    for (name, hists) in samples {
+        let total = hists.all.count() as f64;
+        for (k, v) in binned(&hists.all, bin_size) {
+            if !single {
+                write!(out, "{}{}", name, sep)?;
+            }
+            let fraction = if total > 0.0 { v as f64 / total } else { 0.0 };
+            writeln!(out, "{}{}{}{}{}", k, sep, v, sep, fraction)?;
+        }
+    }
+    Ok(())
+}
+
+/// Histograms of samples as json.
+struct SavedHistograms<'a> {
+    samples: &'a [(String, Histograms)],
+    bin_size: usize,
+}
+
+/// Histogram of one sample, see `write_histograms_json`.
+struct SavedHistogram<'a> {
+    name: &'a str,
+    hist: &'a InsertSizeHistogram,
+    bin_size: usize,
+}
+
+impl Serialize for SavedHistograms<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let samples: Vec<SavedHistogram> = self
+            .samples
+            .iter()
+            .map(|(name, hists)| SavedHistogram {
+                name,
+                hist: &hists.all,
+                bin_size: self.bin_size,
+            })
+            .collect();
+        let mut state = serializer.serialize_struct("SavedHistograms", 2)?;
+        state.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        state.serialize_field("samples", &samples)?;
+        state.end()
+    }
+}
+
+impl Serialize for SavedHistogram<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let sum = self.hist.summary();
+        let bins: Vec<(usize, u64)> = binned(self.hist, self.bin_size)
+            .into_iter()
+            .filter(|v| v.1 > 0)
+            .collect();
+        let sizes: Vec<usize> = bins.iter().map(|v| v.0).collect();
+        let counts: Vec<u64> = bins.iter().map(|v| v.1).collect();
+        let mut state = serializer.serialize_struct("SavedHistogram", 10)?;
+        state.serialize_field("sample", self.name)?;
+        state.serialize_field("upper", &self.hist.upper())?;
+        state.serialize_field("bin_size", &self.bin_size)?;
+        state.serialize_field("total_count", &sum.all_count)?;
+        state.serialize_field("total_mean", &sum.all_mean)?;
+        state.serialize_field("total_sd", &sum.all_std)?;
+        state.serialize_field("total_min", &sum.min)?;
+        state.serialize_field("total_max", &sum.max)?;
+        state.serialize_field("insert_size", &sizes)?;
+        state.serialize_field("count", &counts)?;
+        state.end()
+    }
+}
+
+/// Write histograms as json, samples in order, each with upper, non-empty `bin_size` bp bins as
+/// `insert_size` and `count` arrays, and count, mean, sd, smallest and largest insert size of
+/// all pairs, above upper included, so histograms can be drawn and merged again.
+pub fn write_histograms_json<W: Write>(
+    out: W,
+    samples: &[(String, Histograms)],
+    bin_size: usize,
+) -> Result<()> {
+    serde_json::to_writer_pretty(out, &SavedHistograms { samples, bin_size })?;
+    Ok(())
+}
+
+/// Read histograms written by `write_histograms_json` to a `.json` file, or by
+/// `write_histograms` to a table, comma separated for `.csv`, tab separated otherwise, in
+/// sample order. Without a sample column the one sample of a table is named ``.
+///
+/// Upper of each table histogram is its largest insert size. Counts of binned histograms stay
+/// at the smallest insert size of each bin.
+pub fn read_histograms(path: &str) -> Result<Vec<(String, InsertSizeHistogram)>> {
+    if path.ends_with(".json") {
+        return read_histograms_json(path);
+    }
+    let sep = if path.ends_with(".csv") { ',' } else { '\t' };
+    let mut lines = BufReader::new(open_file(path)?).lines();
+    let header = lines
+        .next()
+        .transpose()?
+        .ok_or_else(|| Error::Malformed(format!("Empty histogram table: {}", path)))?;
+    let column = |name: &str| header.split(sep).position(|v| v.trim() == name);
+    let (key, count) = match (column("insert_size"), column("count")) {
+        (Some(key), Some(count)) => (key, count),
+        _ => {
+            return Err(Error::Malformed(format!(
+                "No insert_size or count column in histogram table: {}",
+                path
+            )))
+        }
+    };
+    let sample = column("sample");
+    let mut samples: Vec<(String, Vec<(usize, u32)>)> = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let err = || Error::Malformed(format!("Malformed histogram line: {}", line));
+        let fields: Vec<&str> = line.split(sep).map(str::trim).collect();
+        let name = match sample {
+            Some(i) => *fields.get(i).ok_or_else(err)?,
+            None => "",
+        };
+        let k: usize = fields
+            .get(key)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(err)?;
+        let v: u32 = fields
+            .get(count)
+            .and_then(|v| v.parse().ok())
+            .ok_or_else(err)?;
+        // Rows of a sample are together.
+        match samples.last_mut() {
+            Some((last, bins)) if last == name => bins.push((k, v)),
+            _ => samples.push((name.to_string(), vec![(k, v)])),
+        }
+    }
+    if samples.is_empty() {
+        return Err(Error::Malformed(format!(
+            "No rows in histogram table: {}",
+            path
+        )));
+    }
+    Ok(samples
+        .into_iter()
+        .map(|(name, bins)| {
+            let upper = bins.iter().map(|v| v.0).max().unwrap_or(0);
+            let mut hist = InsertSizeHistogram::new(upper);
+            for (k, v) in bins {
+                hist.add_count(k, v);
+            }
+            (name, hist)
+        })
+        .collect())
+}
+
+/// Read histograms written by `write_histograms_json`.
+fn read_histograms_json(path: &str) -> Result<Vec<(String, InsertSizeHistogram)>> {
+    let mut text = String::new();
+    open_file(path)?.read_to_string(&mut text)?;
+    let json: Value = serde_json::from_str(&text)?;
+    let err = || Error::Malformed(format!("Malformed histogram json: {}", path));
+    let samples = json["samples"].as_array().ok_or_else(err)?;
+    if samples.is_empty() {
+        return Err(Error::Malformed(format!(
+            "No samples in histogram json: {}",
+            path
+        )));
+    }
+    samples
+        .iter()
+        .map(|sample| {
+            let number = |key: &str| sample[key].as_u64().ok_or_else(err);
+            let numbers = |key: &str| -> Result<Vec<u64>> {
+                sample[key]
+                    .as_array()
+                    .ok_or_else(err)?
+                    .iter()
+                    .map(|v| v.as_u64().ok_or_else(err))
+                    .collect()
+            };
+            let name = sample["sample"].as_str().ok_or_else(err)?.to_string();
+            let (sizes, counts) = (numbers("insert_size")?, numbers("count")?);
+            if sizes.len() != counts.len() {
+                return Err(err());
+            }
+            let mut hist = InsertSizeHistogram::new(number("upper")? as usize);
+            for (k, v) in sizes.into_iter().zip(counts) {
+                hist.add_count(k as usize, v as u32);
+            }
+            hist.set_totals(
+                number("total_count")? as u32,
+                sample["total_mean"].as_f64().ok_or_else(err)?,
+                sample["total_sd"].as_f64().ok_or_else(err)?,
+                number("total_min")? as usize,
+                number("total_max")? as usize,
+            );
+            Ok((name, hist))
+        })
+        .collect()
+}
+
+/// Write one row of main statistics per sample, fields separated by `sep`.
+pub fn write_summaries<W: Write>(
+    mut out: W,
+    samples: &[(String, Summary)],
+    sep: char,
+) -> io::Result<()> {
+    let header = [
+        "sample",
+        "total_count",
+        "count",
+        "mean",
+        "sd",
+        "median",
+        "mode",
+        "mad",
+        "iqr",
+        "q1",
+        "q3",
+        "trimmed_mean",
+    ];
+    writeln!(out, "{}", header.join(&sep.to_string()))?;
+    for (name, sum) in samples {
+        let fields = [
+            name.clone(),
+            sum.all_count.to_string(),
+            sum.count.to_string(),
+            round2(sum.mean).to_string(),
+            round2(sum.std).to_string(),
+            sum.median.to_string(),
+            sum.mode.to_string(),
+            sum.mad.to_string(),
+            sum.iqr.to_string(),
+            sum.q1.to_string(),
+            sum.q3.to_string(),
+            round2(sum.trimmed_mean).to_string(),
+        ];
+        writeln!(out, "{}", fields.join(&sep.to_string()))?;
+    }
+    Ok(())
+}
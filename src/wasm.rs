@@ -0,0 +1,35 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use wasm_bindgen::prelude::*;
+
+use crate::hist::Histograms;
+use crate::reader::open_bytes;
+use crate::{compute_histograms_from, Options};
+
+/// Histogram and summary json of bam or sam `bytes`, e.g. a slice dropped on a QC page,
+/// counting insert sizes up to `upper`, 0 for the default.
+#[wasm_bindgen]
+pub fn analyze(bytes: &[u8], upper: usize) -> Result<String, JsValue> {
+    let mut opts = Options::default();
+    if upper > 0 {
+        opts.upper = upper;
+    }
+    open_bytes(bytes.to_vec())
+        .and_then(|reader| compute_histograms_from("input", reader, &opts))
+        .and_then(|v| Ok(serde_json::to_string(&Analysis(&v))?))
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Counts by insert size from 0 and summary of histograms.
+struct Analysis<'a>(&'a Histograms);
+
+impl Serialize for Analysis<'_> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Analysis", 2)?;
+        state.serialize_field("histogram", self.0.all.counts())?;
+        state.serialize_field("summary", &self.0.summary())?;
+        state.end()
+    }
+}
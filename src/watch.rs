@@ -0,0 +1,111 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::bgzf::EOF_BLOCK;
+use crate::error::{Error, Result};
+use crate::region::glob_match;
+
+/// Seconds between polls of a watched directory by default.
+pub const DEFAULT_WATCH_INTERVAL: u64 = 30;
+
+/// Polls a directory for finished files of a name pattern, e.g. bams written by an instrument
+/// attached aligner, each is reported once.
+///
+/// A file is finished once its size and modification time stay the same for a poll or it has
+/// not been modified for an interval, and a `.bam` also ends with the bgzf EOF block.
+pub struct Watcher {
+    dir: PathBuf,
+    pattern: String,
+    interval: Duration,
+    // Size and modification time of unfinished files at the last poll.
+    pending: HashMap<PathBuf, (u64, SystemTime)>,
+    reported: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    /// Watch `dir` for file names matching glob `pattern`, polled every `interval`.
+    pub fn new(dir: &str, pattern: &str, interval: Duration) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+            pattern: pattern.to_string(),
+            interval,
+            pending: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Files finished since the last poll, sorted by path.
+    pub fn poll(&mut self) -> Result<Vec<PathBuf>> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| Error::from(e).in_file(&self.dir.display().to_string()))?;
+        let mut finished = Vec::new();
+        let mut pending = HashMap::new();
+        for entry in entries {
+            let entry = match entry {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{}: {}", self.dir.display(), e);
+                    continue;
+                }
+            };
+            let path = entry.path();
+            let matched = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|v| glob_match(&self.pattern, v));
+            if !matched || self.reported.contains(&path) {
+                continue;
+            }
+            // Files may go away between listing and stat or open, they are seen again if back.
+            let meta = match entry.metadata() {
+                Ok(v) if v.is_file() => v,
+                _ => continue,
+            };
+            let modified = match meta.modified() {
+                Ok(v) => v,
+                Err(e) => {
+                    warn!("{}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            let state = (meta.len(), modified);
+            let settled = self.pending.get(&path) == Some(&state)
+                || modified.elapsed().is_ok_and(|v| v >= self.interval);
+            let complete = settled
+                && match is_complete(&path) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("{}: {}", path.display(), e);
+                        continue;
+                    }
+                };
+            if complete {
+                finished.push(path);
+            } else {
+                pending.insert(path, state);
+            }
+        }
+        self.pending = pending;
+        finished.sort();
+        self.reported.extend(finished.iter().cloned());
+        Ok(finished)
+    }
+}
+
+/// Whether file at `path` is complete, a `.bam` ends with the bgzf EOF block.
+fn is_complete(path: &Path) -> Result<bool> {
+    if path.extension().is_none_or(|v| v != "bam") {
+        return Ok(true);
+    }
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() < EOF_BLOCK.len() as u64 {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::End(-(EOF_BLOCK.len() as i64)))?;
+    let mut tail = [0u8; EOF_BLOCK.len()];
+    file.read_exact(&mut tail)?;
+    Ok(tail == EOF_BLOCK)
+}